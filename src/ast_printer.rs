@@ -1,5 +1,7 @@
 use crate::expr::{Expr, Visitor};
+use crate::stmt::{Stmt, StmtVisitor};
 use crate::token::Literal;
+use crate::token::Token;
 
 pub struct ExprVisitor;
 
@@ -8,6 +10,30 @@ impl ExprVisitor {
         expr.accept(self)
     }
 
+    pub fn print_stmt(&self, stmt: &Stmt) -> String {
+        stmt.accept(self)
+    }
+
+    ///与[`ExprVisitor::parenthesize`]相同，但各项已经打印成了字符串
+    fn list(&self, name: &str, parts: Vec<String>) -> String {
+        let mut result = String::from("(");
+        result.push_str(name);
+        for part in parts {
+            result.push(' ');
+            result.push_str(&part);
+        }
+        result.push(')');
+        result
+    }
+
+    ///打印函数的参数列表和函数体
+    fn function(&self, name: &str, params: &[Token], body: &[Stmt]) -> String {
+        let params: Vec<_> = params.iter().map(|p| p.lexeme.as_str()).collect();
+        let mut parts = vec![format!("({})", params.join(" "))];
+        parts.extend(body.iter().map(|stmt| self.print_stmt(stmt)));
+        self.list(name, parts)
+    }
+
     fn parenthesize(&self, name: &str, exprs: Vec<&Expr>) -> String {
         let mut result = String::from("(");
         result.push_str(name);
@@ -39,19 +65,303 @@ impl Visitor<String> for ExprVisitor {
     fn visit_unary(&self, operator: &crate::token::Token, right: &Expr) -> String {
         self.parenthesize(operator.lexeme.as_str(), vec![right])
     }
+
+    fn visit_variable(&self, name: &crate::token::Token) -> String {
+        name.lexeme.clone()
+    }
+
+    fn visit_assign(&self, name: &crate::token::Token, value: &Expr) -> String {
+        self.parenthesize(&format!("= {}", name.lexeme), vec![value])
+    }
+
+    fn visit_logical(&self, left: &Expr, operator: &crate::token::Token, right: &Expr) -> String {
+        self.parenthesize(operator.lexeme.as_str(), vec![left, right])
+    }
+
+    fn visit_comma(&self, expressions: &[Expr]) -> String {
+        self.parenthesize(",", expressions.iter().collect())
+    }
+
+    fn visit_ternary(&self, condition: &Expr, then_branch: &Expr, else_branch: &Expr) -> String {
+        self.parenthesize("?:", vec![condition, then_branch, else_branch])
+    }
+
+    fn visit_call(
+        &self,
+        callee: &Expr,
+        _paren: &crate::token::Token,
+        arguments: &[Expr],
+        names: &[Token],
+    ) -> String {
+        let positional = arguments.len() - names.len();
+        let mut parts = vec![self.print(callee)];
+        parts.extend(arguments[..positional].iter().map(|arg| self.print(arg)));
+        parts.extend(
+            names
+                .iter()
+                .zip(&arguments[positional..])
+                .map(|(name, arg)| format!("{}: {}", name.lexeme, self.print(arg))),
+        );
+        self.list("call", parts)
+    }
+
+    fn visit_get(&self, object: &Expr, name: &crate::token::Token) -> String {
+        self.parenthesize(&format!(". {}", name.lexeme), vec![object])
+    }
+
+    fn visit_this(&self, _keyword: &crate::token::Token) -> String {
+        String::from("this")
+    }
+
+    fn visit_super(&self, _keyword: &crate::token::Token, method: &crate::token::Token) -> String {
+        format!("(super {})", method.lexeme)
+    }
+
+    fn visit_block_expr(&self, statements: &[Stmt], value: Option<&Expr>) -> String {
+        let mut parts: Vec<_> = statements
+            .iter()
+            .map(|stmt| self.print_stmt(stmt))
+            .collect();
+        parts.extend(value.map(|value| self.print(value)));
+        self.list("block", parts)
+    }
+
+    fn visit_lambda(&self, params: &[crate::token::Token], body: &[Stmt]) -> String {
+        self.function("fun", params, body)
+    }
+
+    fn visit_set(
+        &self,
+        object: &Expr,
+        name: &crate::token::Token,
+        operator: Option<&crate::token::Token>,
+        value: &Expr,
+    ) -> String {
+        let operator = operator.map_or("", |operator| operator.lexeme.as_str());
+        self.parenthesize(
+            &format!(".{}= {}", operator, name.lexeme),
+            vec![object, value],
+        )
+    }
+
+    fn visit_list(&self, elements: &[Expr]) -> String {
+        self.parenthesize("list", elements.iter().collect())
+    }
+
+    fn visit_index(&self, object: &Expr, _bracket: &crate::token::Token, index: &Expr) -> String {
+        self.parenthesize("[]", vec![object, index])
+    }
+
+    fn visit_index_set(
+        &self,
+        object: &Expr,
+        _bracket: &crate::token::Token,
+        index: &Expr,
+        operator: Option<&crate::token::Token>,
+        value: &Expr,
+    ) -> String {
+        let operator = operator.map_or("", |operator| operator.lexeme.as_str());
+        self.parenthesize(&format!("[]{}=", operator), vec![object, index, value])
+    }
 }
 
-pub fn print(expr: &Expr) -> String {
-    expr.accept(&ExprVisitor)
+impl StmtVisitor<String> for ExprVisitor {
+    fn visit_expression_stmt(&self, expression: &Expr) -> String {
+        self.parenthesize(";", vec![expression])
+    }
+
+    fn visit_print(&self, expressions: &[Expr]) -> String {
+        self.parenthesize("print", expressions.iter().collect())
+    }
+
+    fn visit_var(&self, name: &Token, initializer: Option<&Expr>) -> String {
+        self.parenthesize(
+            &format!("var {}", name.lexeme),
+            initializer.into_iter().collect(),
+        )
+    }
+
+    fn visit_var_group(&self, declarations: &[Stmt]) -> String {
+        self.list(
+            "vars",
+            declarations
+                .iter()
+                .map(|stmt| self.print_stmt(stmt))
+                .collect(),
+        )
+    }
+
+    fn visit_block(&self, statements: &[Stmt]) -> String {
+        self.list(
+            "block",
+            statements
+                .iter()
+                .map(|stmt| self.print_stmt(stmt))
+                .collect(),
+        )
+    }
+
+    fn visit_if(&self, condition: &Expr, then_branch: &Stmt, else_branch: Option<&Stmt>) -> String {
+        let mut parts = vec![self.print(condition), self.print_stmt(then_branch)];
+        parts.extend(else_branch.map(|stmt| self.print_stmt(stmt)));
+        self.list("if", parts)
+    }
+
+    fn visit_while(&self, condition: &Expr, body: &Stmt, increment: Option<&Expr>) -> String {
+        let mut parts = vec![self.print(condition), self.print_stmt(body)];
+        parts.extend(increment.map(|expr| self.print(expr)));
+        self.list("while", parts)
+    }
+
+    fn visit_for_in(&self, name: &Token, iterable: &Expr, body: &Stmt) -> String {
+        self.list(
+            &format!("for {} in", name.lexeme),
+            vec![self.print(iterable), self.print_stmt(body)],
+        )
+    }
+
+    fn visit_function(&self, name: &Token, params: &[Token], body: &[Stmt]) -> String {
+        self.function(&format!("fun {}", name.lexeme), params, body)
+    }
+
+    fn visit_class(
+        &self,
+        name: &Token,
+        superclass: Option<&Expr>,
+        methods: &[Stmt],
+        getters: &[Stmt],
+        class_methods: &[Stmt],
+    ) -> String {
+        let mut head = format!("class {}", name.lexeme);
+        if let Some(superclass) = superclass {
+            head.push_str(&format!(" < {}", self.print(superclass)));
+        }
+        let mut parts: Vec<_> = methods.iter().map(|stmt| self.print_stmt(stmt)).collect();
+        for getter in getters {
+            if let Stmt::Function { name, body, .. } = getter {
+                let body = body.iter().map(|stmt| self.print_stmt(stmt)).collect();
+                parts.push(self.list(&format!("getter {}", name.lexeme), body));
+            }
+        }
+        for method in class_methods {
+            if let Stmt::Function {
+                name, params, body, ..
+            } = method
+            {
+                parts.push(self.function(&format!("class {}", name.lexeme), params, body));
+            }
+        }
+        self.list(&head, parts)
+    }
+
+    fn visit_return(&self, _keyword: &Token, value: Option<&Expr>) -> String {
+        self.parenthesize("return", value.into_iter().collect())
+    }
+
+    fn visit_break(&self, _keyword: &Token) -> String {
+        String::from("(break)")
+    }
+
+    fn visit_continue(&self, _keyword: &Token) -> String {
+        String::from("(continue)")
+    }
+
+    fn visit_assert(&self, _keyword: &Token, expression: &Expr, message: Option<&Expr>) -> String {
+        let mut exprs = vec![expression];
+        exprs.extend(message);
+        self.parenthesize("assert", exprs)
+    }
+
+    fn visit_defer(&self, _keyword: &Token, statement: &Stmt) -> String {
+        self.list("defer", vec![self.print_stmt(statement)])
+    }
+
+    fn visit_try(&self, body: &[Stmt], catch_var: &Token, catch_body: &[Stmt]) -> String {
+        let body = self.visit_block(body);
+        let catch = self.list(
+            &format!("catch {}", catch_var.lexeme),
+            catch_body
+                .iter()
+                .map(|stmt| self.print_stmt(stmt))
+                .collect(),
+        );
+        self.list("try", vec![body, catch])
+    }
 }
 
-fn parenthesize(name: &str, exprs: Vec<&Expr>) -> String {
-    let mut result = String::from("(");
-    result.push_str(name);
-    for expr in exprs {
-        result.push(' ');
-        result.push_str(&expr.accept(&ExprVisitor));
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+
+    fn print_source(s: &str) -> String {
+        let expr = Parser::new(Scanner::new(s.to_string()).scan_tokens())
+            .expression()
+            .unwrap();
+        ExprVisitor.print(&expr)
+    }
+
+    #[test]
+    fn test_print_assign_is_right_associative() {
+        assert_eq!(print_source("a = b = c = 1"), "(= a (= b (= c 1)))");
+    }
+
+    #[test]
+    fn test_print_precedence() {
+        assert_eq!(print_source("1 + 2 * 3"), "(+ 1 (* 2 3))");
+    }
+
+    #[test]
+    fn test_print_every_expression() {
+        assert_eq!(print_source("a or b and !c"), "(or a (and b (! c)))");
+        assert_eq!(print_source("f(1, g())"), "(call f 1 (call g))");
+        assert_eq!(print_source("f(1, y: 2, x: a)"), "(call f 1 y: 2 x: a)");
+        assert_eq!(print_source("a.b.c = this.d"), "(.= c (. b a) (. d this))");
+        assert_eq!(print_source("super.m(x)"), "(call (super m) x)");
+        assert_eq!(print_source("c ? 1 : 2, 3"), "(, (?: c 1 2) 3)");
+        assert_eq!(print_source("a[0] = [1, nil]"), "([]= a 0 (list 1 nil))");
+        assert_eq!(print_source("l[i][j]"), "([] ([] l i) j)");
+        assert_eq!(
+            print_source("{ var a = 1; a; a + 1 }"),
+            "(block (var a 1) (; a) (+ a 1))"
+        );
+        assert_eq!(print_source("{ print 1; }"), "(block (print 1))");
+        assert_eq!(
+            print_source("fun (a, b) { return a; }"),
+            "(fun (a b) (return a))"
+        );
+    }
+
+    #[test]
+    fn test_print_statements() {
+        let statements = Parser::new(
+            Scanner::new(
+                "var a;\nfun f(n) { if (n) return n; else return; }\nfor (var i = 0; i < 2; i = i + 1) { defer print i; continue; }\nwhile (true) break;\nclass B < A { m() { f(this); } area { return 1; } class make(a) { return B(); } }".to_string(),
+            )
+            .scan_tokens(),
+        )
+        .parse()
+        .unwrap();
+        let printed: Vec<_> = statements
+            .iter()
+            .map(|stmt| ExprVisitor.print_stmt(stmt))
+            .collect();
+        assert_eq!(
+            printed,
+            vec![
+                "(var a)",
+                "(fun f (n) (if n (return n) (return)))",
+                "(block (var i 0) (while (< i 2) (block (defer (print i)) (continue)) (= i (+ i 1))))",
+                "(while true (break))",
+                "(class B < A (fun m () (; (call f this))) (getter area (return 1)) (class make (a) (return (call B))))",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_print_variable() {
+        assert_eq!(print_source("a + b"), "(+ a b)");
     }
-    result.push(')');
-    result
 }
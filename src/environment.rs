@@ -1,4 +1,6 @@
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::rc::Rc;
 
 use crate::{loxresult::LoxResult, token::Token, value::Value};
 
@@ -27,7 +29,8 @@ use crate::{loxresult::LoxResult, token::Token, value::Value};
 pub struct Environment {
     ///这里存放了全局变量
     values: HashMap<String, Value>,
-    enclosing: Option<Box<Environment>>,
+    ///父环境，多个子环境可以共享同一个父环境
+    enclosing: Option<Rc<RefCell<Environment>>>,
 }
 
 impl Environment {
@@ -41,43 +44,82 @@ impl Environment {
     }
 
     ///产生子环境
-    pub fn new_enclosing(enclosing: Environment) -> Self {
+    pub fn new_enclosing(enclosing: Rc<RefCell<Environment>>) -> Self {
         Environment {
             values: HashMap::new(),
-            enclosing: Some(Box::new(enclosing)),
+            enclosing: Some(enclosing),
         }
     }
 
-    pub fn get_enclosing_env(&mut self) -> Option<Box<Self>> {
-        self.enclosing.clone()
-    }
-
     // remember to handle none
     ///这是[`Environment`]中的核心函数
     ///在当前的环境中搜索变量，如果没有找到，那么就向其父环境寻找,由此反复
     ///如果到global仍没有找到，那么就抛出异常
-    pub fn get(&self, name: Token) -> Result<Value, LoxResult> {
+    pub fn get(&self, name: &Token) -> Result<Value, LoxResult> {
         if let Some(v) = self.values.get(&name.lexeme) {
             return Ok(v.clone());
         } else if let Some(enclosing) = &self.enclosing {
-            return enclosing.get(name);
+            return enclosing.borrow().get(name);
         }
-        // BUG: error occur when calling function
         Err(LoxResult::RuntimeError {
             token: name.clone(),
             message: format!("Undefined variable '{}'.", &name.lexeme),
         })
     }
 
+    ///读取向外第`distance`层环境中的变量，`distance`由[`crate::resolver`]计算得出
+    pub fn get_at(&self, distance: usize, name: &str) -> Value {
+        if distance == 0 {
+            return self.values.get(name).cloned().unwrap_or(Value::Nil);
+        }
+        match &self.enclosing {
+            Some(enclosing) => enclosing.borrow().get_at(distance - 1, name),
+            None => Value::Nil,
+        }
+    }
+
+    ///给向外第`distance`层环境中的变量赋值
+    pub fn assign_at(&mut self, distance: usize, name: &str, value: Value) {
+        if distance == 0 {
+            self.values.insert(name.to_string(), value);
+        } else if let Some(enclosing) = &self.enclosing {
+            enclosing.borrow_mut().assign_at(distance - 1, name, value);
+        }
+    }
+
+    ///把环境链中的变量按名字排序写成文本，从当前作用域一直到最外层
+    ///
+    ///字符串会加上引号，函数等无法直接表示的值使用它们的显示形式，比如`<fn f>`
+    pub fn dump(&self) -> String {
+        let mut result = String::new();
+        self.dump_scope(0, &mut result);
+        result
+    }
+
+    fn dump_scope(&self, depth: usize, result: &mut String) {
+        result.push_str(&format!("scope {}:\n", depth));
+        let mut names: Vec<_> = self.values.keys().collect();
+        names.sort();
+        for name in names {
+            match &self.values[name] {
+                Value::String(s) => result.push_str(&format!("  {} = {:?}\n", name, s)),
+                value => result.push_str(&format!("  {} = {}\n", name, value)),
+            }
+        }
+        if let Some(enclosing) = &self.enclosing {
+            enclosing.borrow().dump_scope(depth + 1, result);
+        }
+    }
+
     ///赋值语句
-    pub fn assign(&mut self, name: Token, value: Value) -> Result<(), LoxResult> {
+    pub fn assign(&mut self, name: &Token, value: Value) -> Result<(), LoxResult> {
         if self.values.contains_key(&name.lexeme) {
             self.values.insert(name.lexeme.clone(), value);
             return Ok(());
         }
 
-        if let Some(enclosing) = &mut self.enclosing {
-            enclosing.assign(name, value)?;
+        if let Some(enclosing) = &self.enclosing {
+            enclosing.borrow_mut().assign(name, value)?;
             return Ok(());
         }
 
@@ -97,16 +139,48 @@ mod test {
     fn test_new_enclosing() {
         let mut env = Environment::new();
         env.define("a".to_string(), Value::Number(1.0));
-        let mut child_env = Environment::new_enclosing(env.clone());
+        let env = Rc::new(RefCell::new(env));
+        let child_env = Environment::new_enclosing(Rc::clone(&env));
         assert_eq!(
             child_env,
             Environment {
                 values: HashMap::new(),
-                enclosing: Some(Box::new(env))
+                enclosing: Some(env)
             }
         );
     }
 
+    #[test]
+    fn test_get_at_and_assign_at() {
+        //global -> middle -> inner，三层中都定义了`a`
+        let mut global = Environment::new();
+        global.define("a".to_string(), Value::Number(0.0));
+        global.define("g".to_string(), Value::Number(10.0));
+        let global = Rc::new(RefCell::new(global));
+        let mut middle = Environment::new_enclosing(Rc::clone(&global));
+        middle.define("a".to_string(), Value::Number(1.0));
+        let middle = Rc::new(RefCell::new(middle));
+        let mut inner = Environment::new_enclosing(Rc::clone(&middle));
+        inner.define("a".to_string(), Value::Number(2.0));
+
+        assert_eq!(inner.get_at(0, "a"), Value::Number(2.0));
+        assert_eq!(inner.get_at(1, "a"), Value::Number(1.0));
+        assert_eq!(inner.get_at(2, "a"), Value::Number(0.0));
+        assert_eq!(inner.get_at(2, "g"), Value::Number(10.0));
+
+        //只修改正好第`distance`层中的变量
+        inner.assign_at(1, "a", Value::Boolean(true));
+        assert_eq!(inner.get_at(0, "a"), Value::Number(2.0));
+        assert_eq!(middle.borrow().get_at(0, "a"), Value::Boolean(true));
+        assert_eq!(global.borrow().get_at(0, "a"), Value::Number(0.0));
+
+        inner.assign_at(2, "g", Value::Nil);
+        assert_eq!(global.borrow().get_at(0, "g"), Value::Nil);
+        inner.assign_at(0, "a", Value::Number(3.0));
+        assert_eq!(inner.get_at(0, "a"), Value::Number(3.0));
+        assert_eq!(inner.get_at(1, "a"), Value::Boolean(true));
+    }
+
     #[test]
     fn test_new() {
         assert_eq!(
@@ -1,4 +1,33 @@
-use crate::{loxcallable::LoxCallable, token::Token, value::Value};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::{
+    stmt::Stmt,
+    token::{Span, Token},
+};
+
+///表达式的编号，[`crate::resolver`]用它在解释器中记录变量所在作用域的深度
+///
+///编号参与相等比较，所以分别解析出来的两个语法树通常不相等
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExprId(usize);
+
+impl ExprId {
+    ///产生一个新的编号，每次调用都不相同
+    pub fn new() -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        ExprId(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+
+    pub fn value(&self) -> usize {
+        self.0
+    }
+}
+
+impl Default for ExprId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 ///为[`Expr`]类型实现观察者模式
 pub trait Visitor<R> {
@@ -6,46 +35,184 @@ pub trait Visitor<R> {
     fn visit_grouping(&self, expression: &Expr) -> R;
     fn visit_literal(&self, value: &crate::token::Literal) -> R;
     fn visit_unary(&self, operator: &Token, right: &Expr) -> R;
+    fn visit_variable(&self, name: &Token) -> R;
+    fn visit_assign(&self, name: &Token, value: &Expr) -> R;
+    fn visit_logical(&self, left: &Expr, operator: &Token, right: &Expr) -> R;
+    fn visit_comma(&self, expressions: &[Expr]) -> R;
+    fn visit_ternary(&self, condition: &Expr, then_branch: &Expr, else_branch: &Expr) -> R;
+    fn visit_call(&self, callee: &Expr, paren: &Token, arguments: &[Expr], names: &[Token]) -> R;
+    fn visit_get(&self, object: &Expr, name: &Token) -> R;
+    fn visit_this(&self, keyword: &Token) -> R;
+    fn visit_super(&self, keyword: &Token, method: &Token) -> R;
+    fn visit_block_expr(&self, statements: &[Stmt], value: Option<&Expr>) -> R;
+    fn visit_lambda(&self, params: &[Token], body: &[Stmt]) -> R;
+    fn visit_set(&self, object: &Expr, name: &Token, operator: Option<&Token>, value: &Expr) -> R;
+    fn visit_list(&self, elements: &[Expr]) -> R;
+    fn visit_index(&self, object: &Expr, bracket: &Token, index: &Expr) -> R;
+    fn visit_index_set(
+        &self,
+        object: &Expr,
+        bracket: &Token,
+        index: &Expr,
+        operator: Option<&Token>,
+        value: &Expr,
+    ) -> R;
 }
 
 ///定义了语言中的expression，expression类型如下
+///
+///每个表达式的`span`记录了它在源代码中的范围，供编辑器等工具使用，解释器不需要它
 #[derive(Debug, PartialEq, Clone)]
 pub enum Expr {
     Binary {
         left: Box<Expr>,
         operator: Token,
         right: Box<Expr>,
+        span: Span,
     },
     Grouping {
         expression: Box<Expr>,
+        span: Span,
     },
     Literal {
         value: crate::token::Literal,
+        span: Span,
     },
     Unary {
         operator: Token,
         right: Box<Expr>,
+        span: Span,
     },
     Variable {
+        id: ExprId,
         name: Token,
+        span: Span,
     },
     Assign {
+        id: ExprId,
         name: Token,
         value: Box<Expr>,
+        span: Span,
     },
     Logical {
         left: Box<Expr>,
         operator: Token,
         right: Box<Expr>,
-    }, //TODO: add support for comma
+        span: Span,
+    },
+    ///逗号表达式 `a, b, c`，从左到右依次求值，结果是最后一项的值
+    Comma {
+        expressions: Vec<Expr>,
+        span: Span,
+    },
+    ///条件表达式 `condition ? then_branch : else_branch`
+    Ternary {
+        condition: Box<Expr>,
+        then_branch: Box<Expr>,
+        else_branch: Box<Expr>,
+        span: Span,
+    },
+    ///函数调用 `callee(arguments)`，具名参数`name: value`总是在按位置的参数之后
     Call {
         callee: Box<Expr>,
         paren: Token,
         arguments: Vec<Expr>,
+        ///具名参数的名字，依次对应`arguments`中最后`names.len()`个参数
+        names: Vec<Token>,
+        span: Span,
+    },
+    ///读取实例的属性 `object.name`
+    Get {
+        object: Box<Expr>,
+        name: Token,
+        span: Span,
+    },
+    ///方法中的`this`
+    This {
+        id: ExprId,
+        keyword: Token,
+        span: Span,
+    },
+    ///`super.method`，在父类中查找方法
+    Super {
+        id: ExprId,
+        keyword: Token,
+        method: Token,
+        span: Span,
+    },
+    ///块表达式 `{ stmt; ... value }`，在新的作用域中依次执行语句，
+    ///最后一个没有`;`的表达式是块的值，没有时块的值是nil
+    Block {
+        statements: Vec<Stmt>,
+        value: Option<Box<Expr>>,
+        span: Span,
+    },
+    ///匿名函数 `fun (a, b) { ... }`
+    Lambda {
+        params: Vec<Token>,
+        body: Vec<Stmt>,
+        span: Span,
+    },
+    ///给实例的字段赋值 `object.name = value`
+    ///
+    ///`object.name += value`这样的复合赋值中`operator`是对应的二元运算符，
+    ///执行时`object`只计算一次，读出字段的值与`value`运算后再写回
+    Set {
+        object: Box<Expr>,
+        name: Token,
+        operator: Option<Token>,
+        value: Box<Expr>,
+        span: Span,
+    },
+    ///列表字面量 `[a, b, c]`
+    List {
+        elements: Vec<Expr>,
+        span: Span,
+    },
+    ///读取列表的元素 `object[index]`，`bracket`是右方括号，用于报告错误
+    Index {
+        object: Box<Expr>,
+        bracket: Token,
+        index: Box<Expr>,
+        span: Span,
+    },
+    ///给列表的元素赋值 `object[index] = value`，`operator`与[`Expr::Set`]中的相同
+    IndexSet {
+        object: Box<Expr>,
+        bracket: Token,
+        index: Box<Expr>,
+        operator: Option<Token>,
+        value: Box<Expr>,
+        span: Span,
     },
 }
 
 impl Expr {
+    ///节点在源代码中的范围，见[`Span`]
+    pub fn span(&self) -> Span {
+        match self {
+            Expr::Binary { span, .. }
+            | Expr::Grouping { span, .. }
+            | Expr::Literal { span, .. }
+            | Expr::Unary { span, .. }
+            | Expr::Variable { span, .. }
+            | Expr::Assign { span, .. }
+            | Expr::Logical { span, .. }
+            | Expr::Comma { span, .. }
+            | Expr::Ternary { span, .. }
+            | Expr::Call { span, .. }
+            | Expr::Get { span, .. }
+            | Expr::This { span, .. }
+            | Expr::Super { span, .. }
+            | Expr::Block { span, .. }
+            | Expr::Lambda { span, .. }
+            | Expr::Set { span, .. }
+            | Expr::List { span, .. }
+            | Expr::Index { span, .. }
+            | Expr::IndexSet { span, .. } => *span,
+        }
+    }
+
     ///为[`Expr`]类型实现观察者模式
     pub fn accept<R>(&self, visitor: &impl Visitor<R>) -> R {
         match self {
@@ -53,11 +220,66 @@ impl Expr {
                 left,
                 operator,
                 right,
+                ..
             } => visitor.visit_binary(left, operator, right),
-            Expr::Grouping { expression } => visitor.visit_grouping(expression),
-            Expr::Literal { value } => visitor.visit_literal(value),
-            Expr::Unary { operator, right } => visitor.visit_unary(operator, right),
-            _ => todo!(),
+            Expr::Grouping { expression, .. } => visitor.visit_grouping(expression),
+            Expr::Literal { value, .. } => visitor.visit_literal(value),
+            Expr::Unary {
+                operator, right, ..
+            } => visitor.visit_unary(operator, right),
+            Expr::Variable { name, .. } => visitor.visit_variable(name),
+            Expr::Assign { name, value, .. } => visitor.visit_assign(name, value),
+            Expr::Logical {
+                left,
+                operator,
+                right,
+                ..
+            } => visitor.visit_logical(left, operator, right),
+            Expr::Comma { expressions, .. } => visitor.visit_comma(expressions),
+            Expr::Ternary {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => visitor.visit_ternary(condition, then_branch, else_branch),
+            Expr::Call {
+                callee,
+                paren,
+                arguments,
+                names,
+                ..
+            } => visitor.visit_call(callee, paren, arguments, names),
+            Expr::Get { object, name, .. } => visitor.visit_get(object, name),
+            Expr::This { keyword, .. } => visitor.visit_this(keyword),
+            Expr::Super {
+                keyword, method, ..
+            } => visitor.visit_super(keyword, method),
+            Expr::Block {
+                statements, value, ..
+            } => visitor.visit_block_expr(statements, value.as_deref()),
+            Expr::Lambda { params, body, .. } => visitor.visit_lambda(params, body),
+            Expr::Set {
+                object,
+                name,
+                operator,
+                value,
+                ..
+            } => visitor.visit_set(object, name, operator.as_ref(), value),
+            Expr::List { elements, .. } => visitor.visit_list(elements),
+            Expr::Index {
+                object,
+                bracket,
+                index,
+                ..
+            } => visitor.visit_index(object, bracket, index),
+            Expr::IndexSet {
+                object,
+                bracket,
+                index,
+                operator,
+                value,
+                ..
+            } => visitor.visit_index_set(object, bracket, index, operator.as_ref(), value),
         }
     }
 }
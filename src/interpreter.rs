@@ -1,16 +1,104 @@
 //! interpreter.rs是用于词法分析的文件，它将执行[`Vec<Stmt>`]和[`Vec<Expr>`]语句，并于作用域进行交互，这里是整个编译器的终点
 //!
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap};
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
 use crate::{
-    environment::Environment, expr::Expr, loxcallable::LoxCallable, loxfunction::LoxFunction,
-    loxresult::LoxResult, stmt::Stmt, token::Token, token_type::TokenType, value::Value, Lox,
+    ast_printer::ExprVisitor,
+    environment::Environment,
+    expr::{Expr, ExprId},
+    loxcallable::LoxCallable,
+    loxclass::LoxClass,
+    loxfunction::LoxFunction,
+    loxinstance::LoxInstance,
+    loxresult::LoxResult,
+    natives::{self, Rng},
+    stmt::Stmt,
+    token::Token,
+    token_type::TokenType,
+    value::Value,
 };
 
+///读取一行输入追加到参数中，返回读到的字节数，0表示输入已经结束，与[`BufRead::read_line`]相同
+pub type LineReader = Box<dyn FnMut(&mut String) -> std::io::Result<usize>>;
+
+///[`Interpreter`]默认允许的函数调用嵌套层数，见[`Interpreter::set_max_call_depth`]
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 1000;
+
+///按默认的调用层数限制执行时，建议给运行解释器的线程分配的栈大小
+///
+///解释器递归地执行语法树，每层lox调用都要占用几十KB的rust栈，debug构建中尤其多，
+///比主线程默认的栈大小需要的多得多
+pub const STACK_SIZE: usize = 256 * 1024 * 1024;
+
+///一段程序是怎样结束的，见[`Interpreter::run_program`]
+#[derive(Debug, Clone)]
+pub enum RunOutcome {
+    ///所有语句都执行完了
+    Completed,
+    ///因为没有被接住的运行时错误而停止
+    RuntimeError(LoxResult),
+    ///调用了原生函数`exit`，其中是它给出的退出码
+    Exited(i32),
+}
+
+impl RunOutcome {
+    ///由[`Interpreter::interpret`]返回的错误得出程序的结局，最后一个错误是让程序停止的原因
+    pub fn from_errors(errors: Vec<LoxResult>) -> RunOutcome {
+        match errors.into_iter().last() {
+            None => RunOutcome::Completed,
+            Some(LoxResult::Exit { code }) => RunOutcome::Exited(code),
+            Some(error) => RunOutcome::RuntimeError(error),
+        }
+    }
+
+    ///命令行程序的退出码：正常结束是0，运行时错误是70，`exit`给出的退出码原样使用
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            RunOutcome::Completed => 0,
+            RunOutcome::RuntimeError(_) => 70,
+            RunOutcome::Exited(code) => *code,
+        }
+    }
+}
+
 pub struct Interpreter {
     ///是整个解释器的全局环境，用于保存全局变量
-    //should change globals to Rc
-    pub globals: Environment,
-    ///每个大括号作用域的子环境
-    environment: Environment,
+    pub globals: Rc<RefCell<Environment>>,
+    ///当前所在的作用域，在顶层时就是[`Interpreter::globals`]，每进入一个大括号作用域都会产生一个子环境
+    environment: Rc<RefCell<Environment>>,
+    ///每个正在执行的块中被`defer`推迟的语句，块退出时按注册的逆序执行
+    deferred: Vec<Vec<Stmt>>,
+    ///发生运行时错误时，把环境写入这个目录，见[`Interpreter::set_dump_on_error`]
+    dump_on_error: Option<PathBuf>,
+    ///运行时错误发生时最内层的环境，只在设置了`dump_on_error`时记录
+    error_environment: Option<Rc<RefCell<Environment>>>,
+    ///运行时错误向外传播时经过的函数调用，只在设置了`dump_on_error`时记录
+    error_trace: Vec<String>,
+    ///块已经带着错误退出时，推迟语句又产生的运行时错误，见[`Interpreter::run_deferred`]
+    unwind_errors: Vec<LoxResult>,
+    ///[`crate::resolver`]算出的局部变量深度，以表达式的编号为key，不在其中的变量是全局变量
+    locals: HashMap<usize, usize>,
+    ///为true时把执行的每条语句和它的结果打印到stderr，见[`Interpreter::with_trace`]
+    trace: bool,
+    ///正在执行的语句嵌套了几层，用于trace输出的缩进
+    trace_depth: usize,
+    ///执行过的语句开始的行，只在打开了[`Interpreter::set_coverage`]时记录
+    executed_lines: Option<BTreeSet<i32>>,
+    ///原生函数`readLine`从这里读取输入，默认是stdin，见[`Interpreter::set_input`]
+    input: LineReader,
+    ///`print`语句输出到这里，默认是stdout，见[`Interpreter::set_output`]
+    output: Box<dyn Write>,
+    ///正在执行的函数调用的层数
+    call_depth: usize,
+    ///函数调用最多嵌套的层数，超过时产生运行时错误而不是让rust的栈溢出
+    max_call_depth: usize,
+    ///原生函数`rand`和`rand_int`使用的随机数生成器，见[`Interpreter::set_seed`]
+    rng: Rng,
 }
 
 impl Default for Interpreter {
@@ -21,22 +109,217 @@ impl Default for Interpreter {
 
 impl Interpreter {
     pub fn new() -> Self {
-        let globals = Environment::new();
-        //TODO: implement native function like clock
+        Interpreter::with_trace(false)
+    }
+
+    ///`trace`为true时，每条语句执行前把它打印到stderr，执行成功后打印它的结果，
+    ///块和函数体中的语句会多缩进一层
+    pub fn with_trace(trace: bool) -> Self {
+        let globals = Interpreter::new_globals();
         Interpreter {
+            environment: Rc::clone(&globals),
             globals,
-            environment: Environment::new(),
+            deferred: Vec::new(),
+            dump_on_error: None,
+            error_environment: None,
+            error_trace: Vec::new(),
+            unwind_errors: Vec::new(),
+            locals: HashMap::new(),
+            trace,
+            trace_depth: 0,
+            executed_lines: None,
+            input: Box::new(|line| std::io::stdin().read_line(line)),
+            output: Box::new(std::io::stdout()),
+            call_depth: 0,
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            rng: Rng::from_time(),
+        }
+    }
+
+    ///只定义了原生函数的全局环境
+    fn new_globals() -> Rc<RefCell<Environment>> {
+        let mut globals = Environment::new();
+        natives::define_natives(&mut globals);
+        Rc::new(RefCell::new(globals))
+    }
+
+    ///丢掉所有全局变量和变量解析的结果，回到刚创建时的状态；输入、输出和其他设置保持不变
+    pub fn reset(&mut self) {
+        self.globals = Interpreter::new_globals();
+        self.environment = Rc::clone(&self.globals);
+        self.locals.clear();
+    }
+
+    ///设置之后，逃逸到[`Interpreter::interpret`]的运行时错误会把当时的环境链、
+    ///调用栈和错误信息写入`dir`目录下一个带时间戳的文件
+    pub fn set_dump_on_error(&mut self, dir: PathBuf) {
+        self.dump_on_error = Some(dir);
+    }
+
+    ///为true时记录每条被执行的语句开始的行，之后可以通过[`Interpreter::executed_lines`]取得
+    pub fn set_coverage(&mut self, coverage: bool) {
+        self.executed_lines = coverage.then(BTreeSet::new);
+    }
+
+    ///执行过的语句开始的行，没有打开[`Interpreter::set_coverage`]时是`None`
+    pub fn executed_lines(&self) -> Option<&BTreeSet<i32>> {
+        self.executed_lines.as_ref()
+    }
+
+    ///替换`readLine`读取输入的来源，比如在测试中使用内存中的数据
+    ///
+    ///默认每次调用都直接从stdin读取一行，不会预先缓冲，所以repl和脚本中的`readLine`可以交替使用stdin
+    pub fn set_input(&mut self, mut input: impl BufRead + 'static) {
+        self.input = Box::new(move |line| input.read_line(line));
+    }
+
+    ///替换`print`语句的输出目标，比如把输出收集到内存中
+    pub fn set_output(&mut self, output: impl Write + 'static) {
+        self.output = Box::new(output);
+    }
+
+    ///把`text`原样写入输出，`print`语句和原生函数`print_raw`、`println`都通过它输出
+    ///
+    ///与`print!`不同，写入失败时不会panic，输出被关闭不影响程序继续执行
+    pub fn write_output(&mut self, text: &str) {
+        let _ = self.output.write_all(text.as_bytes());
+    }
+
+    ///把输出中缓冲的内容立即写出，用于没有以换行结尾的输出，比如`readLine`之前的提示
+    pub fn flush_output(&mut self) {
+        let _ = self.output.flush();
+    }
+
+    ///设置函数调用最多嵌套的层数，默认是[`DEFAULT_MAX_CALL_DEPTH`]
+    ///
+    ///超过时产生`Stack overflow.`运行时错误，它和其他运行时错误一样可以被`try`接住。
+    ///解释器递归地执行语法树，每层lox调用都要占用不少rust的栈，设置得太大时进程仍然可能栈溢出
+    pub fn set_max_call_depth(&mut self, depth: usize) {
+        self.max_call_depth = depth;
+    }
+
+    ///设置`rand`和`rand_int`的随机数种子，种子相同时它们产生的序列也相同。默认用当前时间作为种子
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = Rng::new(seed);
+    }
+
+    ///`[0, 1)`之间的下一个随机数
+    pub fn random(&mut self) -> f64 {
+        self.rng.next_f64()
+    }
+
+    ///从输入中读取一行，不包括行尾的换行符，输入结束时返回`None`
+    pub fn read_line(&mut self) -> std::io::Result<Option<String>> {
+        let mut line = String::new();
+        if (self.input)(&mut line)? == 0 {
+            return Ok(None);
+        }
+        if line.ends_with('\n') {
+            line.pop();
+            if line.ends_with('\r') {
+                line.pop();
+            }
+        }
+        Ok(Some(line))
+    }
+
+    ///记录编号为`id`的表达式所用的变量定义在向外第`depth`层作用域中
+    pub fn resolve(&mut self, id: ExprId, depth: usize) {
+        self.locals.insert(id.value(), depth);
+    }
+
+    ///根据[`Interpreter::resolve`]记录的深度查找变量，没有记录的到全局环境中查找
+    ///
+    ///声明了但从未赋值的变量([`Value::Uninitialized`])不能读取
+    fn look_up_variable(&self, id: ExprId, name: &Token) -> Result<Value, LoxResult> {
+        let value = match self.locals.get(&id.value()) {
+            Some(distance) => self.environment.borrow().get_at(*distance, &name.lexeme),
+            None => self.globals.borrow().get(name)?,
+        };
+        if let Value::Uninitialized = value {
+            return Err(LoxResult::RuntimeError {
+                token: name.clone(),
+                message: format!(
+                    "Variable '{}' is used before being initialized.",
+                    name.lexeme
+                ),
+            });
         }
+        Ok(value)
     }
 
     ///解释从[`crate::parser`]得来的[`Vec<Stmt>`]
-    pub fn interpret(&mut self, statements: Vec<Stmt>) {
-        statements.into_iter().for_each(|stmt| {
-            if let Err(e) = self.execute(stmt) {
-                Lox::runtime_error(e);
-                return;
+    ///
+    ///整个脚本被视作最外层的块，顶层的`defer`语句会在脚本执行结束后执行
+    ///
+    ///成功时返回最后一条语句的值，表达式语句和`print`语句的值是表达式的值，其他语句的值是nil；
+    ///出错时返回按发生顺序排列的运行时错误，最后一个是让脚本停止的错误
+    pub fn interpret(&mut self, statements: Vec<Stmt>) -> Result<Value, Vec<LoxResult>> {
+        self.deferred.push(Vec::new());
+        let result = self.execute_statements(statements);
+        let result = self.run_deferred(result);
+        let mut errors = std::mem::take(&mut self.unwind_errors);
+        let mut value = Value::Nil;
+        match result {
+            Ok(v) => value = v,
+            Err(e) => {
+                if let (LoxResult::RuntimeError { .. }, Some(dir)) = (&e, &self.dump_on_error) {
+                    match self.dump_environment(dir, &e) {
+                        Ok(path) => eprintln!("Environment dumped to {}", path.display()),
+                        Err(err) => eprintln!("Failed to dump environment: {}", err),
+                    }
+                }
+                errors.push(e);
             }
-        })
+        }
+        self.error_environment = None;
+        self.error_trace.clear();
+        if errors.is_empty() {
+            Ok(value)
+        } else {
+            Err(errors)
+        }
+    }
+
+    ///与[`Interpreter::interpret`]相同，但只返回程序是怎样结束的，
+    ///嵌入解释器的程序可以借此区分正常结束、出错和调用了`exit`，而不用自己检查错误列表
+    pub fn run_program(&mut self, statements: Vec<Stmt>) -> RunOutcome {
+        match self.interpret(statements) {
+            Ok(_) => RunOutcome::Completed,
+            Err(errors) => RunOutcome::from_errors(errors),
+        }
+    }
+
+    ///把错误发生时的环境链写入`dir`目录，返回写入的文件路径
+    fn dump_environment(&self, dir: &Path, error: &LoxResult) -> std::io::Result<PathBuf> {
+        let mut content = match error {
+            LoxResult::RuntimeError { token, message } => {
+                format!("[line {}] {}\n", token.line, message)
+            }
+            _ => String::new(),
+        };
+        content.push_str("\nstack trace:\n");
+        for frame in &self.error_trace {
+            content.push_str(&format!("  {}\n", frame));
+        }
+        content.push_str("\nenvironment:\n");
+        content.push_str(
+            &self
+                .error_environment
+                .as_ref()
+                .unwrap_or(&self.environment)
+                .borrow()
+                .dump(),
+        );
+
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or_default();
+        std::fs::create_dir_all(dir)?;
+        let path = dir.join(format!("lox-env-{}.txt", millis));
+        std::fs::write(&path, content)?;
+        Ok(path)
     }
     //TODO: change the function signature otherwise there are bugs in whlie loop
     //
@@ -45,59 +328,222 @@ impl Interpreter {
     ///根据[`Stmt`]的类型不同,进行不同的处理
     ///[`Interpreter::evaluate`]同理
     fn execute(&mut self, stmt: Stmt) -> Result<Value, LoxResult> {
+        if let Some(lines) = &mut self.executed_lines {
+            lines.insert(stmt.span().start.0);
+        }
+        if self.trace {
+            return self.execute_traced(stmt);
+        }
+        self.execute_stmt(stmt)
+    }
+
+    ///打开trace时执行语句，前后各打印一行
+    fn execute_traced(&mut self, stmt: Stmt) -> Result<Value, LoxResult> {
+        let indent = "  ".repeat(self.trace_depth);
+        eprintln!("{}{}", indent, ExprVisitor.print_stmt(&stmt));
+        self.trace_depth += 1;
+        let result = self.execute_stmt(stmt);
+        self.trace_depth -= 1;
+        match &result {
+            Ok(value) => eprintln!("{}=> {}", indent, value),
+            Err(LoxResult::ReturnValue { value }) => eprintln!("{}=> return {}", indent, value),
+            Err(_) => {}
+        }
+        result
+    }
+
+    fn execute_stmt(&mut self, stmt: Stmt) -> Result<Value, LoxResult> {
         match stmt {
-            Stmt::Print { expression } => {
-                let value = self.evaluate(*expression)?;
-                println!("{}", value);
-                Ok(Value::Nil)
+            Stmt::Print { expressions, .. } => {
+                let mut values = Vec::with_capacity(expressions.len());
+                for expression in expressions {
+                    values.push(self.evaluate(expression)?);
+                }
+                let line: Vec<_> = values.iter().map(|value| value.to_string()).collect();
+                self.write_output(&format!("{}\n", line.join(" ")));
+                Ok(values.pop().unwrap_or(Value::Nil))
             }
-            Stmt::Expression { expression } => Ok(self.evaluate(*expression)?),
-            Stmt::Var { name, initializer } => {
-                let mut value = Value::Nil;
+            Stmt::Expression { expression, .. } => Ok(self.evaluate(*expression)?),
+            Stmt::Var {
+                name, initializer, ..
+            } => {
+                let mut value = Value::Uninitialized;
                 if let Some(initializer) = initializer {
                     value = self.evaluate(*initializer)?;
                 }
-                self.globals.define(name.lexeme, value);
+                self.environment.borrow_mut().define(name.lexeme, value);
                 Ok(Value::Nil)
             }
-            Stmt::Block { statements } => {
-                //WARNING: the return value of new_enclosing is not correct in function execute_block
-                self.execute_block(statements, Environment::new_enclosing(self.globals.clone()))
+            Stmt::VarGroup { declarations, .. } => {
+                for declaration in declarations {
+                    self.execute(declaration)?;
+                }
+                Ok(Value::Nil)
             }
+            Stmt::Block { statements, .. } => self.execute_block(
+                statements,
+                Environment::new_enclosing(Rc::clone(&self.environment)),
+            ),
             Stmt::If {
                 condition,
                 then_branch,
                 else_branch,
+                ..
             } => {
-                let value = self.evaluate(*condition)?;
-                match value {
-                    Value::Boolean(bool) => {
-                        if bool {
-                            self.execute(*then_branch)?;
-                        } else {
-                            if let Some(else_stmt) = else_branch {
-                                self.execute(*else_stmt)?;
-                            }
-                        }
-                    }
-                    _ => unreachable!(), //TODO: add the exception handler
+                if self.evaluate(*condition)?.is_true() {
+                    self.execute(*then_branch)?;
+                } else if let Some(else_stmt) = else_branch {
+                    self.execute(*else_stmt)?;
                 }
                 Ok(Value::Nil)
             }
-            Stmt::While { condition, body } => {
+            Stmt::While {
+                condition,
+                body,
+                increment,
+                ..
+            } => {
                 while self.evaluate(*condition.clone())?.is_true() {
-                    self.execute(*body.clone())?;
+                    match self.execute(*body.clone()) {
+                        Ok(_) | Err(LoxResult::Continue) => {}
+                        Err(LoxResult::Break) => break,
+                        Err(e) => return Err(e),
+                    }
+                    if let Some(increment) = &increment {
+                        self.evaluate(*increment.clone())?;
+                    }
+                }
+                Ok(Value::Nil)
+            }
+            Stmt::ForIn {
+                name,
+                iterable,
+                body,
+                ..
+            } => {
+                let items = match self.evaluate(*iterable)? {
+                    Value::List(list) => list.borrow().clone(),
+                    Value::String(s) => s.chars().map(|c| Value::String(c.to_string())).collect(),
+                    _ => {
+                        return Err(LoxResult::RuntimeError {
+                            token: name,
+                            message: "Can only iterate over lists and strings.".to_string(),
+                        })
+                    }
+                };
+                for item in items {
+                    let mut environment = Environment::new_enclosing(Rc::clone(&self.environment));
+                    environment.define(name.lexeme.clone(), item);
+                    match self.execute_block(vec![*body.clone()], environment) {
+                        Ok(_) | Err(LoxResult::Continue) => {}
+                        Err(LoxResult::Break) => break,
+                        Err(e) => return Err(e),
+                    }
                 }
                 Ok(Value::Nil)
             }
-            Stmt::Function { name, params, body } => {
-                let function = Value::LoxFunction(LoxFunction::new(name.clone(), params, body));
+            Stmt::Function {
+                name, params, body, ..
+            } => {
+                let function = Value::LoxFunction(LoxFunction::new(
+                    name.clone(),
+                    params,
+                    body,
+                    Rc::clone(&self.environment),
+                ));
                 //change here
-                self.globals.define(name.lexeme, function);
+                self.environment.borrow_mut().define(name.lexeme, function);
                 //WARNING: error
                 Ok(Value::Nil)
             }
-            Stmt::Return { keyword: _, value } => {
+            Stmt::Class {
+                name,
+                superclass,
+                methods,
+                getters,
+                class_methods,
+                ..
+            } => {
+                let superclass = match superclass {
+                    Some(Expr::Variable { id, name, .. }) => {
+                        match self.look_up_variable(id, &name)? {
+                            Value::LoxClass(superclass) => Some(superclass),
+                            _ => {
+                                return Err(LoxResult::RuntimeError {
+                                    token: name,
+                                    message: "Superclass must be a class.".to_string(),
+                                })
+                            }
+                        }
+                    }
+                    _ => None,
+                };
+
+                let mut instance_methods = HashMap::new();
+                for method in methods {
+                    if let Stmt::Function {
+                        name, params, body, ..
+                    } = method
+                    {
+                        let mut function = LoxFunction::new(
+                            name.clone(),
+                            params,
+                            body,
+                            Rc::clone(&self.environment),
+                        )
+                        .with_superclass(superclass.clone());
+                        if name.lexeme == "init" {
+                            function = function.into_initializer();
+                        }
+                        instance_methods.insert(name.lexeme, function);
+                    }
+                }
+                for getter in getters {
+                    if let Stmt::Function {
+                        name, params, body, ..
+                    } = getter
+                    {
+                        let function = LoxFunction::new(
+                            name.clone(),
+                            params,
+                            body,
+                            Rc::clone(&self.environment),
+                        )
+                        .with_superclass(superclass.clone())
+                        .into_getter();
+                        instance_methods.insert(name.lexeme, function);
+                    }
+                }
+                //静态方法中不能使用`this`和`super`，所以不需要父类
+                let mut static_methods = HashMap::new();
+                for method in class_methods {
+                    if let Stmt::Function {
+                        name, params, body, ..
+                    } = method
+                    {
+                        let function = LoxFunction::new(
+                            name.clone(),
+                            params,
+                            body,
+                            Rc::clone(&self.environment),
+                        );
+                        static_methods.insert(name.lexeme, function);
+                    }
+                }
+                let class = LoxClass::new(
+                    name.lexeme.clone(),
+                    superclass,
+                    instance_methods,
+                    static_methods,
+                );
+                self.environment
+                    .borrow_mut()
+                    .define(name.lexeme, Value::LoxClass(Rc::new(class)));
+                Ok(Value::Nil)
+            }
+            Stmt::Return {
+                keyword: _, value, ..
+            } => {
                 let mut return_value = Value::Nil;
                 if let Some(value) = value {
                     return_value = self.evaluate(value)?;
@@ -106,43 +552,225 @@ impl Interpreter {
                     value: return_value,
                 })
             }
-            _ => unreachable!(),
+            Stmt::Break { keyword: _, .. } => Err(LoxResult::Break),
+            Stmt::Continue { keyword: _, .. } => Err(LoxResult::Continue),
+            Stmt::Assert {
+                keyword,
+                expression,
+                message,
+                ..
+            } => {
+                if self.evaluate(*expression)?.is_true() {
+                    return Ok(Value::Nil);
+                }
+                let message = match message {
+                    Some(message) => self.evaluate(*message)?.to_string(),
+                    None => "Assertion failed.".to_string(),
+                };
+                Err(LoxResult::RuntimeError {
+                    token: keyword,
+                    message,
+                })
+            }
+            Stmt::Defer {
+                keyword: _,
+                statement,
+                ..
+            } => {
+                if let Some(deferred) = self.deferred.last_mut() {
+                    deferred.push(*statement);
+                }
+                Ok(Value::Nil)
+            }
+            //只接住运行时错误，`return`、`break`和`continue`照常向外传播
+            Stmt::Try {
+                body,
+                catch_var,
+                catch_body,
+                ..
+            } => {
+                let result = self.execute_block(
+                    body,
+                    Environment::new_enclosing(Rc::clone(&self.environment)),
+                );
+                match result {
+                    Err(LoxResult::RuntimeError { message, .. }) => {
+                        //错误已经被处理，不再需要为它导出环境
+                        self.error_environment = None;
+                        self.error_trace.clear();
+                        let mut environment =
+                            Environment::new_enclosing(Rc::clone(&self.environment));
+                        environment.define(catch_var.lexeme, Value::String(message));
+                        self.execute_block(catch_body, environment)
+                    }
+                    result => result,
+                }
+            }
         }
     }
 
     ///进入一个作用域interpret要做的事情:
-    ///把父作用域(environment)中的变量移动到子作用域
-    ///然后执行子作用域中的语句
+    ///把`environment`作为当前作用域，然后执行其中的语句
+    ///
+    ///无论块是正常结束还是因为`return`或错误提前退出，都会经过同一个出口：
+    ///先执行本块中被`defer`推迟的语句，再恢复进入之前的作用域
     pub fn execute_block(
         &mut self,
         statements: Vec<Stmt>,
         environment: Environment,
     ) -> Result<Value, LoxResult> {
-        let previous = std::mem::replace(&mut self.globals, environment); //useless
+        let previous = std::mem::replace(&mut self.environment, Rc::new(RefCell::new(environment)));
+        self.deferred.push(Vec::new());
+        let result = self.execute_statements(statements);
+        let result = self.run_deferred(result);
+        if let Err(LoxResult::RuntimeError { .. }) = result {
+            if self.dump_on_error.is_some() && self.error_environment.is_none() {
+                self.error_environment = Some(Rc::clone(&self.environment));
+            }
+        }
+        self.environment = previous;
+        result
+    }
+
+    ///依次执行语句，遇到第一个错误(或`return`)时停止，返回最后一条语句的值
+    fn execute_statements(&mut self, statements: Vec<Stmt>) -> Result<Value, LoxResult> {
+        let mut value = Value::Nil;
         for stmt in statements {
-            if let Err(e) = self.execute(stmt) {
-                self.globals = previous;
-                return Err(e);
+            value = self.execute(stmt)?;
+        }
+        Ok(value)
+    }
+
+    ///按注册的逆序执行当前块中被推迟的语句
+    ///
+    ///错误处理策略:
+    ///
+    ///如果块是正常结束的，第一个出错的推迟语句的错误会成为块的结果；
+    ///如果块已经带着`return`或错误退出，推迟语句产生的运行时错误会被记录下来，
+    ///由[`Interpreter::interpret`]一起返回，不会替换原本的返回值或错误。无论哪种情况，剩下的推迟语句都会继续执行
+    fn run_deferred(&mut self, mut result: Result<Value, LoxResult>) -> Result<Value, LoxResult> {
+        let deferred = self.deferred.pop().unwrap_or_default();
+        for stmt in deferred.into_iter().rev() {
+            match self.execute(stmt) {
+                Ok(_) => {}
+                Err(e) if result.is_ok() => result = Err(e),
+                Err(e @ LoxResult::RuntimeError { .. }) => self.unwind_errors.push(e),
+                Err(_) => {}
             }
         }
-        if let Some(previous) = self.globals.get_enclosing_env() {
-            self.globals = *previous;
+        result
+    }
+
+    ///调用函数，调用的层数超过[`Interpreter::set_max_call_depth`]设置的限制时返回运行时错误
+    ///
+    ///所有对lox函数的调用都要经过这里，包括创建实例时对`init`的调用
+    pub(crate) fn call(
+        &mut self,
+        function: &dyn LoxCallable,
+        paren: &Token,
+        arguments: Vec<Value>,
+    ) -> Result<Value, LoxResult> {
+        if self.call_depth >= self.max_call_depth {
+            return Err(LoxResult::RuntimeError {
+                token: paren.clone(),
+                message: "Stack overflow.".to_string(),
+            });
+        }
+        self.call_depth += 1;
+        let value = function.call(self, paren, arguments);
+        self.call_depth -= 1;
+        value
+    }
+
+    ///属性读取到的是getter时立即调用它并返回结果，其他值原样返回
+    fn call_getter(&mut self, value: Value, name: &Token) -> Result<Value, LoxResult> {
+        match value {
+            Value::LoxFunction(function) if function.is_getter() => {
+                self.call(&function, name, Vec::new())
+            }
+            value => Ok(value),
         }
-        Ok(Value::Nil)
     }
 
-    ///检查操作数是否符合要求
-    fn check_number_operands(
+    ///比较运算的两个操作数必须同为数字或同为字符串，字符串按字典序比较
+    fn check_comparable_operands(
         operator: &Token,
         left: &Value,
         right: &Value,
     ) -> Result<(), LoxResult> {
-        if let (Value::Number(_), Value::Number(_)) = (left, right) {
-            return Ok(());
+        match (left, right) {
+            (Value::Number(_), Value::Number(_)) | (Value::String(_), Value::String(_)) => Ok(()),
+            _ => Err(LoxResult::RuntimeError {
+                token: operator.clone(),
+                message: "Operands must be two numbers or two strings.".to_string(),
+            }),
+        }
+    }
+
+    ///只有列表可以使用下标
+    fn check_list(object: Value, bracket: &Token) -> Result<Rc<RefCell<Vec<Value>>>, LoxResult> {
+        match object {
+            Value::List(list) => Ok(list),
+            _ => Err(LoxResult::RuntimeError {
+                token: bracket.clone(),
+                message: "Only lists can be indexed.".to_string(),
+            }),
         }
-        Err(LoxResult::RuntimeError {
-            token: operator.clone(),
-            message: format!("Operand must be a number"),
+    }
+
+    ///下标必须是不超出列表长度的非负整数
+    fn check_index(bracket: &Token, index: &Value, len: usize) -> Result<usize, LoxResult> {
+        match index {
+            Value::Number(n) if n.fract() == 0.0 => {
+                if *n >= 0.0 && (*n as usize) < len {
+                    Ok(*n as usize)
+                } else {
+                    Err(LoxResult::RuntimeError {
+                        token: bracket.clone(),
+                        message: "List index out of range.".to_string(),
+                    })
+                }
+            }
+            _ => Err(LoxResult::RuntimeError {
+                token: bracket.clone(),
+                message: "List index must be an integer.".to_string(),
+            }),
+        }
+    }
+
+    ///计算二元运算符，复合赋值也使用它
+    fn binary(left: Value, operator: &Token, right: Value) -> Result<Value, LoxResult> {
+        Ok(match operator.token_type {
+            TokenType::PLUS => left.add(right, operator)?,
+            TokenType::MINUS => left.sub(right, operator)?,
+            TokenType::STAR => left.mul(right, operator)?,
+            TokenType::SLASH => left.div(right, operator)?,
+            TokenType::SLASH_SLASH => left.floor_div(right, operator)?,
+            TokenType::AMPERSAND
+            | TokenType::PIPE
+            | TokenType::CARET
+            | TokenType::LESS_LESS
+            | TokenType::GREATER_GREATER => left.bitwise(right, operator)?,
+            TokenType::EQUAL_EQUAL => Value::Boolean(left.is_equal(&right)),
+            TokenType::BANG_EQUAL => Value::Boolean(!left.is_equal(&right)),
+            TokenType::GREATER => {
+                Interpreter::check_comparable_operands(operator, &left, &right)?;
+                Value::Boolean(left > right)
+            }
+            TokenType::GREATER_EQUAL => {
+                Interpreter::check_comparable_operands(operator, &left, &right)?;
+                Value::Boolean(left >= right)
+            }
+            TokenType::LESS => {
+                Interpreter::check_comparable_operands(operator, &left, &right)?;
+                Value::Boolean(left < right)
+            }
+            TokenType::LESS_EQUAL => {
+                Interpreter::check_comparable_operands(operator, &left, &right)?;
+                Value::Boolean(left <= right)
+            }
+
+            _ => unreachable!(),
         })
     }
 
@@ -154,132 +782,295 @@ impl Interpreter {
                 left,
                 operator,
                 right,
+                ..
             } => {
                 let left = self.evaluate(*left)?;
                 let right = self.evaluate(*right)?;
-
-                match operator.token_type {
-                    TokenType::PLUS => match (&left, &right) {
-                        (Value::Number(_), Value::Number(_))
-                        | (Value::String(_), Value::String(_)) => left + right,
-                        _ => {
-                            return Err(LoxResult::RuntimeError {
-                                token: operator,
-                                message: format!("Operands must be two numbers or two strings."),
-                            })
-                        }
-                    },
-                    TokenType::MINUS => {
-                        Interpreter::check_number_operands(&operator, &left, &right)?;
-                        left - right
-                    }
-                    TokenType::STAR => {
-                        Interpreter::check_number_operands(&operator, &left, &right)?;
-                        left * right
-                    }
-                    TokenType::SLASH => {
-                        Interpreter::check_number_operands(&operator, &left, &right)?;
-                        left / right
-                    }
-                    TokenType::EQUAL_EQUAL => Value::Boolean(left == right),
-                    TokenType::BANG_EQUAL => Value::Boolean(left != right),
-                    TokenType::GREATER => {
-                        Interpreter::check_number_operands(&operator, &left, &right)?;
-                        Value::Boolean(left > right)
-                    }
-                    TokenType::GREATER_EQUAL => {
-                        Interpreter::check_number_operands(&operator, &left, &right)?;
-                        Value::Boolean(left >= right)
-                    }
-                    TokenType::LESS => {
-                        Interpreter::check_number_operands(&operator, &left, &right)?;
-                        Value::Boolean(left < right)
-                    }
-                    TokenType::LESS_EQUAL => {
-                        Interpreter::check_number_operands(&operator, &left, &right)?;
-                        Value::Boolean(left <= right)
-                    }
-
-                    _ => unreachable!(),
-                }
+                Interpreter::binary(left, &operator, right)?
             }
-            Expr::Grouping { expression } => self.evaluate(*expression)?,
-            Expr::Literal { value } => match value {
+            Expr::Grouping { expression, .. } => self.evaluate(*expression)?,
+            Expr::Literal { value, .. } => match value {
                 crate::token::Literal::String(s) => Value::String(s),
                 crate::token::Literal::Number(n) => Value::Number(n),
                 crate::token::Literal::Bool(b) => Value::Boolean(b),
                 crate::token::Literal::Nil => Value::Nil,
             },
-            Expr::Unary { operator, right } => {
+            Expr::Unary {
+                operator, right, ..
+            } => {
                 let right_value = self.evaluate(*right)?;
                 match operator.token_type {
-                    TokenType::MINUS => -right_value,
+                    TokenType::MINUS => right_value.neg(&operator)?,
                     TokenType::BANG => !right_value,
                     _ => unreachable!(),
                 }
             }
-            //WARNING:
-            //self.environment.get
-            Expr::Variable { name } => self.globals.get(name)?,
-            Expr::Assign { name, value } => {
+            Expr::Variable { id, name, .. } => self.look_up_variable(id, &name)?,
+            Expr::Assign {
+                id, name, value, ..
+            } => {
                 let value = self.evaluate(*value)?;
-                self.globals.assign(name, value.clone())?;
+                match self.locals.get(&id.value()) {
+                    Some(distance) => self.environment.borrow_mut().assign_at(
+                        *distance,
+                        &name.lexeme,
+                        value.clone(),
+                    ),
+                    None => self.globals.borrow_mut().assign(&name, value.clone())?,
+                }
                 value
             }
             Expr::Logical {
                 left,
                 operator,
                 right,
+                ..
             } => {
                 let left = self.evaluate(*left)?;
-                if operator.token_type == TokenType::OR {
-                    if left.is_true() {
-                        return Ok(left);
-                    } else {
-                        if !left.is_true() {
-                            return Ok(left);
-                        }
-                    }
+                //`or`在左边为真时、`and`在左边为假时、`??`在左边不是nil时直接返回左边，不再计算右边
+                let short_circuit = match operator.token_type {
+                    TokenType::OR => left.is_true(),
+                    TokenType::QUESTION_QUESTION => left != Value::Nil,
+                    _ => !left.is_true(),
+                };
+                if short_circuit {
+                    return Ok(left);
                 }
                 self.evaluate(*right)?
             }
+            Expr::Comma { expressions, .. } => {
+                let mut value = Value::Nil;
+                for expression in expressions {
+                    value = self.evaluate(expression)?;
+                }
+                value
+            }
+            Expr::Ternary {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                if self.evaluate(*condition)?.is_true() {
+                    self.evaluate(*then_branch)?
+                } else {
+                    self.evaluate(*else_branch)?
+                }
+            }
             Expr::Call {
                 callee,
                 paren,
                 arguments,
+                names,
+                ..
             } => {
                 // dbg!(&callee);
                 // dbg!(&paren);
                 // dbg!(&arguments);
 
                 let callee = self.evaluate(*callee)?;
+                let frame = self
+                    .dump_on_error
+                    .as_ref()
+                    .map(|_| format!("{} called at [line {}]", callee, paren.line));
 
                 let mut parameters = Vec::new();
                 for argument in arguments {
                     parameters.push(self.evaluate(argument)?);
                 }
-                //TODO: implement the type checking : whether callee implement the trait,
-                //loxcallable
+                //只有函数、类和内置函数可以调用，其他值调用[`LoxCallable`]的方法会panic
+                if !matches!(
+                    callee,
+                    Value::LoxFunction(_) | Value::LoxClass(_) | Value::NativeFunction(_)
+                ) {
+                    return Err(LoxResult::RuntimeError {
+                        token: paren,
+                        message: "Can only call functions and classes.".to_string(),
+                    });
+                }
+                if !names.is_empty() {
+                    parameters = match &callee {
+                        Value::LoxFunction(function) => {
+                            function.order_arguments(parameters, &names, &paren)?
+                        }
+                        _ => {
+                            return Err(LoxResult::RuntimeError {
+                                token: paren,
+                                message: "Only lox functions take named arguments.".to_string(),
+                            })
+                        }
+                    };
+                }
 
-                let function: Box<dyn LoxCallable>;
-                function = Box::new(callee);
+                let function: Box<dyn LoxCallable> = Box::new(callee);
 
-                if parameters.len() != function.arity() {
+                let arity = function.arity();
+                if !arity.accepts(parameters.len()) {
                     return Err(LoxResult::RuntimeError {
                         token: paren,
-                        message: format!(
-                            "Expect {} arguments but got {}.",
-                            function.arity(),
-                            parameters.len()
-                        ),
+                        message: arity.mismatch_message(parameters.len()),
                     });
                 }
 
-                let value = function.call(self, parameters)?;
-                return Ok(value);
+                let value = self.call(function.as_ref(), &paren, parameters);
+                if let (Err(LoxResult::RuntimeError { .. }), Some(frame)) = (&value, frame) {
+                    self.error_trace.push(frame);
+                }
+                return value;
+            }
+            Expr::Get { object, name, .. } => match self.evaluate(*object)? {
+                Value::LoxInstance(instance) => {
+                    let value = LoxInstance::get(&instance, &name)?;
+                    self.call_getter(value, &name)?
+                }
+                Value::LoxClass(class) => match class.find_class_method(&name.lexeme) {
+                    Some(method) => Value::LoxFunction(method.clone()),
+                    None => {
+                        return Err(LoxResult::RuntimeError {
+                            message: format!("Undefined property '{}'.", name.lexeme),
+                            token: name,
+                        })
+                    }
+                },
+                _ => {
+                    return Err(LoxResult::RuntimeError {
+                        token: name,
+                        message: "Only instances have properties.".to_string(),
+                    })
+                }
+            },
+            Expr::Block {
+                mut statements,
+                value,
+                ..
+            } => {
+                //块的值作为最后一条语句执行，这样它和其他语句在同一个环境中，推迟的语句也在它之后执行
+                let has_value = value.is_some();
+                if let Some(value) = value {
+                    statements.push(Stmt::Expression {
+                        span: value.span(),
+                        expression: value,
+                    });
+                }
+                let value = self.execute_block(
+                    statements,
+                    Environment::new_enclosing(Rc::clone(&self.environment)),
+                )?;
+                if has_value {
+                    value
+                } else {
+                    Value::Nil
+                }
+            }
+            Expr::Lambda { params, body, .. } => Value::LoxFunction(LoxFunction::lambda(
+                params,
+                body,
+                Rc::clone(&self.environment),
+            )),
+            Expr::This { id, keyword, .. } => self.look_up_variable(id, &keyword)?,
+            Expr::Super {
+                id,
+                keyword,
+                method,
+                ..
+            } => {
+                let superclass = self.look_up_variable(id, &keyword)?;
+                //`this`和`super`定义在同一个环境中
+                let this = self.look_up_variable(
+                    id,
+                    &Token::new(TokenType::THIS, "this".to_string(), None, keyword.line),
+                )?;
+                match (superclass, this) {
+                    (Value::LoxClass(superclass), Value::LoxInstance(instance)) => match superclass
+                        .find_method(&method.lexeme)
+                    {
+                        Some(function) => {
+                            self.call_getter(Value::LoxFunction(function.bind(instance)), &method)?
+                        }
+                        None => {
+                            return Err(LoxResult::RuntimeError {
+                                message: format!("Undefined property '{}'.", method.lexeme),
+                                token: method,
+                            })
+                        }
+                    },
+                    _ => unreachable!(),
+                }
+            }
+            Expr::Set {
+                object,
+                name,
+                operator,
+                value,
+                ..
+            } => {
+                let instance = match self.evaluate(*object)? {
+                    Value::LoxInstance(instance) => instance,
+                    _ => {
+                        return Err(LoxResult::RuntimeError {
+                            token: name,
+                            message: "Only instances have fields.".to_string(),
+                        })
+                    }
+                };
+                //和变量的复合赋值一样，先读取原来的值，再计算右边
+                let value = match operator {
+                    Some(operator) => {
+                        let current = LoxInstance::get(&instance, &name)?;
+                        let current = self.call_getter(current, &name)?;
+                        let value = self.evaluate(*value)?;
+                        Interpreter::binary(current, &operator, value)?
+                    }
+                    None => self.evaluate(*value)?,
+                };
+                instance.borrow_mut().set(&name, value.clone());
+                value
+            }
+            Expr::List { elements, .. } => {
+                let mut values = Vec::new();
+                for element in elements {
+                    values.push(self.evaluate(element)?);
+                }
+                Value::List(Rc::new(RefCell::new(values)))
+            }
+            Expr::Index {
+                object,
+                bracket,
+                index,
+                ..
+            } => {
+                let list = Interpreter::check_list(self.evaluate(*object)?, &bracket)?;
+                let index = self.evaluate(*index)?;
+                let list = list.borrow();
+                let index = Interpreter::check_index(&bracket, &index, list.len())?;
+                list[index].clone()
+            }
+            Expr::IndexSet {
+                object,
+                bracket,
+                index,
+                operator,
+                value,
+                ..
+            } => {
+                let list = Interpreter::check_list(self.evaluate(*object)?, &bracket)?;
+                let index = self.evaluate(*index)?;
+                let value = match operator {
+                    Some(operator) => {
+                        let len = list.borrow().len();
+                        let position = Interpreter::check_index(&bracket, &index, len)?;
+                        let current = list.borrow()[position].clone();
+                        let value = self.evaluate(*value)?;
+                        Interpreter::binary(current, &operator, value)?
+                    }
+                    None => self.evaluate(*value)?,
+                };
+                //计算右边时列表的长度可能改变了，写入之前再检查一次下标
+                let len = list.borrow().len();
+                let index = Interpreter::check_index(&bracket, &index, len)?;
+                list.borrow_mut()[index] = value.clone();
+                value
             }
-
-            _ => todo!(),
         })
     }
 }
@@ -289,35 +1080,1188 @@ mod test {
 
     use super::*;
     use crate::parser::Parser;
+    use crate::resolver::Resolver;
     use crate::Scanner;
 
+    ///通过[`Interpreter::interpret`]执行`s`并返回最后一条语句的值，`s`也可以是一个没有`;`的表达式
     fn get_value(s: &str) -> Value {
+        let mut parser = Parser::new(Scanner::new(s.to_string()).scan_tokens());
+        let statements = match parser.parse_expression() {
+            Some(expression) => vec![Stmt::Expression {
+                span: expression.span(),
+                expression: Box::new(expression),
+            }],
+            None => parser.parse().unwrap(),
+        };
         let mut interpreter = Interpreter::new();
+        Resolver::new(&mut interpreter)
+            .resolve(&statements)
+            .unwrap();
+        interpreter.interpret(statements).unwrap()
+    }
+
+    ///逐条执行一段程序，返回第一个错误
+    fn run(interpreter: &mut Interpreter, s: &str) -> Result<Value, LoxResult> {
+        let statements = Parser::new(Scanner::new(s.to_string()).scan_tokens())
+            .parse()
+            .unwrap();
+        Resolver::new(interpreter).resolve(&statements).unwrap();
+        for stmt in statements {
+            interpreter.execute(stmt)?;
+        }
+        Ok(Value::Nil)
+    }
+
+    ///可以交给[`Interpreter::set_output`]的缓冲区，测试中保留一份克隆来读取输出
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl SharedBuffer {
+        fn contents(&self) -> String {
+            String::from_utf8(self.0.borrow().clone()).unwrap()
+        }
+    }
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn get_global(interpreter: &Interpreter, name: &str) -> Value {
         interpreter
-            .evaluate(
-                Parser::new(Scanner::new(s.to_string()).scan_tokens())
-                    .expression()
-                    .unwrap(),
-            )
+            .globals
+            .borrow()
+            .get(&Token::new(
+                TokenType::IDENTIFIER,
+                name.to_string(),
+                None,
+                1,
+            ))
             .unwrap()
     }
 
     #[test]
-    fn test_eval_variable() {
-        assert_eq!(get_value("var a = 1;\nprint a;"), Value::Number(1.0));
+    fn test_defer_runs_in_reverse_order_on_block_exit() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "var log = \"\";\n{\n defer log = log + \"a\";\n defer log = log + \"b\";\n log = log + \"c\";\n}",
+        )
+        .unwrap();
+        assert_eq!(
+            get_global(&interpreter, "log"),
+            Value::String("cba".to_string())
+        );
     }
 
     #[test]
-    fn test_eval_equal_not_equal() {
-        assert_eq!(get_value("\"hello\" == \"hello\""), Value::Boolean(true));
-        assert_eq!(get_value("\"hello\" == \"hello!\""), Value::Boolean(false));
+    fn test_defer_error_does_not_replace_return_value() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "fun f() {\n defer missing;\n return 1;\n}\nvar r = f();",
+        )
+        .unwrap();
+        assert_eq!(get_global(&interpreter, "r"), Value::Number(1.0));
     }
 
     #[test]
-    fn test_eval_complex_expression() {
-        assert_eq!(get_value("1+2 * (8/4)"), Value::Number(5.0));
-    }
-
+    fn test_defer_error_on_normal_exit() {
+        let mut interpreter = Interpreter::new();
+        match run(&mut interpreter, "{\n defer missing;\n}") {
+            Err(LoxResult::RuntimeError { token, message }) => {
+                assert_eq!(token.line, 2);
+                assert_eq!(message, "Undefined variable 'missing'.");
+            }
+            other => panic!("expected a runtime error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_chained_assignment_assigns_every_target() {
+        let mut interpreter = Interpreter::new();
+        run(&mut interpreter, "var a;\nvar b;\nvar c;\na = b = c = 1;").unwrap();
+        for name in ["a", "b", "c"] {
+            assert_eq!(get_global(&interpreter, name), Value::Number(1.0));
+        }
+    }
+
+    #[test]
+    fn test_chained_assignment_evaluates_value_once() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "var count = 0;\nfun next() { count = count + 1; }\nvar a;\nvar b;\na = b = next();",
+        )
+        .unwrap();
+        assert_eq!(get_global(&interpreter, "count"), Value::Number(1.0));
+    }
+
+    #[test]
+    fn test_break_exits_loop() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "var i = 0;\nwhile (true) {\n  if (i == 3) break;\n  i = i + 1;\n}",
+        )
+        .unwrap();
+        assert_eq!(get_global(&interpreter, "i"), Value::Number(3.0));
+    }
+
+    #[test]
+    fn test_continue_runs_for_increment() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "var sum = 0;\nvar steps = 0;\nfor (var i = 0; i < 5; i = i + 1) {\n  steps = steps + 1;\n  if (i == 2) continue;\n  sum = sum + i;\n}",
+        )
+        .unwrap();
+        assert_eq!(get_global(&interpreter, "steps"), Value::Number(5.0));
+        assert_eq!(get_global(&interpreter, "sum"), Value::Number(8.0));
+    }
+
+    #[test]
+    fn test_break_and_continue_run_deferred_statements() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "var log = \"\";\nfor (var i = 0; i < 3; i = i + 1) {\n  defer log = log + \";\";\n  if (i == 0) continue;\n  if (i == 2) break;\n  log = log + \"x\";\n}",
+        )
+        .unwrap();
+        assert_eq!(
+            get_global(&interpreter, "log"),
+            Value::String(";x;;".to_string())
+        );
+    }
+
+    #[test]
+    fn test_condition_truthiness() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "var taken = \"\";\nif (nil) taken = taken + \"nil\";\nif (0) taken = taken + \"0\";\nif (\"hi\") taken = taken + \"hi\";\nif (\"\") taken = taken + \"empty\"; else taken = taken + \"!\";\nif (false) taken = taken + \"false\"; else taken = taken + \"else\";",
+        )
+        .unwrap();
+        assert_eq!(
+            get_global(&interpreter, "taken"),
+            Value::String("0hiemptyelse".to_string())
+        );
+    }
+
+    #[test]
+    fn test_while_condition_truthiness() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "var n = 3;\nvar x = \"go\";\nwhile (x) {\n  n = n - 1;\n  if (n == 0) x = nil;\n}",
+        )
+        .unwrap();
+        assert_eq!(get_global(&interpreter, "n"), Value::Number(0.0));
+    }
+
+    #[test]
+    fn test_class_declaration() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "class Bacon {\n  eat() { print \"crunch\"; }\n}",
+        )
+        .unwrap();
+        match get_global(&interpreter, "Bacon") {
+            Value::LoxClass(class) => {
+                assert_eq!(class.to_string(), "<class Bacon>");
+                assert!(class.find_method("eat").is_some());
+            }
+            other => panic!("expected a class, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_instance_fields() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "class Point {}\nvar p = Point();\np.x = 1;\np.y = p.x + 1;\nvar y = p.y;",
+        )
+        .unwrap();
+        assert_eq!(get_global(&interpreter, "y"), Value::Number(2.0));
+        assert_eq!(
+            get_global(&interpreter, "p").to_string(),
+            "<Point instance>"
+        );
+    }
+
+    #[test]
+    fn test_compound_assignment() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "var a = 1;\na += 2;\nvar b = a *= 4;\na -= 2;\na /= 5;\nvar s = \"x\";\ns += 1;",
+        )
+        .unwrap();
+        assert_eq!(get_global(&interpreter, "a"), Value::Number(2.0));
+        assert_eq!(get_global(&interpreter, "b"), Value::Number(12.0));
+        assert_eq!(
+            get_global(&interpreter, "s"),
+            Value::String("x1".to_string())
+        );
+
+        //`counter.count`和`list[next()]`中的对象和下标都只计算一次
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "class Counter {}\nvar counter = Counter();\ncounter.count = 0;\n\
+             var calls = 0;\nfun get() { calls += 1; return counter; }\n\
+             get().count += 1;\nget().count += 1;\nvar count = counter.count;\n\
+             var list = [10, 20];\nvar i = 0;\nfun next() { i += 1; return i - 1; }\n\
+             list[next()] += 5;",
+        )
+        .unwrap();
+        assert_eq!(get_global(&interpreter, "count"), Value::Number(2.0));
+        assert_eq!(get_global(&interpreter, "calls"), Value::Number(2.0));
+        assert_eq!(get_global(&interpreter, "list").to_string(), "[15, 20]");
+        assert_eq!(get_global(&interpreter, "i"), Value::Number(1.0));
+
+        //三种目标都先读取原来的值，再计算有副作用的右边
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "class C {}\nvar c = C();\nc.x = 1;\nvar a = 1;\nvar l = [1];\n\
+             fun f() { a = 10; return 1; }\nfun g() { c.x = 10; return 1; }\n\
+             fun h() { l[0] = 10; return 1; }\n\
+             a += f();\nc.x += g();\nl[0] += h();\nvar x = c.x;\nvar e = l[0];",
+        )
+        .unwrap();
+        for name in ["a", "x", "e"] {
+            assert_eq!(
+                get_global(&interpreter, name),
+                Value::Number(2.0),
+                "{}",
+                name
+            );
+        }
+
+        match run(&mut Interpreter::new(), "var a = nil;\na -= 1;") {
+            Err(LoxResult::RuntimeError { token, message }) => {
+                assert_eq!((token.lexeme.as_str(), token.line), ("-", 2));
+                assert_eq!(message, "Operands must be numbers.");
+            }
+            other => panic!("expected a runtime error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_instance_undefined_property() {
+        let mut interpreter = Interpreter::new();
+        match run(&mut interpreter, "class Point {}\nPoint().x;") {
+            Err(LoxResult::RuntimeError { message, .. }) => {
+                assert_eq!(message, "Undefined property 'x'.")
+            }
+            other => panic!("expected a runtime error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_this_in_methods() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "class Counter {\n  inc() { this.x = this.x + 1; }\n  get() { return this.x; }\n}\nvar c = Counter();\nc.x = 0;\nc.inc();\nvar first = c.get();\nc.inc();\nvar second = c.get();",
+        )
+        .unwrap();
+        assert_eq!(get_global(&interpreter, "first"), Value::Number(1.0));
+        assert_eq!(get_global(&interpreter, "second"), Value::Number(2.0));
+    }
+
+    #[test]
+    fn test_super_dispatches_to_superclass() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "class A {\n  name() { return \"A\" + this.tag; }\n  greet() { return \"hi\"; }\n}\nclass B < A {\n  name() { return \"B\" + super.name(); }\n}\nvar b = B();\nb.tag = \"!\";\nvar name = b.name();\nvar greeting = b.greet();",
+        )
+        .unwrap();
+        assert_eq!(
+            get_global(&interpreter, "name"),
+            Value::String("BA!".to_string())
+        );
+        assert_eq!(
+            get_global(&interpreter, "greeting"),
+            Value::String("hi".to_string())
+        );
+    }
+
+    #[test]
+    fn test_init_returns_this() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "class A {\n  init(x) {\n    this.x = x;\n    if (x < 0) return;\n    this.y = 1;\n  }\n}\nvar a = A(0);\nvar b = a.init(-1);\nvar same = a == b;\nvar init = a.init;\nvar c = init(2);\nvar y = c.y;",
+        )
+        .unwrap();
+        assert_eq!(get_global(&interpreter, "same"), Value::Boolean(true));
+        assert_eq!(get_global(&interpreter, "c"), get_global(&interpreter, "a"));
+        assert_eq!(get_global(&interpreter, "y"), Value::Number(1.0));
+    }
+
+    #[test]
+    fn test_init_constructor() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "class Point {\n  init(x, y) {\n    this.x = x;\n    this.y = y;\n  }\n  sum() { return this.x + this.y; }\n}\nclass Point3 < Point {}\nclass Empty {}\nvar p = Point(1, 2);\nvar sum = p.sum();\nvar q = Point3(3, 4).sum();\nvar e = Empty();",
+        )
+        .unwrap();
+        assert_eq!(get_global(&interpreter, "sum"), Value::Number(3.0));
+        assert_eq!(get_global(&interpreter, "q"), Value::Number(7.0));
+        assert_eq!(
+            get_global(&interpreter, "e").to_string(),
+            "<Empty instance>"
+        );
+
+        for (source, expected) in [
+            ("Point(1);", "Expect 2 arguments but got 1."),
+            ("Empty(1);", "Expect 0 arguments but got 1."),
+        ] {
+            match run(&mut interpreter, source) {
+                Err(LoxResult::RuntimeError { message, .. }) => assert_eq!(message, expected),
+                other => panic!("expected a runtime error, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_getters() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "class Rect {\n  area { return this.w * this.h; }\n  scale(n) { this.w = this.w * n; }\n}\nclass Square < Rect {\n  area { return super.area + 1; }\n}\nvar r = Rect();\nr.w = 2;\nr.h = 3;\nvar area = r.area;\nvar scale = r.scale;\nscale(2);\nvar scaled = r.area;\nvar s = Square();\ns.w = 2;\ns.h = 2;\nvar square = s.area;",
+        )
+        .unwrap();
+        assert_eq!(get_global(&interpreter, "area"), Value::Number(6.0));
+        assert_eq!(get_global(&interpreter, "scale").to_string(), "<fn scale>");
+        assert_eq!(get_global(&interpreter, "scaled"), Value::Number(12.0));
+        assert_eq!(get_global(&interpreter, "square"), Value::Number(5.0));
+    }
+
+    #[test]
+    fn test_static_methods() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "class Point {\n  class make(x, y) {\n    var p = Point();\n    p.x = x;\n    p.y = y;\n    return p;\n  }\n  sum() { return this.x + this.y; }\n}\nclass Point3 < Point {}\nvar a = Point.make(1, 2).sum();\nvar b = Point3.make(3, 4).sum();",
+        )
+        .unwrap();
+        assert_eq!(get_global(&interpreter, "a"), Value::Number(3.0));
+        assert_eq!(get_global(&interpreter, "b"), Value::Number(7.0));
+
+        match run(&mut interpreter, "Point.sum();") {
+            Err(LoxResult::RuntimeError { token, message }) => {
+                assert_eq!(token.lexeme, "sum");
+                assert_eq!(message, "Undefined property 'sum'.");
+            }
+            other => panic!("expected a runtime error, got {:?}", other),
+        }
+        match run(&mut interpreter, "Point.make(1, 2).make;") {
+            Err(LoxResult::RuntimeError { message, .. }) => {
+                assert_eq!(message, "Undefined property 'make'.")
+            }
+            other => panic!("expected a runtime error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_superclass_must_be_a_class() {
+        let mut interpreter = Interpreter::new();
+        match run(&mut interpreter, "var A = 1;\nclass B < A {}") {
+            Err(LoxResult::RuntimeError { token, message }) => {
+                assert_eq!(token.lexeme, "A");
+                assert_eq!(message, "Superclass must be a class.");
+            }
+            other => panic!("expected a runtime error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_block_scope() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "var a = \"outer\";\nvar seen;\n{\n  var b = \"inner\";\n  var a = \"shadow\";\n  seen = b;\n}\n{\n  seen = seen + a;\n}",
+        )
+        .unwrap();
+        assert_eq!(
+            get_global(&interpreter, "a"),
+            Value::String("outer".to_string())
+        );
+        assert_eq!(
+            get_global(&interpreter, "seen"),
+            Value::String("innerouter".to_string())
+        );
+        assert!(interpreter
+            .globals
+            .borrow()
+            .get(&Token::new(TokenType::IDENTIFIER, "b".to_string(), None, 1))
+            .is_err());
+    }
+
+    #[test]
+    fn test_block_scope_restored_after_error() {
+        let mut interpreter = Interpreter::new();
+        assert!(run(&mut interpreter, "{\n  var b = 1;\n  missing;\n}").is_err());
+        run(&mut interpreter, "var b = 2;").unwrap();
+        assert_eq!(get_global(&interpreter, "b"), Value::Number(2.0));
+        assert!(Rc::ptr_eq(&interpreter.environment, &interpreter.globals));
+    }
+
+    #[test]
+    fn test_closures_capture_loop_variable() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "var first;\nvar second;\nfor (var i = 0; i < 2; i = i + 1) {\n  var j = i;\n  fun show() { return j; }\n  if (i == 0) first = show; else second = show;\n}\nvar a = first();\nvar b = second();",
+        )
+        .unwrap();
+        assert_eq!(get_global(&interpreter, "a"), Value::Number(0.0));
+        assert_eq!(get_global(&interpreter, "b"), Value::Number(1.0));
+    }
+
+    #[test]
+    fn test_closure_keeps_captured_state() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "fun makeCounter() {\n  var i = 0;\n  fun count() {\n    i = i + 1;\n    return i;\n  }\n  return count;\n}\nvar counter = makeCounter();\nvar a = counter();\nvar b = counter();\nvar c = counter();\nvar other = makeCounter()();",
+        )
+        .unwrap();
+        assert_eq!(get_global(&interpreter, "a"), Value::Number(1.0));
+        assert_eq!(get_global(&interpreter, "b"), Value::Number(2.0));
+        assert_eq!(get_global(&interpreter, "c"), Value::Number(3.0));
+        assert_eq!(get_global(&interpreter, "other"), Value::Number(1.0));
+    }
+
+    #[test]
+    fn test_recursion_through_closures() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "fun isEven(n) {\n  if (n == 0) return true;\n  return isOdd(n - 1);\n}\nfun isOdd(n) {\n  if (n == 0) return false;\n  return isEven(n - 1);\n}\nvar a = isEven(10);\nvar b = isOdd(7);\nvar c = isEven(7);",
+        )
+        .unwrap();
+        assert_eq!(get_global(&interpreter, "a"), Value::Boolean(true));
+        assert_eq!(get_global(&interpreter, "b"), Value::Boolean(true));
+        assert_eq!(get_global(&interpreter, "c"), Value::Boolean(false));
+
+        run(
+            &mut interpreter,
+            "fun outer(limit) {\n  var base = 100;\n  fun fib(n) {\n    if (n < 2) return base + n;\n    return fib(n - 1) + fib(n - 2) - base;\n  }\n  return fib(limit);\n}\nvar d = outer(10);",
+        )
+        .unwrap();
+        assert_eq!(get_global(&interpreter, "d"), Value::Number(155.0));
+    }
+
+    #[test]
+    fn test_lambda_as_argument() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "fun apply(f, x) { return f(x); }\nvar factor = 3;\nvar a = apply(fun (x) { return x * 2; }, 5);\nvar b = apply(fun (x) { return x * factor; }, 5);\nvar show = fun () {};",
+        )
+        .unwrap();
+        assert_eq!(get_global(&interpreter, "a"), Value::Number(10.0));
+        assert_eq!(get_global(&interpreter, "b"), Value::Number(15.0));
+        assert_eq!(get_global(&interpreter, "show").to_string(), "<fn>");
+
+        match run(&mut interpreter, "apply(fun (x, y) { return x; }, 1);") {
+            Err(LoxResult::RuntimeError { message, .. }) => {
+                assert_eq!(message, "Expect 2 arguments but got 1.")
+            }
+            other => panic!("expected a runtime error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_resolved_variable_ignores_later_shadowing() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "var a = \"global\";\nvar seen;\n{\n  fun show() { return a; }\n  var first = show();\n  var a = \"block\";\n  seen = first + show();\n}",
+        )
+        .unwrap();
+        assert_eq!(
+            get_global(&interpreter, "seen"),
+            Value::String("globalglobal".to_string())
+        );
+    }
+
+    #[test]
+    fn test_clock() {
+        let mut interpreter = Interpreter::new();
+        run(&mut interpreter, "var now = clock();").unwrap();
+        assert!(matches!(get_global(&interpreter, "now"), Value::Number(n) if n > 0.0));
+
+        match run(&mut interpreter, "clock(1);") {
+            Err(LoxResult::RuntimeError { message, .. }) => {
+                assert_eq!(message, "Expect 0 arguments but got 1.")
+            }
+            other => panic!("expected a runtime error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_len() {
+        assert_eq!(get_value("len(\"\")"), Value::Number(0.0));
+        assert_eq!(get_value("len(\"hello\")"), Value::Number(5.0));
+        assert_eq!(get_value("len([1, 2, 3])"), Value::Number(3.0));
+
+        let mut interpreter = Interpreter::new();
+        for (name, s) in [("chinese", "你好"), ("mixed", "héllo🦀")] {
+            interpreter
+                .globals
+                .borrow_mut()
+                .define(name.to_string(), Value::String(s.to_string()));
+        }
+        run(
+            &mut interpreter,
+            "var a = len(chinese);\nvar b = len(mixed);",
+        )
+        .unwrap();
+        assert_eq!(get_global(&interpreter, "a"), Value::Number(2.0));
+        assert_eq!(get_global(&interpreter, "b"), Value::Number(6.0));
+
+        match run(&mut interpreter, "len(1);") {
+            Err(LoxResult::RuntimeError { token, message }) => {
+                assert_eq!(token.lexeme, ")");
+                assert_eq!(message, "Can only get the length of a string or list.")
+            }
+            other => panic!("expected a runtime error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_trace_does_not_change_results() {
+        let mut interpreter = Interpreter::with_trace(true);
+        run(
+            &mut interpreter,
+            "var a = 1;\nfun f(x) { { return x * 2; } }\nvar b = f(a);\nwhile (a < 3) a = a + 1;",
+        )
+        .unwrap();
+        assert_eq!(get_global(&interpreter, "a"), Value::Number(3.0));
+        assert_eq!(get_global(&interpreter, "b"), Value::Number(2.0));
+        assert_eq!(interpreter.trace_depth, 0);
+    }
+
+    #[test]
+    fn test_typeof() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "class A { m() {} }\nfun f() {}\nvar types = [typeof(1), typeof(\"s\"), typeof(true), typeof(nil), typeof(f), typeof(fun () {}), typeof(clock), typeof(A), typeof(A()), typeof(A().m), typeof([])];",
+        )
+        .unwrap();
+        assert_eq!(
+            get_global(&interpreter, "types").to_string(),
+            "[\"number\", \"string\", \"boolean\", \"nil\", \"function\", \"function\", \"function\", \"class\", \"instance\", \"function\", \"list\"]"
+        );
+        assert_eq!(
+            get_value("typeof(typeof(1))"),
+            Value::String("string".to_string())
+        );
+    }
+
+    #[test]
+    fn test_contains_and_index_of() {
+        assert_eq!(
+            get_value("contains(\"hello\", \"ell\")"),
+            Value::Boolean(true)
+        );
+        assert_eq!(
+            get_value("contains(\"hello\", \"le\")"),
+            Value::Boolean(false)
+        );
+        assert_eq!(get_value("index_of(\"héllo\", \"l\")"), Value::Number(2.0));
+        assert_eq!(get_value("index_of(\"hello\", \"\")"), Value::Number(0.0));
+        assert_eq!(get_value("index_of([1, 2, 3], 2)"), Value::Number(1.0));
+        assert_eq!(get_value("index_of([1, 2, 3], \"2\")"), Value::Number(-1.0));
+        assert_eq!(
+            get_value("contains([nil, \"a\"], \"a\")"),
+            Value::Boolean(true)
+        );
+        assert_eq!(get_value("contains([], nil)"), Value::Boolean(false));
+
+        let mut interpreter = Interpreter::new();
+        for (src, expected) in [
+            ("contains(1, 1);", "Can only search a string or list."),
+            (
+                "index_of(\"a\", 1);",
+                "Can only search a string for a string.",
+            ),
+        ] {
+            match run(&mut interpreter, src) {
+                Err(LoxResult::RuntimeError { message, .. }) => assert_eq!(message, expected),
+                other => panic!("expected a runtime error for {}, got {:?}", src, other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_json_natives() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "var data = [1, \"two\", [true, nil], []];\nvar json = json_stringify(data);\nvar back = json_parse(json);\nvar same = json_stringify(back) == json;",
+        )
+        .unwrap();
+        assert_eq!(
+            get_global(&interpreter, "json"),
+            Value::String("[1,\"two\",[true,null],[]]".to_string())
+        );
+        assert_eq!(
+            get_global(&interpreter, "back").to_string(),
+            "[1, \"two\", [true, nil], []]"
+        );
+        assert_eq!(get_global(&interpreter, "same"), Value::Boolean(true));
+
+        for (src, expected) in [
+            ("json_stringify(clock);", "Can't convert function to JSON."),
+            (
+                "class A {}\njson_stringify([A()]);",
+                "Can't convert instance to JSON.",
+            ),
+            ("json_parse(1);", "Can only parse a string as JSON."),
+            (
+                "json_parse(\"[1\");",
+                "Invalid JSON: Expect ',' or ']' in array at position 2.",
+            ),
+        ] {
+            match run(&mut interpreter, src) {
+                Err(LoxResult::RuntimeError { message, .. }) => assert_eq!(message, expected),
+                other => panic!("expected a runtime error for {}, got {:?}", src, other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_math_natives() {
+        assert_eq!(get_value("pow(2, 10)"), Value::Number(1024.0));
+        assert_eq!(get_value("sqrt(9)"), Value::Number(3.0));
+        assert_eq!(get_value("abs(-3)"), Value::Number(3.0));
+        assert_eq!(get_value("floor(-1.5)"), Value::Number(-2.0));
+        assert_eq!(get_value("ceil(1.2)"), Value::Number(2.0));
+        assert_eq!(get_value("round(2.5)"), Value::Number(3.0));
+        assert_eq!(get_value("sign(-3)"), Value::Number(-1.0));
+        assert_eq!(get_value("sign(0)"), Value::Number(0.0));
+        assert_eq!(get_value("sign(2.5)"), Value::Number(1.0));
+        assert_eq!(get_value("gcd(12, 18)"), Value::Number(6.0));
+        assert_eq!(get_value("gcd(-4, 0)"), Value::Number(4.0));
+        assert_eq!(get_value("lcm(4, 6)"), Value::Number(12.0));
+        assert_eq!(get_value("lcm(-4, 6)"), Value::Number(12.0));
+        assert_eq!(get_value("lcm(0, 6)"), Value::Number(0.0));
+
+        let mut interpreter = Interpreter::new();
+        match run(&mut interpreter, "pow(2, \"3\");") {
+            Err(LoxResult::RuntimeError { message, .. }) => {
+                assert_eq!(message, "Argument 2 must be a number.")
+            }
+            other => panic!("expected a runtime error, got {:?}", other),
+        }
+        match run(&mut interpreter, "sqrt(nil);") {
+            Err(LoxResult::RuntimeError { message, .. }) => {
+                assert_eq!(message, "Argument 1 must be a number.")
+            }
+            other => panic!("expected a runtime error, got {:?}", other),
+        }
+        match run(&mut interpreter, "gcd(4, 1.5);") {
+            Err(LoxResult::RuntimeError { message, .. }) => {
+                assert_eq!(message, "Argument 2 must be an integer.")
+            }
+            other => panic!("expected a runtime error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_random_natives() {
+        let sequence = |seed| {
+            let mut interpreter = Interpreter::new();
+            interpreter.set_seed(seed);
+            run(
+                &mut interpreter,
+                "var a = [];\nfor (var i = 0; i < 20; i = i + 1) { push(a, rand()); push(a, rand_int(-2, 2)); }",
+            )
+            .unwrap();
+            match get_global(&interpreter, "a") {
+                Value::List(list) => list.borrow().clone(),
+                other => panic!("expected a list, got {:?}", other),
+            }
+        };
+        let values = sequence(7);
+        assert_eq!(values, sequence(7));
+        assert_ne!(values, sequence(8));
+        for pair in values.chunks(2) {
+            match pair {
+                [Value::Number(r), Value::Number(n)] => {
+                    assert!((0.0..1.0).contains(r), "{}", r);
+                    assert!((-2.0..=2.0).contains(n) && n.fract() == 0.0, "{}", n);
+                }
+                other => panic!("unexpected {:?}", other),
+            }
+        }
+        assert_eq!(get_value("rand_int(3, 3)"), Value::Number(3.0));
+
+        let mut interpreter = Interpreter::new();
+        for (source, expected) in [
+            (
+                "rand_int(2, 1);",
+                "Lower bound must not be greater than upper bound.",
+            ),
+            ("rand_int(0, 1.5);", "Bounds must be integers."),
+            ("rand_int(\"0\", 1);", "Argument 1 must be a number."),
+        ] {
+            match run(&mut interpreter, source) {
+                Err(LoxResult::RuntimeError { message, .. }) => assert_eq!(message, expected),
+                other => panic!("expected a runtime error, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_print_natives() {
+        let output = SharedBuffer::default();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_output(output.clone());
+        run(
+            &mut interpreter,
+            "print_raw(\"| \");\nprint_raw(1);\nprint_raw(\" |\");\nprintln();\nprintln(\"a\", [2], nil);\nprint_raw(\"x\");",
+        )
+        .unwrap();
+        assert_eq!(output.contents(), "| 1 |\na [2] nil\nx");
+        assert_eq!(get_value("print_raw(\"\")"), Value::Nil);
+    }
+
+    #[test]
+    fn test_special_numbers() {
+        let output = SharedBuffer::default();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_output(output.clone());
+        run(
+            &mut interpreter,
+            "var nan = sqrt(-1);\nvar inf = pow(10, 400);\nprint nan, inf, -inf, [nan];\nprint nan == nan, nan != nan, inf == inf, -inf < inf;",
+        )
+        .unwrap();
+        assert_eq!(
+            output.contents(),
+            "NaN Infinity -Infinity [NaN]\nfalse true true true\n"
+        );
+    }
+
+    #[test]
+    fn test_named_arguments() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "fun point(x, y, z) { return [x, y, z]; }\nvar a = point(z: 3, x: 1, y: 2);\nvar b = point(1, z: 3, y: 2,);\nclass P { at(x, y) { return x - y; } }\nvar c = P().at(y: 1, x: 5);",
+        )
+        .unwrap();
+        assert_eq!(get_global(&interpreter, "a").to_string(), "[1, 2, 3]");
+        assert_eq!(get_global(&interpreter, "b").to_string(), "[1, 2, 3]");
+        assert_eq!(get_global(&interpreter, "c"), Value::Number(4.0));
+
+        for (src, expected) in [
+            ("point(1, 2, w: 3);", "Unknown parameter 'w'."),
+            (
+                "point(1, 2, x: 3);",
+                "Argument 'x' is given more than once.",
+            ),
+            (
+                "point(y: 1, y: 2, z: 3);",
+                "Argument 'y' is given more than once.",
+            ),
+            ("point(1, z: 3);", "Missing argument 'y'."),
+            ("len(s: \"a\");", "Only lox functions take named arguments."),
+        ] {
+            match run(&mut interpreter, src) {
+                Err(LoxResult::RuntimeError { message, .. }) => assert_eq!(message, expected),
+                other => panic!("expected a runtime error for {}, got {:?}", src, other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_multiple_variable_declaration() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "var a = 1, b = a + 1;\nvar c, d = [a, b], e = d;\n{ var a = 10, f = a; b = f; }\nvar sum = 0;\nfor (var i = 0, j = 3; i < j; i = i + 1) sum = sum + i;",
+        )
+        .unwrap();
+        assert_eq!(get_global(&interpreter, "b"), Value::Number(10.0));
+        assert_eq!(get_global(&interpreter, "c"), Value::Uninitialized);
+        assert_eq!(get_global(&interpreter, "e").to_string(), "[1, 2]");
+        assert_eq!(get_global(&interpreter, "sum"), Value::Number(3.0));
+
+        let mut interpreter = Interpreter::new();
+        run(&mut interpreter, "var a = 1, b = a + 1;").unwrap();
+        assert_eq!(get_global(&interpreter, "b"), Value::Number(2.0));
+    }
+
+    #[test]
+    fn test_block_expression() {
+        let output = SharedBuffer::default();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_output(output.clone());
+        run(
+            &mut interpreter,
+            "var a = 1;\nvar x = { var a = 10; print a; a + 1 };\nvar y = { a = a + 1; };\nvar z = { defer print \"deferred\"; { print \"inner\"; } [a, x] };\nfun f(n) { return { var m = n * 2; m + 1 }; }\nvar w = f(3) + { 1 };",
+        )
+        .unwrap();
+        assert_eq!(get_global(&interpreter, "x"), Value::Number(11.0));
+        assert_eq!(get_global(&interpreter, "y"), Value::Nil);
+        assert_eq!(get_global(&interpreter, "a"), Value::Number(2.0));
+        assert_eq!(get_global(&interpreter, "z").to_string(), "[2, 11]");
+        assert_eq!(get_global(&interpreter, "w"), Value::Number(8.0));
+        assert_eq!(output.contents(), "10\ninner\ndeferred\n");
+    }
+
+    #[test]
+    fn test_run_program() {
+        let outcome = |s: &str| {
+            let statements = Parser::new(Scanner::new(s.to_string()).scan_tokens())
+                .parse()
+                .unwrap();
+            let mut interpreter = Interpreter::new();
+            interpreter.set_output(std::io::sink());
+            Resolver::new(&mut interpreter)
+                .resolve(&statements)
+                .unwrap();
+            interpreter.run_program(statements)
+        };
+
+        assert!(matches!(
+            outcome("var a = 1;\nprint a;"),
+            RunOutcome::Completed
+        ));
+        match outcome("print 1;\n-nil;\nprint 2;") {
+            RunOutcome::RuntimeError(LoxResult::RuntimeError { message, .. }) => {
+                assert_eq!(message, "Operand must be a number.")
+            }
+            other => panic!("expected a runtime error, got {:?}", other),
+        }
+        //`try`接不住`exit`
+        let exited = outcome("fun f() { exit(3); }\ntry { f(); } catch (e) { print e; }\nexit(4);");
+        assert!(matches!(exited, RunOutcome::Exited(3)));
+        assert_eq!(exited.exit_code(), 3);
+        assert!(matches!(outcome("exit(0);\n-nil;"), RunOutcome::Exited(0)));
+        assert_eq!(outcome("exit(1.5);").exit_code(), 70);
+    }
+
+    #[test]
+    fn test_stack_overflow() {
+        std::thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn(unbounded_recursion)
+            .unwrap()
+            .join()
+            .unwrap();
+
+        //错误可以被接住，之后还能继续调用
+        let mut interpreter = Interpreter::new();
+        interpreter.set_max_call_depth(10);
+        run(
+            &mut interpreter,
+            "fun f(n) { return f(n + 1); }\nvar e;\ntry { f(0); } catch (error) { e = error; }\nfun g(n) { if (n == 0) return 0; return g(n - 1); }\nvar r = g(9);",
+        )
+        .unwrap();
+        assert_eq!(
+            get_global(&interpreter, "e"),
+            Value::String("Stack overflow.".to_string())
+        );
+        assert_eq!(get_global(&interpreter, "r"), Value::Number(0.0));
+    }
+
+    #[test]
+    fn test_recursive_construction_overflows() {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_max_call_depth(10);
+        match run(
+            &mut interpreter,
+            "class A {\n  init() {\n    A();\n  }\n}\nA();",
+        ) {
+            Err(LoxResult::RuntimeError { token, message }) => {
+                assert_eq!((token.lexeme.as_str(), token.line), (")", 3));
+                assert_eq!(message, "Stack overflow.");
+            }
+            other => panic!("expected a runtime error, got {:?}", other),
+        }
+        assert_eq!(interpreter.call_depth, 0);
+    }
+
+    ///默认的层数限制下无限递归产生运行时错误，需要在栈足够大的线程中执行
+    fn unbounded_recursion() {
+        let mut interpreter = Interpreter::new();
+        match run(
+            &mut interpreter,
+            "var depth = 0;\nfun f() {\n  depth = depth + 1;\n  f();\n}\nf();",
+        ) {
+            Err(LoxResult::RuntimeError { token, message }) => {
+                assert_eq!((token.lexeme.as_str(), token.line), (")", 4));
+                assert_eq!(message, "Stack overflow.");
+            }
+            other => panic!("expected a runtime error, got {:?}", other),
+        }
+        assert_eq!(
+            get_global(&interpreter, "depth"),
+            Value::Number(DEFAULT_MAX_CALL_DEPTH as f64)
+        );
+        assert_eq!(interpreter.call_depth, 0);
+    }
+
+    #[test]
+    fn test_str_and_num() {
+        assert_eq!(get_value("str(3.5)"), Value::String("3.5".to_string()));
+        assert_eq!(get_value("str(2)"), Value::String("2".to_string()));
+        assert_eq!(get_value("str(nil)"), Value::String("nil".to_string()));
+        assert_eq!(
+            get_value("str([1, \"a\"])"),
+            Value::String("[1, \"a\"]".to_string())
+        );
+        assert_eq!(get_value("num(\"42\")"), Value::Number(42.0));
+        assert_eq!(get_value("num(\" -1.5 \")"), Value::Number(-1.5));
+        assert_eq!(get_value("num(str(0.25))"), Value::Number(0.25));
+
+        for (src, expected) in [
+            ("num(\"abc\");", "Can't convert 'abc' to a number."),
+            ("num(\"\");", "Can't convert '' to a number."),
+            ("num(\"inf\");", "Can't convert 'inf' to a number."),
+            ("num(1);", "Can only convert a string to a number."),
+        ] {
+            let mut interpreter = Interpreter::new();
+            match run(&mut interpreter, src) {
+                Err(LoxResult::RuntimeError { token, message }) => {
+                    assert_eq!(token.lexeme, ")");
+                    assert_eq!(message, expected);
+                }
+                other => panic!("expected a runtime error for {}, got {:?}", src, other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_print_to_output() {
+        let output = SharedBuffer::default();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_output(output.clone());
+        run(&mut interpreter, "print 1; print 2;").unwrap();
+        assert_eq!(output.contents(), "1\n2\n");
+
+        run(
+            &mut interpreter,
+            "fun f(s) { print s + \"!\"; }\nf(\"hi\");\nprint [1, \"a\"];",
+        )
+        .unwrap();
+        assert_eq!(output.contents(), "1\n2\nhi!\n[1, \"a\"]\n");
+    }
+
+    #[test]
+    fn test_print_arguments() {
+        let output = SharedBuffer::default();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_output(output.clone());
+        run(&mut interpreter, "print;").unwrap();
+        assert_eq!(output.contents(), "\n");
+        run(&mut interpreter, "print \"a\";").unwrap();
+        assert_eq!(output.contents(), "\na\n");
+        //`,`分隔的是参数，而不是逗号表达式
+        run(&mut interpreter, "var x = 1;\nprint x, x = 2, [x];").unwrap();
+        assert_eq!(output.contents(), "\na\n1 2 [2]\n");
+    }
+
+    #[test]
+    fn test_try_catch() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "var a = \"none\";\nvar after = false;\ntry {\n  var x = 1 / 0;\n  a = \"not caught\";\n} catch (e) {\n  a = e;\n}\nafter = true;\nvar b;\ntry { b = 1; } catch (e) { b = 2; }\nvar c;\ntry { error(\"custom\"); } catch (e) { c = e; }",
+        )
+        .unwrap();
+        assert_eq!(
+            get_global(&interpreter, "a"),
+            Value::String("Division by zero.".to_string())
+        );
+        assert_eq!(get_global(&interpreter, "after"), Value::Boolean(true));
+        assert_eq!(get_global(&interpreter, "b"), Value::Number(1.0));
+        assert_eq!(
+            get_global(&interpreter, "c"),
+            Value::String("custom".to_string())
+        );
+
+        match run(&mut interpreter, "try { 1 / 0; } catch (e) { -e; }") {
+            Err(LoxResult::RuntimeError { message, .. }) => {
+                assert_eq!(message, "Operand must be a number.")
+            }
+            other => panic!("expected the catch block's error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_control_flow_escapes_try() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "fun f() {\n  try { return 1; } catch (e) { return 2; }\n  return 3;\n}\nvar a = f();\nvar i = 0;\nwhile (true) {\n  try { i = i + 1; if (i == 3) break; continue; } catch (e) {}\n}\nfun g() {\n  try { 1 / 0; } catch (e) { return e; }\n}\nvar b = g();",
+        )
+        .unwrap();
+        assert_eq!(get_global(&interpreter, "a"), Value::Number(1.0));
+        assert_eq!(get_global(&interpreter, "i"), Value::Number(3.0));
+        assert_eq!(
+            get_global(&interpreter, "b"),
+            Value::String("Division by zero.".to_string())
+        );
+    }
+
+    #[test]
+    fn test_dangling_else() {
+        for (a, b, expected) in [
+            ("true", "true", "x\n"),
+            ("true", "false", "y\n"),
+            ("false", "true", ""),
+            ("nil", "false", ""),
+            ("1", "nil", "y\n"),
+        ] {
+            let output = SharedBuffer::default();
+            let mut interpreter = Interpreter::new();
+            interpreter.set_output(output.clone());
+            let src = format!("if ({}) if ({}) print \"x\"; else print \"y\";", a, b);
+            run(&mut interpreter, &src).unwrap();
+            assert_eq!(output.contents(), expected, "{}", src);
+        }
+
+        let output = SharedBuffer::default();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_output(output.clone());
+        run(
+            &mut interpreter,
+            "for (var i = 0; i < 4; i = i + 1) {\n  if (i == 0) print \"zero\";\n  else if (i == 1) print \"one\";\n  else if (i == 2) print \"two\";\n  else print \"many\";\n}",
+        )
+        .unwrap();
+        assert_eq!(output.contents(), "zero\none\ntwo\nmany\n");
+    }
+
+    #[test]
+    fn test_read_line() {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_input(std::io::Cursor::new("Ada\r\n42\nlast"));
+        run(
+            &mut interpreter,
+            "var name = readLine();\nvar n = num(readLine()) + 1;\nvar last = readLine();\nvar end = readLine();",
+        )
+        .unwrap();
+        assert_eq!(
+            get_global(&interpreter, "name"),
+            Value::String("Ada".to_string())
+        );
+        assert_eq!(get_global(&interpreter, "n"), Value::Number(43.0));
+        assert_eq!(
+            get_global(&interpreter, "last"),
+            Value::String("last".to_string())
+        );
+        assert_eq!(get_global(&interpreter, "end"), Value::Nil);
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(
+            get_value("format(\"{} + {} = {}\", 1, 2, 3)"),
+            Value::String("1 + 2 = 3".to_string())
+        );
+        assert_eq!(
+            get_value("format(\"[{}]{}\", \"a\", [nil, 2.5])"),
+            Value::String("[a][nil, 2.5]".to_string())
+        );
+        assert_eq!(
+            get_value("format(\"plain\")"),
+            Value::String("plain".to_string())
+        );
+
+        for (src, expected) in [
+            ("format();", "Expect at least 1 arguments but got 0."),
+            ("format(1);", "Format must be a string."),
+            (
+                "format(\"{} {}\", 1);",
+                "Not enough arguments for format string.",
+            ),
+            (
+                "format(\"{}\", 1, 2);",
+                "Too many arguments for format string.",
+            ),
+        ] {
+            let mut interpreter = Interpreter::new();
+            match run(&mut interpreter, src) {
+                Err(LoxResult::RuntimeError { token, message }) => {
+                    assert_eq!(token.lexeme, ")");
+                    assert_eq!(message, expected);
+                }
+                other => panic!("expected a runtime error for {}, got {:?}", src, other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_dump_env_on_error() {
+        let dir = std::env::temp_dir().join(format!("lox-dump-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let mut interpreter = Interpreter::new();
+        interpreter.set_dump_on_error(dir.clone());
+        let source =
+            "var answer = 42;\nfun f() {\n  var local = \"here\";\n  print missing;\n}\nf();";
+        let statements = Parser::new(Scanner::new(source.to_string()).scan_tokens())
+            .parse()
+            .unwrap();
+        Resolver::new(&mut interpreter)
+            .resolve(&statements)
+            .unwrap();
+        assert!(interpreter.interpret(statements).is_err());
+
+        let files: Vec<_> = std::fs::read_dir(&dir).unwrap().collect();
+        assert_eq!(files.len(), 1);
+        let content = std::fs::read_to_string(files[0].as_ref().unwrap().path()).unwrap();
+        assert!(content.contains("[line 4] Undefined variable 'missing'."));
+        assert!(content.contains("<fn f> called at [line 6]"));
+        assert!(content.contains("local = \"here\""));
+        assert!(content.contains("answer = 42"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_eval_variable() {
+        assert_eq!(get_value("var a = 1;\nprint a;"), Value::Number(1.0));
+    }
+
+    #[test]
+    fn test_interpret_returns_last_value() {
+        assert_eq!(get_value("1;\n2 + 3;"), Value::Number(5.0));
+        assert_eq!(get_value("1;\nvar a = 2;"), Value::Nil);
+        assert_eq!(get_value("fun f() { 1; }\nf();"), Value::Nil);
+        assert_eq!(get_value("fun f() { return 1; }\nf();"), Value::Number(1.0));
+    }
+
+    #[test]
+    fn test_eval_equal_not_equal() {
+        assert_eq!(get_value("\"hello\" == \"hello\""), Value::Boolean(true));
+        assert_eq!(get_value("\"hello\" == \"hello!\""), Value::Boolean(false));
+    }
+
+    #[test]
+    fn test_eval_complex_expression() {
+        assert_eq!(get_value("1+2 * (8/4)"), Value::Number(5.0));
+    }
+
     #[test]
     fn test_eval_binary() {
         assert_eq!(get_value("1+2"), Value::Number(3.0));
@@ -326,15 +2270,431 @@ mod test {
         assert_eq!(get_value("5/2"), Value::Number(2.5));
     }
 
+    #[test]
+    fn test_division_by_zero() {
+        for src in ["1 / 0;", "var a = 0;\nprint 1 / a;", "print -1 / -0;"] {
+            let mut interpreter = Interpreter::new();
+            match run(&mut interpreter, src) {
+                Err(LoxResult::RuntimeError { token, message }) => {
+                    assert_eq!(token.lexeme, "/");
+                    assert_eq!(token.line, src.lines().count() as i32);
+                    assert_eq!(message, "Division by zero.");
+                }
+                other => panic!("expected a runtime error for {}, got {:?}", src, other),
+            }
+        }
+        assert_eq!(get_value("0 / 4"), Value::Number(0.0));
+    }
+
+    #[test]
+    fn test_floor_division() {
+        assert_eq!(get_value("7//2"), Value::Number(3.0));
+        assert_eq!(get_value("-7//2"), Value::Number(-4.0));
+        assert_eq!(get_value("7.5//0.5"), Value::Number(15.0));
+        assert_eq!(get_value("1 + 7//2 * 2"), Value::Number(7.0));
+        assert_eq!(get_value("(3 + 4)//2"), Value::Number(3.0));
+        match run(
+            &mut Interpreter::new(),
+            "var a = 0;
+1//a;",
+        ) {
+            Err(LoxResult::RuntimeError { token, message }) => {
+                assert_eq!((token.lexeme.as_str(), token.line), ("//", 2));
+                assert_eq!(message, "Division by zero.");
+            }
+            other => panic!("expected a runtime error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_arithmetic_type_errors() {
+        for (src, lexeme, expected) in [
+            ("-true;", "-", "Operand must be a number."),
+            ("-\"x\";", "-", "Operand must be a number."),
+            ("1 - \"a\";", "-", "Operands must be numbers."),
+            ("nil * 2;", "*", "Operands must be numbers."),
+            ("true / 1;", "/", "Operands must be numbers."),
+            (
+                "1 + nil;",
+                "+",
+                "Operands must be two numbers or include a string.",
+            ),
+        ] {
+            let mut interpreter = Interpreter::new();
+            match run(&mut interpreter, src) {
+                Err(LoxResult::RuntimeError { token, message }) => {
+                    assert_eq!(token.lexeme, lexeme);
+                    assert_eq!(token.line, 1);
+                    assert_eq!(message, expected);
+                }
+                other => panic!("expected a runtime error for {}, got {:?}", src, other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_call_non_callable() {
+        for src in [
+            "1();",
+            "\"not a fn\"();",
+            "var a = nil;\na(1, 2);",
+            "class A {}\nA()();",
+            "[1, 2]();",
+        ] {
+            let mut interpreter = Interpreter::new();
+            match run(&mut interpreter, src) {
+                Err(LoxResult::RuntimeError { token, message }) => {
+                    assert_eq!(token.token_type, TokenType::RIGHT_PAREN);
+                    assert_eq!(token.line, src.lines().count() as i32);
+                    assert_eq!(message, "Can only call functions and classes.");
+                }
+                other => panic!("expected a runtime error for {}, got {:?}", src, other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_bitwise_operators() {
+        assert_eq!(get_value("12 & 10"), Value::Number(8.0));
+        assert_eq!(get_value("12 | 3"), Value::Number(15.0));
+        assert_eq!(get_value("12 ^ 10"), Value::Number(6.0));
+        assert_eq!(get_value("1 << 10"), Value::Number(1024.0));
+        assert_eq!(get_value("1024 >> 3"), Value::Number(128.0));
+        assert_eq!(get_value("-8 >> 1"), Value::Number(-4.0));
+        assert_eq!(get_value("-1 & 0xff"), Value::Number(255.0));
+        // `&`比`|`优先，都比`==`优先；移位比`+`低、比`<`高
+        assert_eq!(get_value("1 | 2 & 3"), Value::Number(3.0));
+        assert_eq!(get_value("6 & 3 == 2"), Value::Boolean(true));
+        assert_eq!(get_value("1 << 2 + 1"), Value::Number(8.0));
+        assert_eq!(get_value("1 << 2 < 5"), Value::Boolean(true));
+    }
+
+    #[test]
+    fn test_bitwise_operand_errors() {
+        for (src, lexeme, expected) in [
+            ("1.5 & 2;", "&", "Operands must be integers."),
+            ("1 | 0.5;", "|", "Operands must be integers."),
+            ("\"a\" ^ 1;", "^", "Operands must be numbers."),
+            ("1 << 64;", "<<", "Shift amount must be between 0 and 63."),
+            ("1 >> -1;", ">>", "Shift amount must be between 0 and 63."),
+        ] {
+            let mut interpreter = Interpreter::new();
+            match run(&mut interpreter, src) {
+                Err(LoxResult::RuntimeError { token, message }) => {
+                    assert_eq!(token.lexeme, lexeme);
+                    assert_eq!(message, expected);
+                }
+                other => panic!("expected a runtime error for {}, got {:?}", src, other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_string_concatenation() {
+        assert_eq!(get_value("\"x\" + 1"), Value::String("x1".to_string()));
+        assert_eq!(get_value("1 + \"x\""), Value::String("1x".to_string()));
+        assert_eq!(get_value("1 + 2"), Value::Number(3.0));
+        assert_eq!(get_value("\"a\" + \"b\""), Value::String("ab".to_string()));
+        assert_eq!(
+            get_value("\"count: \" + 2.5 + nil + true"),
+            Value::String("count: 2.5niltrue".to_string())
+        );
+        assert_eq!(get_value("1 + 2 + \"x\""), Value::String("3x".to_string()));
+    }
+
     #[test]
     fn test_eval_unary() {
         assert_eq!(get_value("-1"), Value::Number(-1.0));
         assert_eq!(get_value("!(true)"), Value::Boolean(false));
     }
 
+    #[test]
+    fn test_eval_string_comparison() {
+        assert_eq!(get_value("\"apple\" < \"banana\""), Value::Boolean(true));
+        assert_eq!(get_value("\"apple\" > \"banana\""), Value::Boolean(false));
+        assert_eq!(get_value("\"app\" < \"apple\""), Value::Boolean(true));
+        assert_eq!(get_value("\"Zebra\" < \"apple\""), Value::Boolean(true));
+        assert_eq!(get_value("\"pear\" <= \"pear\""), Value::Boolean(true));
+        assert_eq!(get_value("\"pear\" >= \"peach\""), Value::Boolean(true));
+    }
+
+    #[test]
+    fn test_mixed_comparison_is_an_error() {
+        let mut interpreter = Interpreter::new();
+        match run(&mut interpreter, "1 < \"a\";") {
+            Err(LoxResult::RuntimeError { token, message }) => {
+                assert_eq!(token.lexeme, "<");
+                assert_eq!(message, "Operands must be two numbers or two strings.");
+            }
+            other => panic!("expected a runtime error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_eval_logical() {
+        assert_eq!(get_value("false or 2"), Value::Number(2.0));
+        assert_eq!(get_value("true or 2"), Value::Boolean(true));
+        assert_eq!(get_value("true and 2"), Value::Number(2.0));
+        assert_eq!(get_value("nil and 2"), Value::Nil);
+    }
+
+    #[test]
+    fn test_logical_short_circuits() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "var calls = 0;\nfun foo() { calls = calls + 1; return true; }\nvar a = nil and foo();\nvar b = true or foo();\nvar c = false or foo();",
+        )
+        .unwrap();
+        assert_eq!(get_global(&interpreter, "a"), Value::Nil);
+        assert_eq!(get_global(&interpreter, "b"), Value::Boolean(true));
+        assert_eq!(get_global(&interpreter, "c"), Value::Boolean(true));
+        assert_eq!(get_global(&interpreter, "calls"), Value::Number(1.0));
+    }
+
+    #[test]
+    fn test_null_coalescing() {
+        assert_eq!(get_value("nil ?? 5"), Value::Number(5.0));
+        assert_eq!(get_value("false ?? 5"), Value::Boolean(false));
+        assert_eq!(get_value("nil ?? nil ?? 3"), Value::Number(3.0));
+        assert_eq!(get_value("nil ?? false or 2"), Value::Number(2.0));
+        assert_eq!(get_value("nil ?? 1 == 1"), Value::Boolean(true));
+
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "var calls = 0;\nfun foo() { calls = calls + 1; return 2; }\nvar a = 1 ?? foo();\nvar b = nil ?? foo();",
+        )
+        .unwrap();
+        assert_eq!(get_global(&interpreter, "a"), Value::Number(1.0));
+        assert_eq!(get_global(&interpreter, "b"), Value::Number(2.0));
+        assert_eq!(get_global(&interpreter, "calls"), Value::Number(1.0));
+    }
+
+    #[test]
+    fn test_eval_ternary() {
+        assert_eq!(get_value("true ? 1 : 2"), Value::Number(1.0));
+        assert_eq!(get_value("false ? 1 : 2"), Value::Number(2.0));
+        assert_eq!(get_value("false ? 1 : true ? 2 : 3"), Value::Number(2.0));
+        assert_eq!(get_value("true ? false ? 1 : 2 : 3"), Value::Number(2.0));
+        assert_eq!(
+            get_value("1 < 2 ? \"yes\" : \"no\""),
+            Value::String("yes".to_string())
+        );
+    }
+
+    #[test]
+    fn test_ternary_uses_truthiness() {
+        assert_eq!(get_value("0 ? 1 : 2"), Value::Number(1.0));
+        assert_eq!(get_value("\"\" ? 1 : 2"), Value::Number(1.0));
+        assert_eq!(get_value("nil ? 1 : 2"), Value::Number(2.0));
+
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "var calls = 0;\nfun foo() { calls = calls + 1; return 1; }\nvar a = nil ? foo() : 2;",
+        )
+        .unwrap();
+        assert_eq!(get_global(&interpreter, "a"), Value::Number(2.0));
+        assert_eq!(get_global(&interpreter, "calls"), Value::Number(0.0));
+    }
+
+    #[test]
+    fn test_eval_comma() {
+        assert_eq!(get_value("1, 2, 3"), Value::Number(3.0));
+
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "var a = 0;\nvar b = 0;\nvar c = (a = 1, b = a + 1, a + b);\nvar n = len([a, b]);",
+        )
+        .unwrap();
+        assert_eq!(get_global(&interpreter, "a"), Value::Number(1.0));
+        assert_eq!(get_global(&interpreter, "b"), Value::Number(2.0));
+        assert_eq!(get_global(&interpreter, "c"), Value::Number(3.0));
+        assert_eq!(get_global(&interpreter, "n"), Value::Number(2.0));
+    }
+
+    #[test]
+    fn test_uninitialized_variable() {
+        let mut interpreter = Interpreter::new();
+        match run(&mut interpreter, "var a;\nprint a;") {
+            Err(LoxResult::RuntimeError { token, message }) => {
+                assert_eq!(token.line, 2);
+                assert_eq!(message, "Variable 'a' is used before being initialized.");
+            }
+            other => panic!("expected a runtime error, got {:?}", other),
+        }
+
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "var a = nil;\nprint a;\nvar b;\nb = 1;\n{ var c; fun f() { return c; } c = 2; var d = f(); }",
+        )
+        .unwrap();
+        assert_eq!(get_global(&interpreter, "a"), Value::Nil);
+        assert_eq!(get_global(&interpreter, "b"), Value::Number(1.0));
+
+        let mut interpreter = Interpreter::new();
+        assert!(run(&mut interpreter, "{ var c; print c; }").is_err());
+    }
+
+    #[test]
+    fn test_function_equality() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "fun f() {}\nfun g() {}\nvar h = f;\nvar l1 = fun () {};\nvar l2 = fun () {};\nclass A { m() {} }\nvar a = A();\nvar b = A();\n\
+             var results = [f == f, f == g, f == h, l1 == l2, l1 == l1, A == A, a == b, a == a, a.m == a.m, a.m == b.m, clock == clock, f == nil];",
+        )
+        .unwrap();
+        assert_eq!(
+            get_global(&interpreter, "results").to_string(),
+            "[true, false, true, false, true, true, false, true, true, false, true, false]"
+        );
+    }
+
+    #[test]
+    fn test_undefined_variable_in_function() {
+        for (src, line) in [
+            ("fun f() {\n  return x;\n}\nf();", 2),
+            ("fun f(a) {\n  var b = a;\n  x = b;\n}\nf(1);", 3),
+            (
+                "fun outer() {\n  fun inner() { print x; }\n  return inner;\n}\nouter()();",
+                2,
+            ),
+        ] {
+            let mut interpreter = Interpreter::new();
+            match run(&mut interpreter, src) {
+                Err(LoxResult::RuntimeError { token, message }) => {
+                    assert_eq!(token.lexeme, "x");
+                    assert_eq!(token.line, line);
+                    assert_eq!(message, "Undefined variable 'x'.");
+                }
+                other => panic!("expected a runtime error for {}, got {:?}", src, other),
+            }
+            //出错之后解释器回到全局环境，仍然可以继续执行
+            run(&mut interpreter, "var x = 1;\nvar y = x;").unwrap();
+            assert_eq!(get_global(&interpreter, "y"), Value::Number(1.0));
+        }
+    }
+
+    #[test]
+    fn test_for_in() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "var sum = 0;\nfor (item in [1, 2, 3]) sum = sum + item;\n\
+             var chars = [];\nfor (c in \"a你b\") { if (c == \"b\") break; push(chars, c); }\n\
+             var odd = 0;\nfor (i in [1, 2, 3, 4, 5]) { if (i / 2 == 2 or i == 2) continue; odd = odd + i; }\n\
+             var fns = [];\nfor (i in [1, 2]) push(fns, fun () { return i; });\nvar first = fns[0]();\n\
+             var i;\nfor (i = 0; i < 3; i = i + 1) {}",
+        )
+        .unwrap();
+        assert_eq!(get_global(&interpreter, "sum"), Value::Number(6.0));
+        assert_eq!(
+            get_global(&interpreter, "chars").to_string(),
+            "[\"a\", \"你\"]"
+        );
+        assert_eq!(get_global(&interpreter, "odd"), Value::Number(9.0));
+        assert_eq!(get_global(&interpreter, "first"), Value::Number(1.0));
+        assert_eq!(get_global(&interpreter, "i"), Value::Number(3.0));
+    }
+
+    #[test]
+    fn test_for_in_non_iterable() {
+        let mut interpreter = Interpreter::new();
+        match run(&mut interpreter, "for (x in 12) print x;") {
+            Err(LoxResult::RuntimeError { token, message }) => {
+                assert_eq!(token.lexeme, "x");
+                assert_eq!(message, "Can only iterate over lists and strings.");
+            }
+            other => panic!("expected a runtime error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_assert() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "assert true;\nassert 1 + 1 == 2 : \"math\";\nassert 0;\nassert \"\";",
+        )
+        .unwrap();
+
+        for (src, expected) in [
+            ("assert false;", "Assertion failed."),
+            ("assert nil : \"x is \" + 1;", "x is 1"),
+            ("var a = 1;\nassert a == 2 ? true : false : a;", "1"),
+        ] {
+            let mut interpreter = Interpreter::new();
+            match run(&mut interpreter, src) {
+                Err(LoxResult::RuntimeError { token, message }) => {
+                    assert_eq!(token.lexeme, "assert");
+                    assert_eq!(token.line, src.lines().count() as i32);
+                    assert_eq!(message, expected);
+                }
+                other => panic!("expected a runtime error for {}, got {:?}", src, other),
+            }
+        }
+    }
+
     #[test]
     fn test_eval_literal() {
         let value = Value::Boolean(true);
         assert_eq!(value, get_value("(true)"))
     }
+
+    #[test]
+    fn test_list_literal_and_index() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "var list = [1, \"two\", nil];\nvar a = list[0];\nvar b = list[1 + 0];\nlist[2] = 3;",
+        )
+        .unwrap();
+        assert_eq!(get_global(&interpreter, "a"), Value::Number(1.0));
+        assert_eq!(
+            get_global(&interpreter, "b"),
+            Value::String("two".to_string())
+        );
+        assert_eq!(
+            get_global(&interpreter, "list").to_string(),
+            "[1, \"two\", 3]"
+        );
+        assert_eq!(get_value("[]").to_string(), "[]");
+    }
+
+    #[test]
+    fn test_list_is_shared() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "var a = [];\nvar b = a;\npush(b, 1);\npush(a, 2);\nvar n = len(a);",
+        )
+        .unwrap();
+        assert_eq!(get_global(&interpreter, "n"), Value::Number(2.0));
+        assert_eq!(get_global(&interpreter, "b").to_string(), "[1, 2]");
+    }
+
+    #[test]
+    fn test_list_index_errors() {
+        for (src, expected) in [
+            ("[1, 2][2];", "List index out of range."),
+            ("[1, 2][-1];", "List index out of range."),
+            ("[1, 2][0.5];", "List index must be an integer."),
+            ("[1, 2][\"0\"];", "List index must be an integer."),
+            ("var a = [1]; a[1] = 2;", "List index out of range."),
+            ("\"abc\"[0];", "Only lists can be indexed."),
+        ] {
+            let mut interpreter = Interpreter::new();
+            match run(&mut interpreter, src) {
+                Err(LoxResult::RuntimeError { token, message }) => {
+                    assert_eq!(token.lexeme, "]");
+                    assert_eq!(message, expected);
+                }
+                other => panic!("expected a runtime error for {}, got {:?}", src, other),
+            }
+        }
+    }
 }
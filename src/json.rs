@@ -0,0 +1,312 @@
+//! json.rs 在lox的值和JSON文本之间转换，供原生函数`json_stringify`和`json_parse`使用
+//!
+//! lox没有映射类型，所以JSON对象既不能产生也不能解析；数字就是lox的`f64`
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::value::{format_number, Value};
+
+///把值转换成JSON文本，只支持数字、字符串、布尔值、nil和由它们组成的列表
+///
+///出错时返回错误信息，函数、类、实例、`NaN`、无穷大和包含自己的列表都无法转换
+pub fn stringify(value: &Value) -> Result<String, String> {
+    let mut result = String::new();
+    write_value(value, &mut result, &mut Vec::new())?;
+    Ok(result)
+}
+
+fn write_value(
+    value: &Value,
+    out: &mut String,
+    open: &mut Vec<*const RefCell<Vec<Value>>>,
+) -> Result<(), String> {
+    match value {
+        Value::Nil => out.push_str("null"),
+        Value::Boolean(b) => out.push_str(&b.to_string()),
+        Value::Number(n) if n.is_finite() => out.push_str(&format_number(*n)),
+        Value::Number(n) => return Err(format!("Can't convert {} to JSON.", format_number(*n))),
+        Value::String(s) => write_string(s, out),
+        Value::List(list) => {
+            if open.contains(&Rc::as_ptr(list)) {
+                return Err("Can't convert a list that contains itself to JSON.".to_string());
+            }
+            open.push(Rc::as_ptr(list));
+            out.push('[');
+            for (i, element) in list.borrow().iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_value(element, out, open)?;
+            }
+            out.push(']');
+            open.pop();
+        }
+        value => return Err(format!("Can't convert {} to JSON.", value.type_name())),
+    }
+    Ok(())
+}
+
+fn write_string(s: &str, out: &mut String) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+///把JSON文本解析成lox的值，`null`是nil，数组是列表
+///
+///出错时返回带有出错位置(按字符计数)的错误信息
+pub fn parse(source: &str) -> Result<Value, String> {
+    let mut parser = JsonParser {
+        chars: source.chars().collect(),
+        current: 0,
+    };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.current < parser.chars.len() {
+        return Err(parser.error("Unexpected character after JSON value"));
+    }
+    Ok(value)
+}
+
+struct JsonParser {
+    chars: Vec<char>,
+    current: usize,
+}
+
+impl JsonParser {
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('n') => self.keyword("null", Value::Nil),
+            Some('t') => self.keyword("true", Value::Boolean(true)),
+            Some('f') => self.keyword("false", Value::Boolean(false)),
+            Some('"') => Ok(Value::String(self.string()?)),
+            Some('[') => self.array(),
+            Some('{') => Err(self.error("JSON objects are not supported")),
+            Some(c) if c == '-' || c.is_ascii_digit() => self.number(),
+            Some(_) => Err(self.error("Unexpected character")),
+            None => Err(self.error("Unexpected end of input")),
+        }
+    }
+
+    fn keyword(&mut self, keyword: &str, value: Value) -> Result<Value, String> {
+        for expected in keyword.chars() {
+            if self.advance() != Some(expected) {
+                return Err(self.error(&format!("Expect '{}'", keyword)));
+            }
+        }
+        Ok(value)
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        self.advance();
+        let mut elements = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.advance();
+        } else {
+            loop {
+                elements.push(self.value()?);
+                self.skip_whitespace();
+                match self.advance() {
+                    Some(',') => {}
+                    Some(']') => break,
+                    _ => return Err(self.error("Expect ',' or ']' in array")),
+                }
+            }
+        }
+        Ok(Value::List(Rc::new(RefCell::new(elements))))
+    }
+
+    ///`-? (0 | [1-9][0-9]*) (. [0-9]+)? ([eE] [+-]? [0-9]+)?`
+    fn number(&mut self) -> Result<Value, String> {
+        let start = self.current;
+        self.match_char('-');
+        if !self.match_char('0') && self.digits() == 0 {
+            return Err(self.error("Expect digit"));
+        }
+        if self.match_char('.') && self.digits() == 0 {
+            return Err(self.error("Expect digit after '.'"));
+        }
+        if self.match_char('e') || self.match_char('E') {
+            if !self.match_char('+') {
+                self.match_char('-');
+            }
+            if self.digits() == 0 {
+                return Err(self.error("Expect digit in exponent"));
+            }
+        }
+        let text: String = self.chars[start..self.current].iter().collect();
+        match text.parse::<f64>() {
+            Ok(n) if n.is_finite() => Ok(Value::Number(n)),
+            _ => Err(self.error("Number out of range")),
+        }
+    }
+
+    fn digits(&mut self) -> usize {
+        let start = self.current;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.current += 1;
+        }
+        self.current - start
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.advance();
+        let mut result = String::new();
+        loop {
+            match self.advance() {
+                Some('"') => return Ok(result),
+                Some('\\') => {
+                    let c = match self.advance() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => self.unicode_escape()?,
+                        _ => return Err(self.error("Invalid escape sequence")),
+                    };
+                    result.push(c);
+                }
+                Some(c) if (c as u32) < 0x20 => {
+                    return Err(self.error("Control character in string"))
+                }
+                Some(c) => result.push(c),
+                None => return Err(self.error("Unterminated string")),
+            }
+        }
+    }
+
+    ///`\u`之后的4位十六进制数，UTF-16的代理对由两个连续的`\u`组成
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        let high = self.hex4()?;
+        let code = if (0xD800..0xDC00).contains(&high) {
+            if !(self.match_char('\\') && self.match_char('u')) {
+                return Err(self.error("Expect low surrogate"));
+            }
+            let low = self.hex4()?;
+            if !(0xDC00..0xE000).contains(&low) {
+                return Err(self.error("Invalid low surrogate"));
+            }
+            0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+        } else {
+            high
+        };
+        char::from_u32(code).ok_or_else(|| self.error("Invalid unicode escape"))
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let mut code = 0;
+        for _ in 0..4 {
+            match self.advance().and_then(|c| c.to_digit(16)) {
+                Some(digit) => code = code * 16 + digit,
+                None => return Err(self.error("Expect 4 hex digits after '\\u'")),
+            }
+        }
+        Ok(code)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t' | '\n' | '\r')) {
+            self.current += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.current).copied()
+    }
+
+    fn advance(&mut self) -> Option<char> {
+        let c = self.peek();
+        if c.is_some() {
+            self.current += 1;
+        }
+        c
+    }
+
+    fn match_char(&mut self, expected: char) -> bool {
+        if self.peek() == Some(expected) {
+            self.current += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn error(&self, message: &str) -> String {
+        format!("Invalid JSON: {} at position {}.", message, self.current)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn list(elements: Vec<Value>) -> Value {
+        Value::List(Rc::new(RefCell::new(elements)))
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let value = list(vec![
+            Value::Number(1.0),
+            Value::Number(-2.5e-3),
+            Value::String("a \"quoted\"\n\u{1}é🦀".to_string()),
+            list(vec![Value::Boolean(true), Value::Nil, list(vec![])]),
+        ]);
+        let json = stringify(&value).unwrap();
+        assert_eq!(
+            json,
+            "[1,-0.0025,\"a \\\"quoted\\\"\\n\\u0001é🦀\",[true,null,[]]]"
+        );
+        assert_eq!(parse(&json).unwrap().to_string(), value.to_string());
+
+        let parsed = parse(" [ 1e2 , \"\\u00e9\\ud83e\\udd80\\/\" , [ ] ] ").unwrap();
+        assert_eq!(parsed.to_string(), "[100, \"é🦀/\", []]");
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            stringify(&Value::Number(f64::NAN)).unwrap_err(),
+            "Can't convert NaN to JSON."
+        );
+        let inner = list(vec![]);
+        if let Value::List(elements) = &inner {
+            elements.borrow_mut().push(inner.clone());
+        }
+        assert!(stringify(&inner).is_err());
+
+        for (source, message) in [
+            ("", "Invalid JSON: Unexpected end of input at position 0."),
+            ("[1,]", "Invalid JSON: Unexpected character at position 3."),
+            (
+                "01",
+                "Invalid JSON: Unexpected character after JSON value at position 1.",
+            ),
+            (
+                "{\"a\": 1}",
+                "Invalid JSON: JSON objects are not supported at position 0.",
+            ),
+            ("\"abc", "Invalid JSON: Unterminated string at position 4."),
+            ("tru", "Invalid JSON: Expect 'true' at position 3."),
+        ] {
+            assert_eq!(parse(source).unwrap_err(), message, "{}", source);
+        }
+    }
+}
@@ -0,0 +1,471 @@
+/*!
+这个项目使用rust编写，是一个`Tree-walk Interpret`, 处理了名为lox的语言，以下为lox的语法
+
+```text
+## Syntax Grammer
+program        → declaration* EOF ;
+
+## Declarations
+declaration    →  classDecl
+                | funDecl
+                | varDecl
+                | statement ;
+
+classDecl      → "class" IDENTIFIER ( "<" IDENTIFIER )?
+                 "{" ( function | getter | "class" function )* "}" ;
+funDecl        → "fun" function ;
+varDecl        → "var" variable ( "," variable )* ";" ;
+variable       → IDENTIFIER ( "=" assignment )? ;
+statement      → exprStmt
+               | assertStmt
+               | breakStmt
+               | continueStmt
+               | deferStmt
+               | forStmt
+               | ifStmt
+               | printStmt
+               | returnStmt
+               | tryStmt
+               | whileStmt
+               | block ;
+
+exprStmt       → expression ";" ;
+assertStmt     → "assert" expression ( ":" expression )? ";" ;
+breakStmt      → "break" ";" ;
+continueStmt   → "continue" ";" ;
+deferStmt      → "defer" statement ;
+forStmt        → "for" "(" ( varDecl | exprStmt | ";" )
+                           expression? ";"
+                           expression? ")" statement
+               | "for" "(" IDENTIFIER "in" expression ")" statement ;
+ifStmt         → "if" "(" expression ")" statement
+                 ( "else" statement )? ;
+printStmt      → "print" ( assignment ( "," assignment )* )? ";" ;
+returnStmt     → "return" expression? ";" ;
+tryStmt        → "try" block "catch" "(" IDENTIFIER ")" block ;
+whileStmt      → "while" "(" expression ")" statement ;
+block          → "{" declaration* "}" ;
+
+##Expressios
+expression     → assignment ( "," assignment )* ;
+
+assignment     → ( call "." )? IDENTIFIER assign_op assignment
+               | call "[" expression "]" assign_op assignment
+               | conditional ;
+assign_op      → "=" | "+=" | "-=" | "*=" | "/=" ;
+
+conditional    → logic_or ( "?" expression ":" conditional )? ;
+
+logic_or       → coalesce ( "or" coalesce )* ;
+coalesce       → logic_and ( "??" logic_and )* ;
+logic_and      → equality ( "and" equality )* ;
+equality       → bit_or ( ( "!=" | "==" ) bit_or )* ;
+bit_or         → bit_xor ( "|" bit_xor )* ;
+bit_xor        → bit_and ( "^" bit_and )* ;
+bit_and        → comparison ( "&" comparison )* ;
+comparison     → shift ( ( ">" | ">=" | "<" | "<=" ) shift )* ;
+shift          → term ( ( "<<" | ">>" ) term )* ;
+term           → factor ( ( "-" | "+" ) factor )* ;
+factor         → unary ( ( "/" | "//" | "*" ) unary )* ;
+
+unary          → ( "!" | "-" ) unary | call ;
+call           → primary ( "(" call_args? ")" | "." IDENTIFIER | "[" expression "]" )* ;
+primary        → "true" | "false" | "nil" | "this"
+               | NUMBER | STRING | IDENTIFIER | "(" expression ")"
+               | "super" "." IDENTIFIER
+               | "fun" "(" parameters? ")" block
+               | "{" declaration* expression? "}"
+               | "[" arguments? "]" ;
+
+## Utility rules
+function       → IDENTIFIER "(" parameters? ")" block ;
+getter         → IDENTIFIER block ;
+parameters     → IDENTIFIER ( "," IDENTIFIER )* ","? ;
+arguments      → assignment ( "," assignment )* ","? ;
+call_args      → argument ( "," argument )* ","? ;
+argument       → ( IDENTIFIER ":" )? assignment ;
+
+## Lexical Grammer
+NUMBER         → DIGITS ( "." DIGITS )?
+               | "0" ( "x" | "X" ) HEX_DIGIT ( "_"? HEX_DIGIT )*
+               | "0" ( "b" | "B" ) ( "0" | "1" ) ( "_"? ( "0" | "1" ) )* ;
+DIGITS         → DIGIT ( "_"? DIGIT )* ;
+HEX_DIGIT      → DIGIT | "a" ... "f" | "A" ... "F" ;
+STRING         → "\"" ( <any char except "\"" and "\\"> | ESCAPE )* "\"" ;
+ESCAPE         → "\\" ( "n" | "t" | "r" | "\\" | "\"" | "0" )
+               | "\\u{" HEX_DIGIT+ "}" ;
+IDENTIFIER     → ALPHA ( ALPHA | DIGIT )* ;
+ALPHA          → "a" ... "z" | "A" ... "Z" | "_" ;
+DIGIT          → "0" ... "9" ;
+```
+
+lox只有一种数字类型`f64`，`/`总是浮点除法，`7 / 2`是`3.5`；`//`是向下取整的除法，
+`7//2`是`3`，`-7//2`是`-4`，两者的除数为0时都是运行时错误。
+
+`//`同时也是单行注释的开头，两者靠空白区分：整除运算符的两边都不能有空白，
+并且它前面紧挨着一个操作数的结尾（数字、字符串、变量名、`)`、`]`等），比如`a//b`、`(a + 1)//2`；
+其他的`//`都是注释，所以`a // b`是`a`后面跟着注释，语句、类头和方法头之后的注释也不受影响
+
+在表达式中的`{ ... }`是块表达式，比如`var x = { var a = 1; a + 1 };`，
+最后一个没有`;`的表达式是它的值，没有这样的表达式时值为nil；语句开头的`{`仍然是块语句。
+
+调用lox函数时可以按参数名传参，比如`f(1, y: 2)`，具名参数必须在按位置的参数之后；
+原生函数和类不接受具名参数!*/
+mod ast_printer;
+pub mod environment;
+pub mod expr;
+pub mod interpreter;
+mod json;
+pub mod loxcallable;
+pub mod loxclass;
+pub mod loxfunction;
+pub mod loxinstance;
+pub mod loxresult;
+pub mod natives;
+pub mod parser;
+pub mod resolver;
+pub mod scanner;
+pub mod stmt;
+pub mod token;
+pub mod token_type;
+pub mod value;
+
+use interpreter::Interpreter;
+use loxresult::LoxResult;
+use parser::Parser;
+use resolver::Resolver;
+use scanner::Scanner;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use stmt::Stmt;
+use token::Token;
+use value::Value;
+
+///接收错误的函数，每个错误都会传给它一次
+pub type ErrorSink = Box<dyn FnMut(&LoxResult)>;
+
+///一次执行中各个阶段的耗时，见[`Lox::set_time`]
+///
+///某个阶段出错时，之后的阶段没有执行，耗时为0
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PhaseTimes {
+    pub scan: Duration,
+    pub parse: Duration,
+    pub resolve: Duration,
+    pub interpret: Duration,
+}
+
+///每个阶段一行，以毫秒为单位
+impl fmt::Display for PhaseTimes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (phase, duration) in [
+            ("scan", self.scan),
+            ("parse", self.parse),
+            ("resolve", self.resolve),
+            ("interpret", self.interpret),
+        ] {
+            writeln!(f, "{:<10}{:.3} ms", phase, duration.as_secs_f64() * 1000.0)?;
+        }
+        Ok(())
+    }
+}
+
+///按行统计的覆盖率，见[`Lox::set_coverage`]
+///
+///可以执行的行是语句开始的行，函数体和没有进入的分支中的语句也包括在内
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Coverage {
+    ///所有语句开始的行
+    pub executable: BTreeSet<i32>,
+    ///至少执行过一次的语句开始的行
+    pub executed: BTreeSet<i32>,
+}
+
+impl Coverage {
+    ///可以执行但一次都没有执行过的行
+    pub fn uncovered(&self) -> Vec<i32> {
+        self.executable
+            .difference(&self.executed)
+            .copied()
+            .collect()
+    }
+}
+
+///第一行是执行过的行数和总行数，如果有没执行过的行，第二行把它们列出来
+impl fmt::Display for Coverage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total = self.executable.len();
+        let executed = self.executable.intersection(&self.executed).count();
+        let percent = if total == 0 {
+            100.0
+        } else {
+            executed as f64 * 100.0 / total as f64
+        };
+        writeln!(
+            f,
+            "coverage: {}/{} lines executed ({:.1}%)",
+            executed, total, percent
+        )?;
+        let uncovered = self.uncovered();
+        if !uncovered.is_empty() {
+            let lines: Vec<_> = uncovered.iter().map(|line| line.to_string()).collect();
+            writeln!(f, "not executed: {}", lines.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+///定义lox结构体，一个[`Lox`]就是一个独立的解释器，可以嵌入到其他程序中使用
+///
+///```
+///use std::{cell::RefCell, rc::Rc};
+///
+///let messages = Rc::new(RefCell::new(Vec::new()));
+///let sink = Rc::clone(&messages);
+///let mut lox = lox::Lox::new();
+///lox.set_error_sink(move |error| sink.borrow_mut().push(error.to_string()));
+///
+///assert!(lox.interpret_source("var a = 1;").is_ok());
+///assert_eq!(lox.interpret_source("a + 1;").unwrap(), lox::value::Value::Number(2.0));
+///let errors = lox.interpret_source("print a + ;\nprint b;").unwrap_err();
+///assert_eq!(errors.len(), 1);
+///assert_eq!(
+///    messages.borrow().as_slice(),
+///    ["[line 1, column 11] Error  at ' ; ': Expect expression"]
+///);
+///```
+pub struct Lox {
+    ///整个解释器的环境，多次调用[`Lox::interpret_source`]之间共享全局变量
+    interpreter: Interpreter,
+    ///错误发生时会交给它处理，默认打印到stderr
+    error_sink: ErrorSink,
+    ///是否记录每个阶段的耗时，见[`Lox::set_time`]
+    time: bool,
+    ///最近一次执行各阶段的耗时
+    phase_times: Option<PhaseTimes>,
+    ///打开了[`Lox::set_coverage`]时，所有执行过的程序中语句开始的行
+    executable_lines: Option<BTreeSet<i32>>,
+}
+
+impl Default for Lox {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+///定义了Lox结构体的方法
+impl Lox {
+    pub fn new() -> Self {
+        Lox::with_trace(false)
+    }
+
+    ///见[`Interpreter::with_trace`]
+    pub fn with_trace(trace: bool) -> Self {
+        Lox {
+            interpreter: Interpreter::with_trace(trace),
+            error_sink: Box::new(|error| eprintln!("{}", error)),
+            time: false,
+            phase_times: None,
+            executable_lines: None,
+        }
+    }
+
+    ///替换默认的错误处理，比如把错误收集起来而不是打印
+    pub fn set_error_sink(&mut self, sink: impl FnMut(&LoxResult) + 'static) {
+        self.error_sink = Box::new(sink);
+    }
+
+    ///见[`Interpreter::set_input`]
+    pub fn set_input(&mut self, input: impl std::io::BufRead + 'static) {
+        self.interpreter.set_input(input);
+    }
+
+    ///见[`Interpreter::set_output`]
+    pub fn set_output(&mut self, output: impl std::io::Write + 'static) {
+        self.interpreter.set_output(output);
+    }
+
+    ///见[`Interpreter::set_seed`]
+    pub fn set_seed(&mut self, seed: u64) {
+        self.interpreter.set_seed(seed);
+    }
+
+    ///见[`Interpreter::reset`]
+    pub fn reset(&mut self) {
+        self.interpreter.reset();
+    }
+
+    ///见[`Interpreter::set_dump_on_error`]
+    pub fn set_dump_on_error(&mut self, dir: PathBuf) {
+        self.interpreter.set_dump_on_error(dir);
+    }
+
+    ///为true时，[`Lox::interpret_source`]和[`Lox::interpret_line`]会记录词法分析、语法分析、
+    ///变量解析和执行各自的耗时，之后可以通过[`Lox::phase_times`]取得。默认关闭，关闭时不会读取时钟
+    pub fn set_time(&mut self, time: bool) {
+        self.time = time;
+    }
+
+    ///最近一次执行各阶段的耗时，没有打开[`Lox::set_time`]时是`None`
+    pub fn phase_times(&self) -> Option<PhaseTimes> {
+        self.phase_times
+    }
+
+    ///为true时统计之后执行的程序的行覆盖率，之后可以通过[`Lox::coverage`]取得。
+    ///多次执行的结果会累加，但行号不区分来自哪一段源代码
+    pub fn set_coverage(&mut self, coverage: bool) {
+        self.executable_lines = coverage.then(BTreeSet::new);
+        self.interpreter.set_coverage(coverage);
+    }
+
+    ///打开[`Lox::set_coverage`]以来的行覆盖率，没有打开时是`None`
+    pub fn coverage(&self) -> Option<Coverage> {
+        Some(Coverage {
+            executable: self.executable_lines.clone()?,
+            executed: self.interpreter.executed_lines()?.clone(),
+        })
+    }
+
+    ///对lox语言进行编译与执行
+    ///
+    ///成功时返回最后一条语句的值，见[`Interpreter::interpret`]
+    ///
+    ///词法、语法和变量解析的错误会让程序不被执行；所有错误都会先交给错误处理函数，再一并返回
+    pub fn interpret_source(&mut self, source: &str) -> Result<Value, Vec<LoxResult>> {
+        let result = self.run(source, false);
+        self.report(result)
+    }
+
+    ///执行REPL中输入的一行
+    ///
+    ///如果整行是一个表达式(没有结尾的`;`)，就像`print`语句一样打印它的值；
+    ///其他情况与[`Lox::interpret_source`]相同，所以语句不会被重复打印
+    pub fn interpret_line(&mut self, source: &str) -> Result<Value, Vec<LoxResult>> {
+        let result = self.run(source, true);
+        self.report(result)
+    }
+
+    ///只做词法和语法分析，把每条顶层语句打印成一行S表达式，不执行程序
+    pub fn dump_ast(&mut self, source: &str) -> Result<String, Vec<LoxResult>> {
+        let (_, result) = scan_and_parse(source);
+        let result = result.map(|statements| {
+            let printer = ast_printer::ExprVisitor;
+            let mut result = String::new();
+            for stmt in &statements {
+                result.push_str(&printer.print_stmt(stmt));
+                result.push('\n');
+            }
+            result
+        });
+        self.report(result)
+    }
+
+    ///把错误交给错误处理函数，原生函数`exit`产生的[`LoxResult::Exit`]不是错误，不会交给它
+    fn report<T>(&mut self, result: Result<T, Vec<LoxResult>>) -> Result<T, Vec<LoxResult>> {
+        if let Err(errors) = &result {
+            for error in errors
+                .iter()
+                .filter(|error| !matches!(error, LoxResult::Exit { .. }))
+            {
+                (self.error_sink)(error);
+            }
+        }
+        result
+    }
+
+    ///`echo`为true时，只有一个表达式的输入会被当作打印它的`print`语句
+    fn run(&mut self, source: &str, echo: bool) -> Result<Value, Vec<LoxResult>> {
+        let mut times = PhaseTimes::default();
+        let result = self.run_phases(source, echo, &mut times);
+        self.phase_times = self.time.then_some(times);
+        result
+    }
+
+    ///依次执行每个阶段，打开了[`Lox::set_time`]时把每个阶段的耗时记录到`times`中
+    fn run_phases(
+        &mut self,
+        source: &str,
+        echo: bool,
+        times: &mut PhaseTimes,
+    ) -> Result<Value, Vec<LoxResult>> {
+        let start = self.start_timer();
+        let mut scanner = Scanner::new(source.to_string());
+        let tokens = scanner.scan_tokens();
+        let mut errors = scanner.errors().to_vec();
+        times.scan = Lox::elapsed(start);
+
+        let start = self.start_timer();
+        let mut parser = Parser::new(tokens);
+        let expression = if echo && errors.is_empty() {
+            parser.parse_expression()
+        } else {
+            None
+        };
+        let statements = match expression {
+            Some(expression) => Ok(vec![Stmt::Print {
+                span: expression.span(),
+                expressions: vec![expression],
+            }]),
+            None => parser.parse(),
+        };
+        times.parse = Lox::elapsed(start);
+        if let (Some(lines), Ok(_)) = (&mut self.executable_lines, &statements) {
+            lines.extend(parser.statement_lines());
+        }
+        let statements = match statements {
+            Ok(statements) if errors.is_empty() => statements,
+            Ok(_) => return Err(errors),
+            Err(parse_errors) => {
+                errors.extend(parse_errors);
+                return Err(errors);
+            }
+        };
+
+        let start = self.start_timer();
+        let resolved = Resolver::new(&mut self.interpreter).resolve(&statements);
+        times.resolve = Lox::elapsed(start);
+        resolved?;
+
+        let start = self.start_timer();
+        let result = self.interpreter.interpret(statements);
+        times.interpret = Lox::elapsed(start);
+        result
+    }
+
+    fn start_timer(&self) -> Option<Instant> {
+        self.time.then(Instant::now)
+    }
+
+    fn elapsed(start: Option<Instant>) -> Duration {
+        start.map_or(Duration::ZERO, |start| start.elapsed())
+    }
+}
+
+///只做词法和语法分析，同时返回token和语法树，供格式化工具、语言服务器等复用前端
+///
+///返回的token总是以[`token_type::TokenType::EOF`]结尾，即使源代码中有词法错误。
+///词法错误和语法错误都放在第二个返回值中，有任何错误时都不会返回语法树
+///
+///```
+///let (tokens, statements) = lox::scan_and_parse("print 1 + 2;");
+///assert_eq!(tokens.len(), 6);
+///assert_eq!(tokens.last().unwrap().token_type, lox::token_type::TokenType::EOF);
+///assert_eq!(statements.unwrap().len(), 1);
+///
+///let (tokens, statements) = lox::scan_and_parse("print 1 +;");
+///assert_eq!(tokens.len(), 5);
+///assert_eq!(statements.unwrap_err().len(), 1);
+///```
+pub fn scan_and_parse(source: &str) -> (Vec<Token>, Result<Vec<Stmt>, Vec<LoxResult>>) {
+    let mut scanner = Scanner::new(source.to_string());
+    let tokens = scanner.scan_tokens();
+    let mut errors = scanner.errors().to_vec();
+    match Parser::new(tokens.clone()).parse() {
+        Ok(statements) if errors.is_empty() => return (tokens, Ok(statements)),
+        Ok(_) => {}
+        Err(parse_errors) => errors.extend(parse_errors),
+    }
+    (tokens, Err(errors))
+}
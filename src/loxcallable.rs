@@ -1,13 +1,42 @@
-use crate::{interpreter::Interpreter, loxresult::LoxResult, value::Value};
+use crate::{interpreter::Interpreter, loxresult::LoxResult, token::Token, value::Value};
 
 ///定义了可以被调用的结构体的共同特征
 ///
-///目前只有[`crate::loxfunction`]
+///目前有[`crate::loxfunction`]、[`crate::loxclass`]和[`crate::natives`]，调用类会创建一个新的实例
+///
+///`paren`是调用处的右括号，调用中产生的运行时错误会指向它
 pub trait LoxCallable {
     fn call(
         &self,
         interpreter: &mut Interpreter,
+        paren: &Token,
         arguments: Vec<Value>,
     ) -> Result<Value, LoxResult>;
-    fn arity(&self) -> usize;
+    fn arity(&self) -> Arity;
+}
+
+///可调用对象接受的参数个数
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arity {
+    ///必须正好是这么多个参数
+    Fixed(usize),
+    ///至少这么多个参数，多出来的参数也会传给函数
+    AtLeast(usize),
+}
+
+impl Arity {
+    pub fn accepts(&self, count: usize) -> bool {
+        match *self {
+            Arity::Fixed(n) => count == n,
+            Arity::AtLeast(n) => count >= n,
+        }
+    }
+
+    ///参数个数不对时的错误信息
+    pub fn mismatch_message(&self, count: usize) -> String {
+        match *self {
+            Arity::Fixed(n) => format!("Expect {} arguments but got {}.", n, count),
+            Arity::AtLeast(n) => format!("Expect at least {} arguments but got {}.", n, count),
+        }
+    }
 }
@@ -0,0 +1,93 @@
+use core::fmt;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::interpreter::Interpreter;
+use crate::loxcallable::{Arity, LoxCallable};
+use crate::loxfunction::LoxFunction;
+use crate::loxinstance::LoxInstance;
+use crate::loxresult::LoxResult;
+use crate::token::Token;
+use crate::value::Value;
+
+///定义了类，类中保存了它的方法
+#[derive(PartialEq, Clone, Debug)]
+pub struct LoxClass {
+    ///类名
+    name: String,
+    ///父类
+    superclass: Option<Rc<LoxClass>>,
+    ///方法名到方法的映射
+    methods: HashMap<String, LoxFunction>,
+    ///静态方法，通过类本身调用，不绑定实例
+    class_methods: HashMap<String, LoxFunction>,
+}
+
+impl LoxClass {
+    pub fn new(
+        name: String,
+        superclass: Option<Rc<LoxClass>>,
+        methods: HashMap<String, LoxFunction>,
+        class_methods: HashMap<String, LoxFunction>,
+    ) -> LoxClass {
+        LoxClass {
+            name,
+            superclass,
+            methods,
+            class_methods,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    ///按名字查找方法，本类中没有时到父类中查找
+    pub fn find_method(&self, name: &str) -> Option<&LoxFunction> {
+        self.methods.get(name).or_else(|| {
+            self.superclass
+                .as_ref()
+                .and_then(|superclass| superclass.find_method(name))
+        })
+    }
+
+    ///按名字查找静态方法，本类中没有时到父类中查找
+    pub fn find_class_method(&self, name: &str) -> Option<&LoxFunction> {
+        self.class_methods.get(name).or_else(|| {
+            self.superclass
+                .as_ref()
+                .and_then(|superclass| superclass.find_class_method(name))
+        })
+    }
+}
+
+///调用一个类会创建这个类的新实例，类或父类中有`init`方法时，参数会传给它来初始化实例
+impl LoxCallable for Rc<LoxClass> {
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        paren: &Token,
+        arguments: Vec<Value>,
+    ) -> Result<Value, LoxResult> {
+        let instance = Rc::new(RefCell::new(LoxInstance::new(Rc::clone(self))));
+        if let Some(initializer) = self.find_method("init") {
+            let initializer = initializer.bind(Rc::clone(&instance));
+            interpreter.call(&initializer, paren, arguments)?;
+        }
+        Ok(Value::LoxInstance(instance))
+    }
+
+    ///与`init`方法的参数个数相同，没有`init`时为0
+    fn arity(&self) -> Arity {
+        self.find_method("init")
+            .map_or(Arity::Fixed(0), |initializer| initializer.arity())
+    }
+}
+
+///为[`LoxClass`] 实现 [`fmt::Display`] ,这样可以使用[`print`]打印出类
+impl fmt::Display for LoxClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<class {}>", self.name)
+    }
+}
@@ -1,16 +1,23 @@
 use core::fmt;
+use std::cell::RefCell;
+use std::rc::Rc;
 
 use crate::environment::Environment;
+use crate::loxclass::LoxClass;
+use crate::loxinstance::LoxInstance;
 use crate::loxresult::LoxResult;
 use crate::token::Token;
 use crate::value::Value;
-use crate::{loxcallable::LoxCallable, stmt::Stmt};
+use crate::{
+    loxcallable::{Arity, LoxCallable},
+    stmt::Stmt,
+};
 
 ///定义了函数结构
 #[derive(PartialEq, Clone, Debug)]
 struct Declaration {
-    ///函数名称
-    name: Token,
+    ///函数名称，匿名函数没有名称
+    name: Option<Token>,
     ///参数列表
     params: Vec<Token>,
     ///函数体
@@ -18,10 +25,20 @@ struct Declaration {
 }
 
 ///定义了函数
-#[derive(PartialEq, Clone, Debug)]
+#[derive(Clone)]
 pub struct LoxFunction {
-    declaration: Declaration,
-    // closure: Environment,
+    ///同一个函数绑定出的方法共享一份声明
+    declaration: Rc<Declaration>,
+    ///定义函数时所在的环境，调用时函数的环境以它为父环境
+    closure: Rc<RefCell<Environment>>,
+    ///方法被绑定到的实例，调用时会作为`this`定义在函数的环境中
+    this: Option<Rc<RefCell<LoxInstance>>>,
+    ///定义这个方法的类的父类，调用时会作为`super`定义在函数的环境中
+    superclass: Option<Rc<LoxClass>>,
+    ///是否是getter，读取到getter时会立即调用它，见[`LoxFunction::into_getter`]
+    getter: bool,
+    ///是否是类的`init`方法，见[`LoxFunction::into_initializer`]
+    initializer: bool,
 }
 
 impl LoxFunction {
@@ -30,18 +47,162 @@ impl LoxFunction {
         name: Token,
         params: Vec<Token>,
         body: Vec<Stmt>,
-        // environment: Environment,
+        closure: Rc<RefCell<Environment>>,
+    ) -> LoxFunction {
+        LoxFunction {
+            declaration: Rc::new(Declaration {
+                name: Some(name),
+                params,
+                body,
+            }),
+            closure,
+            this: None,
+            superclass: None,
+            getter: false,
+            initializer: false,
+        }
+    }
+
+    ///由匿名函数表达式产生的函数
+    pub fn lambda(
+        params: Vec<Token>,
+        body: Vec<Stmt>,
+        closure: Rc<RefCell<Environment>>,
     ) -> LoxFunction {
         LoxFunction {
-            declaration: Declaration { name, params, body },
+            declaration: Rc::new(Declaration {
+                name: None,
+                params,
+                body,
+            }),
+            closure,
+            this: None,
+            superclass: None,
+            getter: false,
+            initializer: false,
+        }
+    }
+
+    ///把方法所在类的父类记录下来，方法体中的`super`会指向它
+    pub fn with_superclass(mut self, superclass: Option<Rc<LoxClass>>) -> LoxFunction {
+        self.superclass = superclass;
+        self
+    }
+
+    ///把方法标记为getter，`instance.name`会直接调用它，而不是返回绑定后的方法
+    pub fn into_getter(mut self) -> LoxFunction {
+        self.getter = true;
+        self
+    }
+
+    pub fn is_getter(&self) -> bool {
+        self.getter
+    }
+
+    ///把方法标记为初始化方法，调用它总是返回绑定的实例，函数体中的`return;`只是提前结束
+    pub fn into_initializer(mut self) -> LoxFunction {
+        self.initializer = true;
+        self
+    }
+
+    ///把方法绑定到实例上，返回一个新的函数，函数体中的`this`指向这个实例
+    pub fn bind(&self, instance: Rc<RefCell<LoxInstance>>) -> LoxFunction {
+        LoxFunction {
+            declaration: Rc::clone(&self.declaration),
+            closure: Rc::clone(&self.closure),
+            this: Some(instance),
+            superclass: self.superclass.clone(),
+            getter: self.getter,
+            initializer: self.initializer,
+        }
+    }
+
+    ///把调用时的参数按参数列表排好顺序，`names`是`arguments`中最后几个具名参数的名字
+    ///
+    ///具名参数不是函数的参数、同一个参数被给出两次、或者有参数没有给出时返回运行时错误
+    pub fn order_arguments(
+        &self,
+        mut arguments: Vec<Value>,
+        names: &[Token],
+        paren: &Token,
+    ) -> Result<Vec<Value>, LoxResult> {
+        let error = |token: &Token, message: String| LoxResult::RuntimeError {
+            token: token.clone(),
+            message,
+        };
+        let params = &self.declaration.params;
+        let named = arguments.split_off(arguments.len() - names.len());
+        let mut ordered: Vec<Option<Value>> = arguments.into_iter().map(Some).collect();
+        if ordered.len() < params.len() {
+            ordered.resize(params.len(), None);
         }
+        for (name, value) in names.iter().zip(named) {
+            let index = params
+                .iter()
+                .position(|param| param.lexeme == name.lexeme)
+                .ok_or_else(|| error(name, format!("Unknown parameter '{}'.", name.lexeme)))?;
+            if ordered[index].is_some() {
+                return Err(error(
+                    name,
+                    format!("Argument '{}' is given more than once.", name.lexeme),
+                ));
+            }
+            ordered[index] = Some(value);
+        }
+        ordered
+            .into_iter()
+            .enumerate()
+            .map(|(index, value)| {
+                value.ok_or_else(|| {
+                    error(
+                        paren,
+                        format!("Missing argument '{}'.", params[index].lexeme),
+                    )
+                })
+            })
+            .collect()
+    }
+
+    ///是否是同一个函数：同一处声明、在同一个环境中创建，并且绑定到同一个实例
+    pub fn is_same(&self, other: &LoxFunction) -> bool {
+        Rc::ptr_eq(&self.declaration, &other.declaration)
+            && Rc::ptr_eq(&self.closure, &other.closure)
+            && match (&self.this, &other.this) {
+                (Some(a), Some(b)) => Rc::ptr_eq(a, b),
+                (None, None) => true,
+                _ => false,
+            }
+    }
+}
+
+///闭包环境中通常保存着函数自己，所以比较和调试输出都不深入闭包，
+///只有声明相同并且捕获的是同一个环境的两个函数才相等
+impl PartialEq for LoxFunction {
+    fn eq(&self, other: &Self) -> bool {
+        self.declaration == other.declaration
+            && Rc::ptr_eq(&self.closure, &other.closure)
+            && self.this == other.this
+            && self.superclass == other.superclass
+            && self.getter == other.getter
+            && self.initializer == other.initializer
+    }
+}
+
+impl fmt::Debug for LoxFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LoxFunction")
+            .field("declaration", &self.declaration)
+            .finish_non_exhaustive()
     }
 }
 
 ///为[`LoxFunction`] 实现 [`fmt::Display`] ,这样可以使用[`print`]打印出函数的类型
 impl fmt::Display for LoxFunction {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "<fn {}>", self.declaration.name.lexeme)
+        match &self.declaration.name {
+            Some(name) => write!(f, "<fn {}>", name.lexeme),
+            None => write!(f, "<fn>"),
+        }
     }
 }
 
@@ -49,23 +210,35 @@ impl LoxCallable for LoxFunction {
     fn call(
         &self,
         interpreter: &mut crate::interpreter::Interpreter,
+        _paren: &Token,
         arguments: Vec<Value>,
     ) -> Result<Value, LoxResult> {
-        // let mut env = self.closure.clone();
-        let mut env = interpreter.globals.clone();
+        let mut env = Environment::new_enclosing(Rc::clone(&self.closure));
+        if let Some(instance) = &self.this {
+            env.define("this".to_string(), Value::LoxInstance(Rc::clone(instance)));
+        }
+        if let Some(superclass) = &self.superclass {
+            env.define("super".to_string(), Value::LoxClass(Rc::clone(superclass)));
+        }
 
         for (index, token) in self.declaration.params.iter().enumerate() {
             env.define(token.lexeme.clone(), arguments[index].clone());
         }
 
-        match interpreter.execute_block(self.declaration.body.clone(), env) {
-            Err(LoxResult::ReturnValue { value }) => return Ok(value),
+        let result = interpreter.execute_block(self.declaration.body.clone(), env);
+        if let (true, Some(instance), Ok(_) | Err(LoxResult::ReturnValue { .. })) =
+            (self.initializer, &self.this, &result)
+        {
+            return Ok(Value::LoxInstance(Rc::clone(instance)));
+        }
+        match result {
+            Err(LoxResult::ReturnValue { value }) => Ok(value),
             Err(e) => Err(e),
-            Ok(value) => Ok(value),
+            Ok(_) => Ok(Value::Nil),
         }
     }
 
-    fn arity(&self) -> usize {
-        self.declaration.params.len()
+    fn arity(&self) -> Arity {
+        Arity::Fixed(self.declaration.params.len())
     }
 }
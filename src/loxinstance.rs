@@ -0,0 +1,53 @@
+use core::fmt;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::loxclass::LoxClass;
+use crate::loxresult::LoxResult;
+use crate::token::Token;
+use crate::value::Value;
+
+///定义了类的实例，实例保存了它所属的类和自己的字段
+#[derive(PartialEq, Clone, Debug)]
+pub struct LoxInstance {
+    class: Rc<LoxClass>,
+    fields: HashMap<String, Value>,
+}
+
+impl LoxInstance {
+    pub fn new(class: Rc<LoxClass>) -> LoxInstance {
+        LoxInstance {
+            class,
+            fields: HashMap::new(),
+        }
+    }
+
+    ///读取属性，先查找字段，再查找类中的方法
+    ///
+    ///找到的方法会绑定到这个实例上，所以要传入实例的[`Rc`]
+    pub fn get(instance: &Rc<RefCell<LoxInstance>>, name: &Token) -> Result<Value, LoxResult> {
+        if let Some(value) = instance.borrow().fields.get(&name.lexeme) {
+            return Ok(value.clone());
+        }
+        if let Some(method) = instance.borrow().class.find_method(&name.lexeme) {
+            return Ok(Value::LoxFunction(method.bind(Rc::clone(instance))));
+        }
+        Err(LoxResult::RuntimeError {
+            token: name.clone(),
+            message: format!("Undefined property '{}'.", name.lexeme),
+        })
+    }
+
+    ///给字段赋值，字段不存在时会新建
+    pub fn set(&mut self, name: &Token, value: Value) {
+        self.fields.insert(name.lexeme.clone(), value);
+    }
+}
+
+///为[`LoxInstance`] 实现 [`fmt::Display`] ,这样可以使用[`print`]打印出实例
+impl fmt::Display for LoxInstance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<{} instance>", self.class.name())
+    }
+}
@@ -1,5 +1,8 @@
 use crate::value::Value;
-use crate::{token::Token, Lox};
+use std::fmt;
+
+use crate::token::Token;
+use crate::token_type::TokenType;
 
 // pub(crate) struct LoxResult {
 //     pub(crate) token: Token,
@@ -16,23 +19,64 @@ pub enum LoxResult {
         token: Token,
         message: String,
     },
+    ///词法分析时的错误，此时还没有token，只知道出错的字符所在的行和列
+    ScanError {
+        line: i32,
+        column: i32,
+        message: String,
+    },
     ///当要在函数体中提前返回时，会触发这个异常
     ReturnValue {
         value: Value,
     },
-    ///循环语句中返回
+    ///`break`语句跳出循环时产生，由最近的[`crate::stmt::Stmt::While`]接住
     Break,
+    ///`continue`语句结束本次循环时产生，循环会继续执行下一次
+    Continue,
+    ///增量解析无法处理这次修改，需要重新解析整个文件
+    FullReparse,
+    ///原生函数`exit`结束整个程序时产生，`code`是进程的退出码，见[`crate::interpreter::RunOutcome`]
+    Exit {
+        code: i32,
+    },
 }
 
-impl LoxResult {
-    pub fn error(&self) -> Self {
+///错误信息的格式，包含有行号和列号
+impl fmt::Display for LoxResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            LoxResult::RuntimeError { token, message }
-            | LoxResult::ParseError { token, message } => {
-                Lox::error_with_token(&token, message);
-                return self.clone();
+            LoxResult::RuntimeError { token, message } => {
+                write!(
+                    f,
+                    "[line {}, column {}] {}  ",
+                    token.line, token.column, message
+                )
+            }
+            LoxResult::ParseError { token, message } => {
+                let location = if token.token_type == TokenType::EOF {
+                    " at end".to_string()
+                } else {
+                    format!(" at ' {} '", token.lexeme)
+                };
+                write!(
+                    f,
+                    "[line {}, column {}] Error {}: {}",
+                    token.line, token.column, location, message
+                )
+            }
+            LoxResult::ScanError {
+                line,
+                column,
+                message,
+            } => {
+                write!(f, "[line {}, column {}] Error : {}", line, column, message)
+            }
+            LoxResult::ReturnValue { .. } => write!(f, "Can't return from top-level code."),
+            LoxResult::Break | LoxResult::Continue => {
+                write!(f, "Can't use loop control outside of a loop.")
             }
-            _ => unreachable!(),
+            LoxResult::FullReparse => write!(f, "The whole file needs to be parsed again."),
+            LoxResult::Exit { code } => write!(f, "Exited with code {}.", code),
         }
     }
 }
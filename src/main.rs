@@ -1,198 +1,558 @@
-/*!
-这个项目使用rust编写，是一个`Tree-walk Interpret`, 处理了名为lox的语言，以下为lox的语法
-
-```text
-## Syntax Grammer
-program        → declaration* EOF ;
-
-## Declarations
-declaration    →  funDecl
-                | varDecl
-                | statement ;
-
-                  "{" function* "}" ;
-funDecl        → "fun" function ;
-varDecl        → "var" IDENTIFIER ( "=" expression )? ";" ;
-statement      → exprStmt
-               | forStmt
-               | ifStmt
-               | printStmt
-               | returnStmt
-               | whileStmt
-               | block ;
-
-exprStmt       → expression ";" ;
-forStmt        → "for" "(" ( varDecl | exprStmt | ";" )
-                           expression? ";"
-                           expression? ")" statement ;
-ifStmt         → "if" "(" expression ")" statement
-                 ( "else" statement )? ;
-printStmt      → "print" expression ";" ;
-returnStmt     → "return" expression? ";" ;
-whileStmt      → "while" "(" expression ")" statement ;
-block          → "{" declaration* "}" ;
-
-##Expressios
-expression     → assignment ;
-
-assignment     → ( call "." )? IDENTIFIER "=" assignment
-               | logic_or ;
-
-logic_or       → logic_and ( "or" logic_and )* ;
-logic_and      → equality ( "and" equality )* ;
-equality       → comparison ( ( "!=" | "==" ) comparison )* ;
-comparison     → term ( ( ">" | ">=" | "<" | "<=" ) term )* ;
-term           → factor ( ( "-" | "+" ) factor )* ;
-factor         → unary ( ( "/" | "*" ) unary )* ;
-
-unary          → ( "!" | "-" ) unary | call ;
-call           → primary ( "(" arguments? ")" | "." IDENTIFIER )* ;
-primary        → "true" | "false" | "nil" | "this"
-               | NUMBER | STRING | IDENTIFIER | "(" expression ")"
-               | "super" "." IDENTIFIER ;
-
-## Utility rules
-function       → IDENTIFIER "(" parameters? ")" block ;
-parameters     → IDENTIFIER ( "," IDENTIFIER )* ;
-arguments      → expression ( "," expression )* ;
-
-## Lexical Grammer
-NUMBER         → DIGIT+ ( "." DIGIT+ )? ;
-STRING         → "\"" <any char except "\"">* "\"" ;
-IDENTIFIER     → ALPHA ( ALPHA | DIGIT )* ;
-ALPHA          → "a" ... "z" | "A" ... "Z" | "_" ;
-DIGIT          → "0" ... "9" ;```!*/
-mod ast_printer;
-pub mod environment;
-pub mod expr;
-pub mod interpreter;
-pub mod loxcallable;
-pub mod loxfunction;
-pub mod loxresult;
-pub mod parser;
-pub mod scanner;
-pub mod stmt;
-pub mod token;
-pub mod token_type;
-pub mod value;
-
-use interpreter::Interpreter;
-use loxresult::LoxResult;
-use once_cell::sync::Lazy;
-use scanner::Scanner;
-use token::Token;
-use token_type::TokenType;
-
-///定义lox结构体
-struct Lox {
-    ///整个解释器的环境
-    interpreter: Interpreter,
-    ///是否在编译期发生错误
-    had_error: bool,
-    ///是否在运行期发生错误
-    had_runtime_error: bool,
-}
-
-///使用lazy初始化LOX变量，LOX变量是一个全局变量，在整个解释器运行期间存在
-///它的类型是[`Lox`]
-static mut LOX: Lazy<Lox> = Lazy::new(Lox::new);
-
-///根据输入的参数个数进入不同的模式，如果参数个数小于二，那么进入本解释器的repl模式
+//! rlox命令行程序，库的用法见[`lox`]
+use lox::interpreter::{RunOutcome, STACK_SIZE};
+use lox::loxresult::LoxResult;
+use lox::Lox;
+use std::io::Write;
+use std::path::PathBuf;
+
+///根据输入的参数进入不同的模式，如果没有给出脚本，那么进入本解释器的repl模式
+///
+///`--dump-env-on-error <dir>`：发生运行时错误时，把环境写入`dir`目录下的文件
+///
+///`--dump-ast`：打印脚本解析得到的语法树而不执行它，必须给出脚本
+///
+///`--trace`：把执行的每条语句和它的结果打印到stderr，repl中同样有效
+///
+///`--time`：执行之后把词法分析、语法分析、变量解析和执行各自的耗时打印到stderr，repl中每行都会打印
+///
+///`--seed <n>`：设置`rand`和`rand_int`的随机数种子，使每次运行产生同样的随机数
+///
+///`--coverage`：执行脚本之后把执行过的行数、可以执行的总行数和没有执行过的行打印到stderr，repl中无效
+///
+///`--quiet`：repl启动时不打印版本信息，读取每行之前也不打印`> `提示符
+///
+///`-e <source>`或`--eval <source>`：执行命令行中给出的代码而不是脚本文件，退出码与脚本相同
+///
+///解释器在一个栈更大的线程中运行，见[`STACK_SIZE`]，这样深层递归会在达到调用层数限制时产生运行时错误，
+///而不是让整个进程崩溃
 pub fn main() {
-    let args: Vec<String> = std::env::args().collect();
-    if args.len() > 2 {
-        println!("Usage: rlox [script]");
-        std::process::exit(64);
-    } else if args.len() == 2 {
-        Lox::run_file(args[1].clone());
-    } else {
-        Lox::run_prompt();
+    let interpreter = std::thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(run)
+        .expect("failed to spawn the interpreter thread");
+    if interpreter.join().is_err() {
+        std::process::exit(101);
     }
 }
 
-///定义了Lox结构体的方法
-impl Lox {
-    pub(crate) fn new() -> Self {
-        Lox {
-            had_error: false,
-            had_runtime_error: false,
-            interpreter: Interpreter::new(),
+fn run() {
+    let mut args = std::env::args().skip(1);
+    let mut script = None;
+    let mut eval = None;
+    let mut dump_ast = false;
+    let mut trace = false;
+    let mut time = false;
+    let mut coverage = false;
+    let mut seed = None;
+    let mut quiet = false;
+    let mut dump_dir = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dump-ast" => dump_ast = true,
+            "--trace" => trace = true,
+            "--time" => time = true,
+            "--coverage" => coverage = true,
+            "--quiet" => quiet = true,
+            "--seed" => match args.next().and_then(|seed| seed.parse::<u64>().ok()) {
+                Some(n) => seed = Some(n),
+                None => usage(),
+            },
+            "--dump-env-on-error" => match args.next() {
+                Some(dir) => dump_dir = Some(PathBuf::from(dir)),
+                None => usage(),
+            },
+            "-e" | "--eval" => match args.next() {
+                Some(source) if eval.is_none() => eval = Some(source),
+                _ => usage(),
+            },
+            _ if script.is_none() => script = Some(arg),
+            _ => usage(),
         }
     }
-
-    ///对文件进行解释
-    pub fn run_file(path: String) -> Result<(), std::io::Error> {
-        let source = std::fs::read_to_string(path)?;
-        Self::run(source);
-        if unsafe { LOX.had_error } {
-            std::process::exit(65);
+    let mut lox = Lox::with_trace(trace);
+    lox.set_time(time);
+    lox.set_coverage(coverage);
+    if let Some(seed) = seed {
+        lox.set_seed(seed);
+    }
+    if let Some(dir) = dump_dir {
+        lox.set_dump_on_error(dir);
+    }
+    let result = match (script, eval) {
+        (Some(_), Some(_)) => usage(),
+        (Some(script), None) => {
+            let source = read_script(&script).unwrap_or_else(|message| {
+                eprintln!("{}", message);
+                std::process::exit(66);
+            });
+            if dump_ast {
+                print_ast(&mut lox, &source);
+            } else {
+                run_source(&mut lox, &source);
+            }
+            Ok(())
         }
-        if unsafe { LOX.had_runtime_error } {
-            std::process::exit(70);
+        (None, Some(source)) if dump_ast => {
+            print_ast(&mut lox, &source);
+            Ok(())
         }
-        Ok(())
-    }
-
-    ///执行解释器的repl模式
-    pub fn run_prompt() -> Result<(), std::io::Error> {
-        loop {
-            // print!("> ");
-            let mut line = String::new();
-            std::io::stdin().read_line(&mut line)?;
-            Self::run(line);
-            unsafe {
-                LOX.had_error = false;
-                LOX.had_runtime_error = false;
-            }
+        (None, Some(source)) => {
+            run_source(&mut lox, &source);
+            Ok(())
         }
+        (None, None) if dump_ast => usage(),
+        (None, None) => run_prompt(&mut lox, quiet),
+    };
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        std::process::exit(74);
+    }
+}
+
+fn usage() -> ! {
+    println!(
+        "Usage: rlox [--dump-env-on-error dir] [--dump-ast] [--trace] [--time] [--coverage] [--seed n] [--quiet] [-e source | script]"
+    );
+    std::process::exit(64);
+}
+
+///读取脚本文件，文件不存在或无法读取时返回给用户看的错误信息，`main`打印它之后以66退出
+fn read_script(path: &str) -> Result<String, String> {
+    std::fs::read_to_string(path).map_err(|e| format!("Can't read script '{}': {}.", path, e))
+}
+
+///执行一段完整的程序，出错时以[`exit_code`]给出的退出码退出
+fn run_source(lox: &mut Lox, source: &str) {
+    let result = lox.interpret_source(source);
+    print_phase_times(lox);
+    if let Some(coverage) = lox.coverage() {
+        eprint!("{}", coverage);
+    }
+    if let Err(errors) = result {
+        std::process::exit(exit_code(&errors));
+    }
+}
+
+///打开了`--time`时把最近一次执行各阶段的耗时打印到stderr
+fn print_phase_times(lox: &Lox) {
+    if let Some(times) = lox.phase_times() {
+        eprint!("{}", times);
+    }
+}
+
+///编译期错误以65退出，程序执行之后的退出码见[`RunOutcome::exit_code`]
+fn exit_code(errors: &[LoxResult]) -> i32 {
+    if errors
+        .iter()
+        .any(|e| matches!(e, LoxResult::RuntimeError { .. } | LoxResult::Exit { .. }))
+    {
+        RunOutcome::from_errors(errors.to_vec()).exit_code()
+    } else {
+        65
     }
+}
 
-    ///对lox语言进行编译与执行
-    pub fn run(source: String) {
-        let scanner = Scanner::new(source);
-        let tokens = scanner.scan_tokens();
-        let mut parser = parser::Parser::new(tokens);
-        let statements = parser.parse();
-        if unsafe { LOX.had_error } {
-            return;
+///repl中的程序调用了`exit`时，以它给出的退出码结束整个repl
+fn exit_if_requested<T>(result: &Result<T, Vec<LoxResult>>) {
+    if let Err(errors) = result {
+        if let Some(LoxResult::Exit { code }) = errors.last() {
+            std::process::exit(*code);
         }
+    }
+}
 
-        unsafe { LOX.interpreter.interpret(statements) }
+///打印程序的语法树，有编译期错误时以65退出
+fn print_ast(lox: &mut Lox, source: &str) {
+    match lox.dump_ast(source) {
+        Ok(ast) => print!("{}", ast),
+        Err(_) => std::process::exit(65),
     }
+}
 
-    ///向stderr打印出发生执行期错误的行数
-    pub(crate) fn runtime_error(error: LoxResult) {
-        //123
-        match error {
-            LoxResult::RuntimeError { token, message }
-            | LoxResult::ParseError { token, message } => {
-                eprintln!("[line {}] {}  ", token.line, message)
+///执行解释器的repl模式，出错之后继续读取下一行，只输入一个表达式时会打印它的值
+///
+///括号没有闭合或者行尾是`\`时会继续读取下一行，拼在一起之后再执行
+///
+///以`:`开头的行是repl命令而不是lox代码，见[`parse_command`]
+fn run_prompt(lox: &mut Lox, quiet: bool) -> Result<(), std::io::Error> {
+    repl(
+        lox,
+        |line| std::io::stdin().read_line(line),
+        &mut std::io::stdout(),
+        quiet,
+    )
+}
+
+///repl的主循环，从`read_line`读取输入，版本信息和提示符写入`out`，`quiet`为true时两者都不打印
+///
+///每次读取都直接调用`read_line`而不是持有stdin的锁，这样原生函数`readLine`也可以读取stdin
+fn repl(
+    lox: &mut Lox,
+    mut read_line: impl FnMut(&mut String) -> std::io::Result<usize>,
+    out: &mut impl Write,
+    quiet: bool,
+) -> Result<(), std::io::Error> {
+    if !quiet {
+        writeln!(
+            out,
+            "rlox {} (type :quit to exit)",
+            env!("CARGO_PKG_VERSION")
+        )?;
+    }
+    let mut buffer = String::new();
+    loop {
+        //提示符没有换行，需要立即写出，否则在等待输入时看不到它
+        if !quiet {
+            write!(out, "> ")?;
+            out.flush()?;
+        }
+        let mut line = String::new();
+        if read_line(&mut line)? == 0 {
+            //输入结束时执行剩下的内容，没有闭合的括号会作为语法错误报告
+            if !buffer.trim().is_empty() {
+                let result = lox.interpret_line(&buffer);
+                print_phase_times(lox);
+                exit_if_requested(&result);
+            }
+            return Ok(());
+        }
+        //继续读取的行属于前面的代码，不是命令
+        if buffer.is_empty() {
+            match parse_command(&line) {
+                Some(Ok(command)) => {
+                    if !run_command(lox, command) {
+                        return Ok(());
+                    }
+                    continue;
+                }
+                Some(Err(message)) => {
+                    eprintln!("{}", message);
+                    continue;
+                }
+                None => {}
             }
-            _ => unreachable!(),
         }
-        unsafe {
-            LOX.had_runtime_error = true;
+        let trimmed = line.trim_end_matches(['\n', '\r']);
+        if let Some(continued) = trimmed.strip_suffix('\\') {
+            buffer.push_str(continued);
+            buffer.push('\n');
+            continue;
         }
+        buffer.push_str(&line);
+        if needs_more_input(&buffer) {
+            continue;
+        }
+        let result = lox.interpret_line(&buffer);
+        print_phase_times(lox);
+        exit_if_requested(&result);
+        buffer.clear();
     }
+}
+
+///repl命令
+#[derive(Debug, PartialEq)]
+enum Command {
+    ///`:load <file>`：在当前的会话中执行脚本文件，之后可以使用其中定义的变量
+    Load(String),
+    ///`:reset`：丢掉所有定义过的变量，见[`Lox::reset`]
+    Reset,
+    ///`:quit`：退出repl
+    Quit,
+}
+
+///解析repl命令，不以`:`开头的行是lox代码，返回`None`；lox代码不会以`:`开头，所以两者不会混淆
+fn parse_command(line: &str) -> Option<Result<Command, String>> {
+    let line = line.trim().strip_prefix(':')?;
+    let (name, argument) = match line.split_once(char::is_whitespace) {
+        Some((name, argument)) => (name, argument.trim()),
+        None => (line, ""),
+    };
+    Some(match (name, argument) {
+        ("load", "") => Err("Usage: :load <file>".to_string()),
+        ("load", path) => Ok(Command::Load(path.to_string())),
+        ("reset", "") => Ok(Command::Reset),
+        ("quit", "") => Ok(Command::Quit),
+        _ => Err(format!("Unknown command ':{}'.", line)),
+    })
+}
 
-    ///打印错误信息，包含有行号
-    pub fn error_with_line(line: i32, message: &str) {
-        Self::report(line, "", message);
+///执行repl命令，`:quit`时返回false
+fn run_command(lox: &mut Lox, command: Command) -> bool {
+    match command {
+        Command::Load(path) => match read_script(&path) {
+            Ok(source) => {
+                let result = lox.interpret_source(&source);
+                print_phase_times(lox);
+                exit_if_requested(&result);
+            }
+            Err(message) => eprintln!("{}", message),
+        },
+        Command::Reset => lox.reset(),
+        Command::Quit => return false,
     }
+    true
+}
 
-    ///打印错误信息，包含有无法解析的字符token
-    pub fn error_with_token(token: &Token, message: &str) {
-        if token.token_type == TokenType::EOF {
-            Self::report(token.line, " at end", message);
+///粗略地扫描输入，判断是否还有没闭合的`(`、`{`、字符串或块注释
+///
+///多出来的右括号不需要更多输入，交给parser报告错误
+fn needs_more_input(source: &str) -> bool {
+    let mut depth = 0;
+    let mut comment_depth = 0;
+    let mut in_string = false;
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+        if in_string {
+            match c {
+                '\\' => {
+                    chars.next();
+                }
+                '"' => in_string = false,
+                _ => {}
+            }
+        } else if comment_depth > 0 {
+            match (c, chars.peek()) {
+                ('/', Some('*')) => {
+                    chars.next();
+                    comment_depth += 1;
+                }
+                ('*', Some('/')) => {
+                    chars.next();
+                    comment_depth -= 1;
+                }
+                _ => {}
+            }
         } else {
-            Self::report(token.line, &format!(" at ' {} '", token.lexeme), message);
+            match (c, chars.peek()) {
+                ('/', Some('/')) => while chars.next_if(|&c| c != '\n').is_some() {},
+                ('/', Some('*')) => {
+                    chars.next();
+                    comment_depth += 1;
+                }
+                ('"', _) => in_string = true,
+                ('(' | '{', _) => depth += 1,
+                (')' | '}', _) => depth -= 1,
+                _ => {}
+            }
         }
     }
-    ///打印出发生编译器错误的行数
-    pub fn report(line: i32, location: &str, message: &str) {
-        eprintln!("[line {}] Error {}: {}", line, location, message);
-        unsafe {
-            LOX.had_error = true;
+    in_string || comment_depth > 0 || depth > 0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use lox::value::Value;
+    use std::{cell::RefCell, rc::Rc, time::Duration};
+
+    #[test]
+    fn test_needs_more_input() {
+        assert!(!needs_more_input("print 1;\n"));
+        assert!(needs_more_input("fun f() {\n"));
+        assert!(needs_more_input("fun f() {\n  print (1 +\n"));
+        assert!(!needs_more_input("fun f() {\n  print (1 + 2);\n}\n"));
+        assert!(!needs_more_input("print \"{\";\n"));
+        assert!(needs_more_input("print \"a\n"));
+        assert!(!needs_more_input("print 1; // {\n"));
+        assert!(needs_more_input("/* {\n"));
+        assert!(!needs_more_input("}\n"));
+    }
+
+    ///把写入的内容和读取的时机按顺序记录到同一个字符串中
+    #[derive(Clone, Default)]
+    struct Transcript(Rc<RefCell<String>>);
+
+    impl Write for Transcript {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().push_str(&String::from_utf8_lossy(buf));
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
         }
     }
+
+    #[test]
+    fn test_repl_prompt() {
+        let transcript = Transcript::default();
+        let log = transcript.clone();
+        let mut lines = vec!["var a = 1;\n", ":reset\n"].into_iter();
+        let read_line = move |line: &mut String| {
+            log.0.borrow_mut().push_str("<read>");
+            Ok(lines.next().map_or(0, |next| {
+                line.push_str(next);
+                next.len()
+            }))
+        };
+        repl(&mut Lox::new(), read_line, &mut transcript.clone(), false).unwrap();
+        assert_eq!(
+            transcript.0.borrow().as_str(),
+            format!(
+                "rlox {} (type :quit to exit)\n> <read>> <read>> <read>",
+                env!("CARGO_PKG_VERSION")
+            )
+        );
+
+        let quiet = Transcript::default();
+        repl(&mut Lox::new(), |_| Ok(0), &mut quiet.clone(), true).unwrap();
+        assert_eq!(quiet.0.borrow().as_str(), "");
+    }
+
+    #[test]
+    fn test_read_missing_script() {
+        let path = std::env::temp_dir().join("rlox-missing-script.lox");
+        let path = path.to_str().unwrap();
+        let message = read_script(path).unwrap_err();
+        assert!(
+            message.starts_with(&format!("Can't read script '{}': ", path)),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(parse_command("print 1;\n"), None);
+        assert_eq!(parse_command("a ? b : c;\n"), None);
+        assert_eq!(
+            parse_command(":load  lib/a b.lox \n"),
+            Some(Ok(Command::Load("lib/a b.lox".to_string())))
+        );
+        assert_eq!(parse_command(" :reset\n"), Some(Ok(Command::Reset)));
+        assert_eq!(parse_command(":quit\n"), Some(Ok(Command::Quit)));
+        assert!(matches!(parse_command(":load\n"), Some(Err(_))));
+        assert_eq!(
+            parse_command(":reset now\n"),
+            Some(Err("Unknown command ':reset now'.".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_reset_command() {
+        let mut lox = Lox::new();
+        lox.set_error_sink(|_| {});
+        lox.interpret_source("var a = 1;\nfun f() { return a; }")
+            .unwrap();
+        assert!(run_command(&mut lox, Command::Reset));
+        assert!(lox.interpret_source("a;").is_err());
+        assert!(lox.interpret_source("f;").is_err());
+        //原生函数仍然可用，可以重新定义变量
+        lox.interpret_source("var a = len(\"ab\");").unwrap();
+        assert_eq!(lox.interpret_source("a;").unwrap(), Value::Number(2.0));
+        assert!(!run_command(&mut lox, Command::Quit));
+    }
+
+    #[test]
+    fn test_repl_lines_share_globals() {
+        let mut lox = Lox::new();
+        lox.set_output(std::io::sink());
+        lox.set_error_sink(|_| {});
+        lox.interpret_line("var a = 1;\n").unwrap();
+        assert_eq!(lox.interpret_line("a\n").unwrap(), Value::Number(1.0));
+
+        //函数和闭包在之后的行中仍然可以使用，出错的行不会留下块中的作用域
+        lox.interpret_line("fun add(n) { return a + n; }\n")
+            .unwrap();
+        assert!(lox.interpret_line("{ var b = 2; -nil; }\n").is_err());
+        assert!(lox.interpret_line("b\n").is_err());
+        lox.interpret_line("a = add(10);\n").unwrap();
+        assert_eq!(lox.interpret_line("add(a)\n").unwrap(), Value::Number(22.0));
+    }
+
+    #[test]
+    fn test_exit_code() {
+        let mut lox = Lox::new();
+        lox.set_error_sink(|_| {});
+        assert!(lox.interpret_source("print 1 + 2;").is_ok());
+        assert_eq!(
+            exit_code(&lox.interpret_source("print 1 +;").unwrap_err()),
+            65
+        );
+        assert_eq!(
+            exit_code(&lox.interpret_source("print -nil;").unwrap_err()),
+            70
+        );
+        assert_eq!(exit_code(&lox.interpret_source("return;").unwrap_err()), 65);
+        assert_eq!(
+            exit_code(&lox.interpret_source("print 1;\nexit(4);").unwrap_err()),
+            4
+        );
+    }
+
+    #[test]
+    fn test_phase_times() {
+        let mut lox = Lox::new();
+        lox.interpret_source("var a = 1;").unwrap();
+        assert!(lox.phase_times().is_none());
+
+        lox.set_time(true);
+        lox.interpret_source("fun f(n) { return n * 2; }\nvar b = f(a);")
+            .unwrap();
+        let output = lox.phase_times().unwrap().to_string();
+        let phases: Vec<_> = output
+            .lines()
+            .map(|line| {
+                assert!(line.ends_with(" ms"), "{}", line);
+                line.split_whitespace().next().unwrap()
+            })
+            .collect();
+        assert_eq!(phases, ["scan", "parse", "resolve", "interpret"]);
+
+        //语法错误之后的阶段没有执行
+        lox.set_error_sink(|_| {});
+        assert!(lox.interpret_source("print 1 +;").is_err());
+        let times = lox.phase_times().unwrap();
+        assert_eq!(times.resolve + times.interpret, Duration::ZERO);
+    }
+
+    #[test]
+    fn test_coverage() {
+        let mut lox = Lox::new();
+        assert!(lox.coverage().is_none());
+
+        lox.set_coverage(true);
+        lox.set_output(std::io::sink());
+        lox.interpret_source(
+            "fun f(n) {\n  if (n > 0) {\n    return 1;\n  } else {\n    return 2;\n  }\n}\nprint f(1);",
+        )
+        .unwrap();
+        let coverage = lox.coverage().unwrap();
+        assert_eq!(
+            coverage.executable.iter().copied().collect::<Vec<_>>(),
+            [1, 2, 3, 4, 5, 8]
+        );
+        assert_eq!(coverage.uncovered(), [4, 5]);
+        assert_eq!(
+            coverage.to_string(),
+            "coverage: 4/6 lines executed (66.7%)\nnot executed: 4, 5\n"
+        );
+
+        lox.interpret_source("print f(0);").unwrap();
+        assert_eq!(
+            lox.coverage().unwrap().to_string(),
+            "coverage: 6/6 lines executed (100.0%)\n"
+        );
+    }
+
+    #[test]
+    fn test_error_native() {
+        let messages = Rc::new(RefCell::new(Vec::new()));
+        let sink = Rc::clone(&messages);
+        let mut lox = Lox::new();
+        lox.set_error_sink(move |error| sink.borrow_mut().push(error.to_string()));
+
+        let errors = lox
+            .interpret_source(
+                "var a = 1;\nif (a > 0) error(\"bad value\");\nprint \"unreachable\";",
+            )
+            .unwrap_err();
+        assert_eq!(exit_code(&errors), 70);
+        let errors = lox.interpret_source("error(42);").unwrap_err();
+        assert_eq!(exit_code(&errors), 70);
+        assert_eq!(
+            messages.borrow().as_slice(),
+            ["[line 2, column 29] bad value  ", "[line 1, column 9] 42  "]
+        );
+    }
 }
@@ -0,0 +1,575 @@
+//! natives.rs 定义了用rust实现、在lox中可以直接调用的原生函数
+
+use core::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::environment::Environment;
+use crate::interpreter::Interpreter;
+use crate::json;
+use crate::loxcallable::{Arity, LoxCallable};
+use crate::loxresult::LoxResult;
+use crate::token::Token;
+use crate::value::Value;
+
+///原生函数的实现，`&Token`是调用处的右括号，用于报告运行时错误
+pub type NativeFn = fn(&mut Interpreter, &Token, Vec<Value>) -> Result<Value, LoxResult>;
+
+///定义了原生函数
+#[derive(Clone, Debug)]
+pub struct NativeFunction {
+    ///函数名称
+    name: &'static str,
+    ///参数个数
+    arity: Arity,
+    function: NativeFn,
+}
+
+impl NativeFunction {
+    pub fn new(name: &'static str, arity: usize, function: NativeFn) -> NativeFunction {
+        NativeFunction {
+            name,
+            arity: Arity::Fixed(arity),
+            function,
+        }
+    }
+
+    ///接受至少`min_arity`个参数的原生函数，所有参数都会传给`function`
+    pub fn variadic(name: &'static str, min_arity: usize, function: NativeFn) -> NativeFunction {
+        NativeFunction {
+            name,
+            arity: Arity::AtLeast(min_arity),
+            function,
+        }
+    }
+}
+
+///同名的原生函数就是同一个函数
+impl PartialEq for NativeFunction {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+///为[`NativeFunction`] 实现 [`fmt::Display`] ,这样可以使用[`print`]打印出函数的类型
+impl fmt::Display for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<native fn {}>", self.name)
+    }
+}
+
+impl LoxCallable for NativeFunction {
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        paren: &Token,
+        arguments: Vec<Value>,
+    ) -> Result<Value, LoxResult> {
+        (self.function)(interpreter, paren, arguments)
+    }
+
+    fn arity(&self) -> Arity {
+        self.arity
+    }
+}
+
+///把所有原生函数定义到全局环境中
+pub fn define_natives(globals: &mut Environment) {
+    let natives = [
+        NativeFunction::new("clock", 0, clock),
+        NativeFunction::new("push", 2, push),
+        NativeFunction::new("len", 1, len),
+        NativeFunction::new("str", 1, str),
+        NativeFunction::new("num", 1, num),
+        NativeFunction::new("readLine", 0, read_line),
+        NativeFunction::new("error", 1, error),
+        NativeFunction::new("exit", 1, exit),
+        NativeFunction::new("typeof", 1, type_of),
+        NativeFunction::variadic("format", 1, format),
+        NativeFunction::new("print_raw", 1, print_raw),
+        NativeFunction::variadic("println", 0, println),
+        NativeFunction::new("contains", 2, contains),
+        NativeFunction::new("index_of", 2, index_of),
+        NativeFunction::new("json_stringify", 1, json_stringify),
+        NativeFunction::new("json_parse", 1, json_parse),
+        NativeFunction::new("pow", 2, pow),
+        NativeFunction::new("sqrt", 1, sqrt),
+        NativeFunction::new("floor", 1, floor),
+        NativeFunction::new("ceil", 1, ceil),
+        NativeFunction::new("abs", 1, abs),
+        NativeFunction::new("round", 1, round),
+        NativeFunction::new("sign", 1, sign),
+        NativeFunction::new("gcd", 2, gcd),
+        NativeFunction::new("lcm", 2, lcm),
+        NativeFunction::new("rand", 0, rand),
+        NativeFunction::new("rand_int", 2, rand_int),
+    ];
+    for native in natives {
+        globals.define(native.name.to_string(), Value::NativeFunction(native));
+    }
+}
+
+///返回从Unix纪元开始到现在的秒数
+fn clock(
+    _interpreter: &mut Interpreter,
+    _paren: &Token,
+    _arguments: Vec<Value>,
+) -> Result<Value, LoxResult> {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs_f64())
+        .unwrap_or_default();
+    Ok(Value::Number(seconds))
+}
+
+///把值追加到列表的末尾，返回nil
+fn push(
+    _interpreter: &mut Interpreter,
+    paren: &Token,
+    arguments: Vec<Value>,
+) -> Result<Value, LoxResult> {
+    match &arguments[0] {
+        Value::List(list) => {
+            list.borrow_mut().push(arguments[1].clone());
+            Ok(Value::Nil)
+        }
+        _ => Err(LoxResult::RuntimeError {
+            token: paren.clone(),
+            message: "Can only push to a list.".to_string(),
+        }),
+    }
+}
+
+///返回字符串或列表的长度，字符串按Unicode字符计数而不是字节
+fn len(
+    _interpreter: &mut Interpreter,
+    paren: &Token,
+    arguments: Vec<Value>,
+) -> Result<Value, LoxResult> {
+    match &arguments[0] {
+        Value::String(s) => Ok(Value::Number(s.chars().count() as f64)),
+        Value::List(list) => Ok(Value::Number(list.borrow().len() as f64)),
+        _ => Err(LoxResult::RuntimeError {
+            token: paren.clone(),
+            message: "Can only get the length of a string or list.".to_string(),
+        }),
+    }
+}
+
+///`contains(collection, value)`：字符串中是否包含子串`value`，或者列表中是否有和`value`相等的元素
+fn contains(
+    _interpreter: &mut Interpreter,
+    paren: &Token,
+    arguments: Vec<Value>,
+) -> Result<Value, LoxResult> {
+    Ok(Value::Boolean(find(paren, &arguments)?.is_some()))
+}
+
+///`index_of(collection, value)`：子串或元素第一次出现的位置，没有找到时返回-1
+fn index_of(
+    _interpreter: &mut Interpreter,
+    paren: &Token,
+    arguments: Vec<Value>,
+) -> Result<Value, LoxResult> {
+    let index = find(paren, &arguments)?;
+    Ok(Value::Number(index.map_or(-1.0, |index| index as f64)))
+}
+
+///在第一个参数中查找第二个参数，`contains`和`index_of`共用
+///
+///字符串和[`len`]一样按Unicode字符计数，列表元素用[`Value::is_equal`]比较
+fn find(paren: &Token, arguments: &[Value]) -> Result<Option<usize>, LoxResult> {
+    let error = |message: &str| LoxResult::RuntimeError {
+        token: paren.clone(),
+        message: message.to_string(),
+    };
+    match (&arguments[0], &arguments[1]) {
+        (Value::String(s), Value::String(sub)) => {
+            Ok(s.find(sub.as_str()).map(|i| s[..i].chars().count()))
+        }
+        (Value::String(_), _) => Err(error("Can only search a string for a string.")),
+        (Value::List(list), value) => Ok(list.borrow().iter().position(|e| e.is_equal(value))),
+        _ => Err(error("Can only search a string or list.")),
+    }
+}
+
+///把值转换成JSON字符串，见[`crate::json`]，无法转换的值产生运行时错误
+fn json_stringify(
+    _interpreter: &mut Interpreter,
+    paren: &Token,
+    arguments: Vec<Value>,
+) -> Result<Value, LoxResult> {
+    json::stringify(&arguments[0])
+        .map(Value::String)
+        .map_err(|message| LoxResult::RuntimeError {
+            token: paren.clone(),
+            message,
+        })
+}
+
+///把JSON字符串解析成lox的值，不是合法的JSON时产生运行时错误
+fn json_parse(
+    _interpreter: &mut Interpreter,
+    paren: &Token,
+    arguments: Vec<Value>,
+) -> Result<Value, LoxResult> {
+    let error = |message: String| LoxResult::RuntimeError {
+        token: paren.clone(),
+        message,
+    };
+    match &arguments[0] {
+        Value::String(s) => json::parse(s).map_err(error),
+        _ => Err(error("Can only parse a string as JSON.".to_string())),
+    }
+}
+
+///把任意值转换成它打印出来的字符串
+fn str(
+    _interpreter: &mut Interpreter,
+    _paren: &Token,
+    arguments: Vec<Value>,
+) -> Result<Value, LoxResult> {
+    Ok(Value::String(arguments[0].to_string()))
+}
+
+///把字符串解析成数字，忽略首尾的空白，无法解析时返回运行时错误
+fn num(
+    _interpreter: &mut Interpreter,
+    paren: &Token,
+    arguments: Vec<Value>,
+) -> Result<Value, LoxResult> {
+    let s = match &arguments[0] {
+        Value::String(s) => s,
+        _ => {
+            return Err(LoxResult::RuntimeError {
+                token: paren.clone(),
+                message: "Can only convert a string to a number.".to_string(),
+            })
+        }
+    };
+    match s.trim().parse::<f64>() {
+        Ok(n) if n.is_finite() => Ok(Value::Number(n)),
+        _ => Err(LoxResult::RuntimeError {
+            token: paren.clone(),
+            message: format!("Can't convert '{}' to a number.", s),
+        }),
+    }
+}
+
+///产生一个指向调用处的运行时错误，参数打印出来的样子就是错误信息
+fn error(
+    _interpreter: &mut Interpreter,
+    paren: &Token,
+    arguments: Vec<Value>,
+) -> Result<Value, LoxResult> {
+    Err(LoxResult::RuntimeError {
+        token: paren.clone(),
+        message: arguments[0].to_string(),
+    })
+}
+
+///`exit(code)`结束整个程序，`code`是进程的退出码，必须是0到255之间的整数
+///
+///它不能被`try`接住，但和`return`一样，块中被推迟的语句仍然会执行
+fn exit(
+    _interpreter: &mut Interpreter,
+    paren: &Token,
+    arguments: Vec<Value>,
+) -> Result<Value, LoxResult> {
+    match arguments[0] {
+        Value::Number(n) if n.fract() == 0.0 && (0.0..=255.0).contains(&n) => {
+            Err(LoxResult::Exit { code: n as i32 })
+        }
+        _ => Err(LoxResult::RuntimeError {
+            token: paren.clone(),
+            message: "Exit code must be an integer between 0 and 255.".to_string(),
+        }),
+    }
+}
+
+///返回参数的类型名，见[`Value::type_name`]
+fn type_of(
+    _interpreter: &mut Interpreter,
+    _paren: &Token,
+    arguments: Vec<Value>,
+) -> Result<Value, LoxResult> {
+    Ok(Value::String(arguments[0].type_name().to_string()))
+}
+
+///把格式字符串中的每个`{}`依次替换成后面参数打印出来的样子，比如`format("{} + {} = {}", 1, 2, 3)`
+///
+///`{}`的个数必须和格式字符串之后的参数个数相同
+fn format(
+    _interpreter: &mut Interpreter,
+    paren: &Token,
+    arguments: Vec<Value>,
+) -> Result<Value, LoxResult> {
+    let error = |message: &str| LoxResult::RuntimeError {
+        token: paren.clone(),
+        message: message.to_string(),
+    };
+    let mut arguments = arguments.into_iter();
+    let fmt = match arguments.next() {
+        Some(Value::String(fmt)) => fmt,
+        _ => return Err(error("Format must be a string.")),
+    };
+    let mut pieces = fmt.split("{}");
+    let mut result = pieces.next().unwrap_or_default().to_string();
+    for piece in pieces {
+        match arguments.next() {
+            Some(argument) => result.push_str(&argument.to_string()),
+            None => return Err(error("Not enough arguments for format string.")),
+        }
+        result.push_str(piece);
+    }
+    if arguments.next().is_some() {
+        return Err(error("Too many arguments for format string."));
+    }
+    Ok(Value::String(result))
+}
+
+///把参数打印出来的样子写入输出，不加换行，返回nil
+fn print_raw(
+    interpreter: &mut Interpreter,
+    _paren: &Token,
+    arguments: Vec<Value>,
+) -> Result<Value, LoxResult> {
+    interpreter.write_output(&arguments[0].to_string());
+    interpreter.flush_output();
+    Ok(Value::Nil)
+}
+
+///和`print`语句一样把参数用空格连接起来并换行输出，返回nil
+fn println(
+    interpreter: &mut Interpreter,
+    _paren: &Token,
+    arguments: Vec<Value>,
+) -> Result<Value, LoxResult> {
+    let line: Vec<_> = arguments.iter().map(|value| value.to_string()).collect();
+    interpreter.write_output(&format!("{}\n", line.join(" ")));
+    Ok(Value::Nil)
+}
+
+///从输入中读取一行并返回不带换行符的字符串，输入结束时返回nil
+///
+///输入默认是stdin。执行脚本文件时脚本是从文件读取的，所以`readLine`读到的是stdin中的内容而不是脚本；
+///在repl中它读取的是下一行输入，见[`Interpreter::set_input`]
+fn read_line(
+    interpreter: &mut Interpreter,
+    paren: &Token,
+    _arguments: Vec<Value>,
+) -> Result<Value, LoxResult> {
+    match interpreter.read_line() {
+        Ok(Some(line)) => Ok(Value::String(line)),
+        Ok(None) => Ok(Value::Nil),
+        Err(e) => Err(LoxResult::RuntimeError {
+            token: paren.clone(),
+            message: format!("Can't read input: {}.", e),
+        }),
+    }
+}
+
+// math
+
+///取出第`index`个参数中的数字，不是数字时返回运行时错误，错误信息中的参数位置从1开始
+fn number_argument(paren: &Token, arguments: &[Value], index: usize) -> Result<f64, LoxResult> {
+    match arguments[index] {
+        Value::Number(n) => Ok(n),
+        _ => Err(LoxResult::RuntimeError {
+            token: paren.clone(),
+            message: format!("Argument {} must be a number.", index + 1),
+        }),
+    }
+}
+
+///和[`number_argument`]一样，但数字还必须是整数
+fn integer_argument(paren: &Token, arguments: &[Value], index: usize) -> Result<f64, LoxResult> {
+    let n = number_argument(paren, arguments, index)?;
+    if n.is_finite() && n.fract() == 0.0 {
+        Ok(n)
+    } else {
+        Err(LoxResult::RuntimeError {
+            token: paren.clone(),
+            message: format!("Argument {} must be an integer.", index + 1),
+        })
+    }
+}
+
+///`pow(base, exp)`返回`base`的`exp`次方
+fn pow(
+    _interpreter: &mut Interpreter,
+    paren: &Token,
+    arguments: Vec<Value>,
+) -> Result<Value, LoxResult> {
+    let base = number_argument(paren, &arguments, 0)?;
+    let exp = number_argument(paren, &arguments, 1)?;
+    Ok(Value::Number(base.powf(exp)))
+}
+
+///平方根，负数的平方根是`nan`
+fn sqrt(
+    _interpreter: &mut Interpreter,
+    paren: &Token,
+    arguments: Vec<Value>,
+) -> Result<Value, LoxResult> {
+    Ok(Value::Number(number_argument(paren, &arguments, 0)?.sqrt()))
+}
+
+///向下取整
+fn floor(
+    _interpreter: &mut Interpreter,
+    paren: &Token,
+    arguments: Vec<Value>,
+) -> Result<Value, LoxResult> {
+    Ok(Value::Number(
+        number_argument(paren, &arguments, 0)?.floor(),
+    ))
+}
+
+///向上取整
+fn ceil(
+    _interpreter: &mut Interpreter,
+    paren: &Token,
+    arguments: Vec<Value>,
+) -> Result<Value, LoxResult> {
+    Ok(Value::Number(number_argument(paren, &arguments, 0)?.ceil()))
+}
+
+///绝对值
+fn abs(
+    _interpreter: &mut Interpreter,
+    paren: &Token,
+    arguments: Vec<Value>,
+) -> Result<Value, LoxResult> {
+    Ok(Value::Number(number_argument(paren, &arguments, 0)?.abs()))
+}
+
+///四舍五入到最近的整数，正好在中间时远离0，所以`round(-2.5)`是`-3`
+fn round(
+    _interpreter: &mut Interpreter,
+    paren: &Token,
+    arguments: Vec<Value>,
+) -> Result<Value, LoxResult> {
+    Ok(Value::Number(
+        number_argument(paren, &arguments, 0)?.round(),
+    ))
+}
+
+///负数返回-1，正数返回1，0返回0，`NaN`返回`NaN`
+fn sign(
+    _interpreter: &mut Interpreter,
+    paren: &Token,
+    arguments: Vec<Value>,
+) -> Result<Value, LoxResult> {
+    let n = number_argument(paren, &arguments, 0)?;
+    let sign = if n > 0.0 {
+        1.0
+    } else if n < 0.0 {
+        -1.0
+    } else if n == 0.0 {
+        0.0
+    } else {
+        f64::NAN
+    };
+    Ok(Value::Number(sign))
+}
+
+///两个整数的最大公约数，结果总是非负数，`gcd(0, 0)`是0
+fn gcd(
+    _interpreter: &mut Interpreter,
+    paren: &Token,
+    arguments: Vec<Value>,
+) -> Result<Value, LoxResult> {
+    let a = integer_argument(paren, &arguments, 0)?;
+    let b = integer_argument(paren, &arguments, 1)?;
+    Ok(Value::Number(euclid(a, b)))
+}
+
+///两个整数的最小公倍数，结果总是非负数，有一个参数是0时结果是0
+fn lcm(
+    _interpreter: &mut Interpreter,
+    paren: &Token,
+    arguments: Vec<Value>,
+) -> Result<Value, LoxResult> {
+    let a = integer_argument(paren, &arguments, 0)?;
+    let b = integer_argument(paren, &arguments, 1)?;
+    if a == 0.0 || b == 0.0 {
+        return Ok(Value::Number(0.0));
+    }
+    Ok(Value::Number((a / euclid(a, b) * b).abs()))
+}
+
+///辗转相除法，参数都是整数
+fn euclid(a: f64, b: f64) -> f64 {
+    let (mut a, mut b) = (a.abs(), b.abs());
+    while b != 0.0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+///返回`[0, 1)`之间的随机数，见[`Interpreter::set_seed`]
+fn rand(
+    interpreter: &mut Interpreter,
+    _paren: &Token,
+    _arguments: Vec<Value>,
+) -> Result<Value, LoxResult> {
+    Ok(Value::Number(interpreter.random()))
+}
+
+///返回`[lo, hi]`之间的随机整数，两个边界都必须是整数并且`lo`不大于`hi`
+fn rand_int(
+    interpreter: &mut Interpreter,
+    paren: &Token,
+    arguments: Vec<Value>,
+) -> Result<Value, LoxResult> {
+    let lo = number_argument(paren, &arguments, 0)?;
+    let hi = number_argument(paren, &arguments, 1)?;
+    let error = |message: &str| {
+        Err(LoxResult::RuntimeError {
+            token: paren.clone(),
+            message: message.to_string(),
+        })
+    };
+    if lo.fract() != 0.0 || hi.fract() != 0.0 {
+        return error("Bounds must be integers.");
+    }
+    if lo > hi {
+        return error("Lower bound must not be greater than upper bound.");
+    }
+    let n = lo + (interpreter.random() * (hi - lo + 1.0)).floor();
+    Ok(Value::Number(n.min(hi)))
+}
+
+///`rand`和`rand_int`使用的伪随机数生成器(splitmix64)，同一个种子总是产生同样的序列
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng { state: seed }
+    }
+
+    ///用当前时间作为种子，每次运行的序列都不同
+    pub fn from_time() -> Rng {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or_default();
+        Rng::new(nanos)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    ///`[0, 1)`之间均匀分布的数，使用`next_u64`的高53位
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
@@ -1,11 +1,12 @@
 //!parser.rs 是用于进行语法分析的文件，将token流转换为 [`Stmt`]，这将用在[`crate::interpreter`]中
 
 use crate::LoxResult;
+use std::collections::{BTreeSet, HashSet};
 use std::vec;
 
-use crate::expr::Expr;
+use crate::expr::{Expr, ExprId};
 use crate::stmt::Stmt;
-use crate::token::{Literal, Token};
+use crate::token::{Literal, Span, Token};
 use crate::token_type::TokenType;
 use crate::token_type::TokenType::*;
 
@@ -15,6 +16,43 @@ pub struct Parser {
     tokens: Vec<Token>,
     ///记录现在分析到的token
     current: usize,
+    ///完整解析时记录的每条顶层语句的token范围
+    index: ProgramIndex,
+    ///当前所在的循环的层数，用于检查`break`和`continue`是否在循环之中
+    loop_depth: usize,
+    ///分析过程中遇到的所有错误，由[`Parser::parse`]一并返回
+    errors: Vec<LoxResult>,
+    ///每条语句(包括块和函数体中的语句)开始的行，见[`Parser::statement_lines`]
+    statement_lines: BTreeSet<i32>,
+}
+
+///一条语句在token流中的范围，包含`start`，不包含`end`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StatementRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+///在完整解析时建立的索引，按顺序记录每条顶层语句的token范围
+///
+///第`i`个范围对应[`Parser::parse`]返回的第`i`条语句，
+///编辑器可以借此找到被修改的语句，再用[`Parser::reparse_statement`]只重新解析这一条
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ProgramIndex {
+    ranges: Vec<StatementRange>,
+}
+
+impl ProgramIndex {
+    pub fn ranges(&self) -> &[StatementRange] {
+        &self.ranges
+    }
+
+    ///返回包含第`token`个token的顶层语句的序号
+    pub fn statement_at(&self, token: usize) -> Option<usize> {
+        self.ranges
+            .iter()
+            .position(|range| range.start <= token && token < range.end)
+    }
 }
 
 ///使用递归下降分析:
@@ -28,92 +66,321 @@ pub struct Parser {
 ///如果发生异常，参与分析的函数都将返回[`LoxResult`]
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Self {
-        Parser { tokens, current: 0 }
+        Parser {
+            tokens,
+            current: 0,
+            index: ProgramIndex::default(),
+            loop_depth: 0,
+            errors: Vec::new(),
+            statement_lines: BTreeSet::new(),
+        }
     }
 
     ///开始语法分析，把token流转化为语句
-    pub fn parse(&mut self) -> Vec<Stmt> {
+    ///
+    ///同时记录每条顶层语句的token范围，见[`Parser::program_index`]
+    ///
+    ///出错时会跳到下一条语句继续分析，最后返回遇到的所有错误，错误不会被打印
+    pub fn parse(&mut self) -> Result<Vec<Stmt>, Vec<LoxResult>> {
         let mut statements = Vec::new();
+        self.index = ProgramIndex::default();
+        self.errors.clear();
+        self.statement_lines.clear();
         while !self.is_at_end() {
-            match self.declaration() {
-                Some(stmt) => statements.push(stmt),
-                None => {}
+            let start = self.current;
+            if let Some(stmt) = self.declaration() {
+                statements.push(stmt);
+                self.index.ranges.push(StatementRange {
+                    start,
+                    end: self.current,
+                });
+            }
+        }
+        if self.errors.is_empty() {
+            Ok(statements)
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
+    }
+
+    ///尝试把全部token分析为一个表达式，用于REPL中直接输入的表达式
+    ///
+    ///不成功时回到token流的开头并且不报告错误，之后可以再调用[`Parser::parse`]
+    ///
+    ///以`{`开头的输入是块语句而不是块表达式，所以总是不成功
+    pub fn parse_expression(&mut self) -> Option<Expr> {
+        if self.check(&LEFT_BRACE) {
+            return None;
+        }
+        match self.expression() {
+            Ok(expr) if self.is_at_end() && self.errors.is_empty() => Some(expr),
+            _ => {
+                self.current = 0;
+                self.errors.clear();
+                None
             }
         }
-        statements
     }
 
+    ///最近一次[`Parser::parse`]建立的顶层语句索引
+    pub fn program_index(&self) -> &ProgramIndex {
+        &self.index
+    }
+
+    ///最近一次[`Parser::parse`]中所有语句开始的行，也就是可以被执行的行，用于覆盖率统计
+    pub fn statement_lines(&self) -> &BTreeSet<i32> {
+        &self.statement_lines
+    }
+
+    ///只重新解析`tokens`中`range`范围内的一条顶层语句
+    ///
+    ///调用者需要保证修改只发生在这条语句内部，并把`range`调整为修改后的范围。
+    ///如果修改改变了括号的配对，或者范围内不再恰好是一条语句，
+    ///会返回[`LoxResult::FullReparse`]，此时需要重新解析整个文件
+    pub fn reparse_statement(
+        tokens: &[Token],
+        range: StatementRange,
+    ) -> Result<Stmt, Vec<LoxResult>> {
+        let slice = match tokens.get(range.start..range.end) {
+            Some(slice) if !slice.is_empty() && Parser::is_balanced(slice) => slice,
+            _ => return Err(vec![LoxResult::FullReparse]),
+        };
+
+        let line = slice[slice.len() - 1].line;
+        let mut tokens = slice.to_vec();
+        tokens.push(Token::new(EOF, String::from(""), None, line));
+
+        let mut parser = Parser::new(tokens);
+        let stmt = parser.declaration_statement().map_err(|e| vec![e])?;
+        if !parser.is_at_end() {
+            return Err(vec![LoxResult::FullReparse]);
+        }
+        if !parser.errors.is_empty() {
+            return Err(parser.errors);
+        }
+        Ok(stmt)
+    }
+
+    ///检查token中的小括号和大括号是否配对
+    fn is_balanced(tokens: &[Token]) -> bool {
+        let mut parens = 0;
+        let mut braces = 0;
+        for token in tokens {
+            match token.token_type {
+                LEFT_PAREN => parens += 1,
+                RIGHT_PAREN => parens -= 1,
+                LEFT_BRACE => braces += 1,
+                RIGHT_BRACE => braces -= 1,
+                _ => {}
+            }
+            if parens < 0 || braces < 0 {
+                return false;
+            }
+        }
+        parens == 0 && braces == 0
+    }
+
+    ///分析一条declaration，如果出错会记录错误并跳到下一条语句
     fn declaration(&mut self) -> Option<Stmt> {
-        fn parse_with_recovery<F>(parser: &mut Parser, parse_fn: F) -> Option<Stmt>
-        where
-            F: FnOnce(&mut Parser) -> Result<Stmt, LoxResult>,
-        {
-            match parse_fn(parser) {
-                Ok(stmt) => Some(stmt),
-                Err(_) => {
-                    parser.synchronize();
-                    None
-                }
+        match self.declaration_statement() {
+            Ok(stmt) => Some(stmt),
+            Err(e) => {
+                self.errors.push(e);
+                self.synchronize();
+                None
             }
         }
-        if self.match_token(&[FUN]) {
-            return parse_with_recovery(self, |p| p.function("function".to_string()));
+    }
+
+    ///分析一条declaration，不进行错误恢复
+    fn declaration_statement(&mut self) -> Result<Stmt, LoxResult> {
+        self.statement_lines.insert(self.peek().line);
+        if self.match_token(&[CLASS]) {
+            return self.class_declaration();
+        }
+
+        //`fun`后面没有名字时是匿名函数，由[`Parser::primary`]处理
+        if self.check(&FUN) && self.check_next(&IDENTIFIER) {
+            self.advance();
+            return self.function("function".to_string());
         }
 
         if self.match_token(&[VAR]) {
-            return parse_with_recovery(self, |p| p.var_declaration());
+            return self.var_declaration();
+        }
+
+        self.statement()
+    }
+
+    ///对类定义的token进行分析，类的主体由若干个方法组成，`<`之后是父类
+    fn class_declaration(&mut self) -> Result<Stmt, LoxResult> {
+        let start = self.current - 1;
+        let name = self.consume(IDENTIFIER, "Expect class name.".to_string())?;
+
+        let mut superclass = None;
+        if self.match_token(&[LESS]) {
+            self.consume(IDENTIFIER, "Expect superclass name.".to_string())?;
+            superclass = Some(Expr::Variable {
+                id: ExprId::new(),
+                name: self.previous(),
+                span: self.span_from(self.current - 1),
+            });
+        }
+
+        self.consume(LEFT_BRACE, "Expect '{' before class body.".to_string())?;
+
+        let mut methods = Vec::new();
+        let mut getters = Vec::new();
+        let mut class_methods = Vec::new();
+        while !self.check(&RIGHT_BRACE) && !self.is_at_end() {
+            let method_start = self.current;
+            if self.match_token(&[CLASS]) {
+                let name = self.consume(IDENTIFIER, "Expect method name.".to_string())?;
+                let (params, body) = self.function_body("method")?;
+                class_methods.push(Stmt::Function {
+                    name,
+                    params,
+                    body,
+                    span: self.span_from(method_start),
+                });
+                continue;
+            }
+            let name = self.consume(IDENTIFIER, "Expect method name.".to_string())?;
+            //方法名后面直接是`{`的是getter
+            if self.check(&LEFT_BRACE) {
+                let body = self.function_block("getter")?;
+                getters.push(Stmt::Function {
+                    name,
+                    params: Vec::new(),
+                    body,
+                    span: self.span_from(method_start),
+                });
+            } else {
+                let (params, body) = self.function_body("method")?;
+                methods.push(Stmt::Function {
+                    name,
+                    params,
+                    body,
+                    span: self.span_from(method_start),
+                });
+            }
         }
 
-        parse_with_recovery(self, |p| p.statement())
+        self.consume(RIGHT_BRACE, "Expect '}' after class body.".to_string())?;
+        Ok(Stmt::Class {
+            name,
+            superclass,
+            methods,
+            getters,
+            class_methods,
+            span: self.span_from(start),
+        })
     }
 
     ///对函数调用的token进行分析
     fn function(&mut self, kind: String) -> Result<Stmt, LoxResult> {
+        let start = self.current - 1;
         let name = self.consume(IDENTIFIER, format!("Expect '(' after {} name.", kind))?;
+        let (params, body) = self.function_body(&kind)?;
+        Ok(Stmt::Function {
+            name,
+            params,
+            body,
+            span: self.span_from(start),
+        })
+    }
 
+    ///分析函数的参数列表和函数体，函数声明和匿名函数共用
+    fn function_body(&mut self, kind: &str) -> Result<(Vec<Token>, Vec<Stmt>), LoxResult> {
         self.consume(LEFT_PAREN, format!("Expect '(' after {} name.", kind))?;
         let mut params = Vec::new();
+        let mut seen = HashSet::new();
         if !self.check(&RIGHT_PAREN) {
             loop {
                 if params.len() >= 255 {
                     return Err(LoxResult::ParseError {
                         token: self.peek(),
                         message: "Can't have more than 255 parameters.".to_string(),
-                    }
-                    .error());
+                    });
                 }
-                params.push(self.consume(IDENTIFIER, "Expect parameter name.".to_string())?);
+                let param = self.consume(IDENTIFIER, "Expect parameter name.".to_string())?;
+                //参数列表已经完整解析，只报告错误而不展开
+                if !seen.insert(param.lexeme.clone()) {
+                    self.errors.push(LoxResult::ParseError {
+                        token: param.clone(),
+                        message: "Already a variable with this name in this scope.".to_string(),
+                    });
+                }
+                params.push(param);
 
-                if !self.match_token(&[COMMA]) {
+                //允许最后一个参数后面有逗号
+                if !self.match_token(&[COMMA]) || self.check(&RIGHT_PAREN) {
                     break;
                 }
             }
         }
         self.consume(RIGHT_PAREN, "Expect ')' after parameters.".to_string())?;
+        let body = self.function_block(kind)?;
+        Ok((params, body))
+    }
+
+    ///分析用大括号包围的函数体
+    fn function_block(&mut self, kind: &str) -> Result<Vec<Stmt>, LoxResult> {
         self.consume(LEFT_BRACE, format!("Expect '{{' before {} body", kind))?;
-        let body = self.block()?;
-        Ok(Stmt::Function { name, params, body })
+        //函数体中的`break`不能跳出函数外的循环
+        let loop_depth = std::mem::replace(&mut self.loop_depth, 0);
+        let body = self.block();
+        self.loop_depth = loop_depth;
+        body
     }
 
     ///对变量定义的token进行分析
+    ///用`,`隔开的多个变量会放进[`Stmt::VarGroup`]，所以初始值从`assignment`开始，而不是逗号表达式
     fn var_declaration(&mut self) -> Result<Stmt, LoxResult> {
+        let start = self.current - 1;
+        let mut declarations = vec![self.variable(start)?];
+        while self.match_token(&[COMMA]) {
+            declarations.push(self.variable(self.current)?);
+        }
+        self.expect_semicolon("Expect ';' after variable declaration.");
+        if declarations.len() == 1 {
+            return Ok(declarations.remove(0));
+        }
+        Ok(Stmt::VarGroup {
+            declarations,
+            span: self.span_from(start),
+        })
+    }
+
+    ///`var`语句中的一个变量和它的初始值，第一个变量的范围从`var`开始
+    fn variable(&mut self, start: usize) -> Result<Stmt, LoxResult> {
         let name = self.consume(IDENTIFIER, "Expect variable name.".to_string())?;
         let mut initializer = None;
         if self.match_token(&[EQUAL]) {
-            initializer = Some(Box::new(self.expression()?));
+            initializer = Some(Box::new(self.assignment()?));
         }
-        self.consume(
-            SEMICOLON,
-            "Expect ';' after variable declaration.".to_string(),
-        )?;
-        Ok(Stmt::Var { name, initializer })
+        Ok(Stmt::Var {
+            name,
+            initializer,
+            span: self.span_from(start),
+        })
     }
 
-    ///分析statement的token，包括[`TokenType::FOR`], [`TokenType::IF`],
+    ///分析statement的token，包括[`TokenType::BREAK`], [`TokenType::CONTINUE`], [`TokenType::DEFER`], [`TokenType::FOR`], [`TokenType::IF`],
     ///[`TokenType::PRINT`],[`TokenType::RETURN`],[`TokenType::WHILE`],[`TokenType::LEFT_BRACE`]
     ///
     ///如果以上[`TokenType`] 都不匹配,那么就进入[`Parser::expression_statement`] 函数
     fn statement(&mut self) -> Result<Stmt, LoxResult> {
+        self.statement_lines.insert(self.peek().line);
+        if self.match_token(&[ASSERT]) {
+            return self.assert_statement();
+        }
+        if self.match_token(&[BREAK, CONTINUE]) {
+            return self.loop_control_statement();
+        }
+        if self.match_token(&[DEFER]) {
+            return self.defer_statement();
+        }
         if self.match_token(&[FOR]) {
             return self.for_statement();
         }
@@ -126,31 +393,127 @@ impl Parser {
         if self.match_token(&[RETURN]) {
             return self.return_statement();
         }
+        if self.match_token(&[TRY]) {
+            return self.try_statement();
+        }
         if self.match_token(&[WHILE]) {
             return self.while_statement();
         }
         if self.match_token(&[LEFT_BRACE]) {
+            let start = self.current - 1;
             return Ok(Stmt::Block {
                 statements: self.block()?,
+                span: self.span_from(start),
             });
         }
         self.expression_statement()
     }
 
+    ///处理`break`和`continue`语句
+    ///
+    ///出现在循环之外时报告错误，但语句本身是完整的，所以不需要跳过后面的token
+    fn loop_control_statement(&mut self) -> Result<Stmt, LoxResult> {
+        let keyword = self.previous();
+        self.consume(SEMICOLON, format!("Expect ';' after '{}'.", keyword.lexeme))?;
+        if self.loop_depth == 0 {
+            self.errors.push(LoxResult::ParseError {
+                message: format!("Can't use '{}' outside of a loop.", keyword.lexeme),
+                token: keyword.clone(),
+            });
+        }
+        let span = self.span_from(self.current - 2);
+        Ok(match keyword.token_type {
+            BREAK => Stmt::Break { keyword, span },
+            _ => Stmt::Continue { keyword, span },
+        })
+    }
+
+    ///处理assert语句，`:`之后是断言失败时显示的信息
+    fn assert_statement(&mut self) -> Result<Stmt, LoxResult> {
+        let start = self.current - 1;
+        let keyword = self.previous();
+        let expression = Box::new(self.expression()?);
+        let message = if self.match_token(&[COLON]) {
+            Some(Box::new(self.expression()?))
+        } else {
+            None
+        };
+        self.consume(SEMICOLON, "Expect ';' after assertion.".to_string())?;
+        Ok(Stmt::Assert {
+            keyword,
+            expression,
+            message,
+            span: self.span_from(start),
+        })
+    }
+
+    ///处理defer语句，被推迟的语句会在所在的块退出时执行
+    fn defer_statement(&mut self) -> Result<Stmt, LoxResult> {
+        let start = self.current - 1;
+        let keyword = self.previous();
+        let statement = Box::new(self.statement()?);
+        Ok(Stmt::Defer {
+            keyword,
+            statement,
+            span: self.span_from(start),
+        })
+    }
+
+    ///处理`try { ... } catch (e) { ... }`语句
+    fn try_statement(&mut self) -> Result<Stmt, LoxResult> {
+        let start = self.current - 1;
+        self.consume(LEFT_BRACE, "Expect '{' after 'try'.".to_string())?;
+        let body = self.block()?;
+        self.consume(CATCH, "Expect 'catch' after try block.".to_string())?;
+        self.consume(LEFT_PAREN, "Expect '(' after 'catch'.".to_string())?;
+        let catch_var = self.consume(IDENTIFIER, "Expect catch variable name.".to_string())?;
+        self.consume(RIGHT_PAREN, "Expect ')' after catch variable.".to_string())?;
+        self.consume(LEFT_BRACE, "Expect '{' before catch body.".to_string())?;
+        let catch_body = self.block()?;
+        Ok(Stmt::Try {
+            body,
+            catch_var,
+            catch_body,
+            span: self.span_from(start),
+        })
+    }
+
     ///处理return语句
     fn return_statement(&mut self) -> Result<Stmt, LoxResult> {
+        let start = self.current - 1;
         let keyword = self.previous();
         let mut value = None;
         if !self.check(&SEMICOLON) {
             value = Some(self.expression()?);
         }
-        self.consume(SEMICOLON, "Expect ';' after return value".to_string())?;
-        Ok(Stmt::Return { keyword, value })
+        self.expect_semicolon("Expect ';' after return value");
+        Ok(Stmt::Return {
+            keyword,
+            value,
+            span: self.span_from(start),
+        })
     }
 
-    ///处理for语句
+    ///处理for语句，它会被转换成[`Stmt::While`]；括号后是`IDENTIFIER in`时是[`Stmt::ForIn`]
+    ///
+    ///`increment`不能像循环体一样放进同一个块中，否则`continue`会跳过它，
+    ///所以单独保存在[`Stmt::While`]里
     fn for_statement(&mut self) -> Result<Stmt, LoxResult> {
+        let start = self.current - 1;
         self.consume(LEFT_PAREN, "Expect '(' after 'for'.".to_string())?;
+        if self.check(&IDENTIFIER) && self.check_next(&IN) {
+            let name = self.advance();
+            self.advance();
+            let iterable = Box::new(self.expression()?);
+            self.consume(RIGHT_PAREN, "Expect ')' after for clause.".to_string())?;
+            let body = Box::new(self.loop_body()?);
+            return Ok(Stmt::ForIn {
+                name,
+                iterable,
+                body,
+                span: self.span_from(start),
+            });
+        }
         let initializer = if self.match_token(&[SEMICOLON]) {
             None
         } else if self.match_token(&[VAR]) {
@@ -171,30 +534,25 @@ impl Parser {
         }
         self.consume(RIGHT_PAREN, "Expect ')' after for clause.".to_string())?;
 
-        let mut body = self.statement()?;
-
-        if let Some(increment) = increment {
-            body = Stmt::Block {
-                statements: vec![
-                    body,
-                    Stmt::Expression {
-                        expression: Box::new(increment),
-                    },
-                ],
-            }
-        }
+        let body = self.loop_body()?;
 
+        //转换出的语句都使用整个for语句的范围
+        let span = self.span_from(start);
         let condition = condition.unwrap_or(Expr::Literal {
             value: Literal::Bool(true),
+            span,
         });
 
-        body = Stmt::While {
+        let mut body = Stmt::While {
             condition: Box::new(condition),
             body: Box::new(body),
+            increment: increment.map(Box::new),
+            span,
         };
         if let Some(initializer) = initializer {
             body = Stmt::Block {
                 statements: vec![initializer, body],
+                span,
             }
         }
         Ok(body)
@@ -202,16 +560,31 @@ impl Parser {
 
     ///处理while
     fn while_statement(&mut self) -> Result<Stmt, LoxResult> {
+        let start = self.current - 1;
         self.consume(LEFT_PAREN, "Expect '(' after 'while'.".to_string())?;
         let condition = Box::new(self.expression()?);
         self.consume(RIGHT_PAREN, "Expect ')' after condition.".to_string())?;
-        let body = Box::new(self.statement()?);
+        let body = Box::new(self.loop_body()?);
+
+        Ok(Stmt::While {
+            condition,
+            body,
+            increment: None,
+            span: self.span_from(start),
+        })
+    }
 
-        Ok(Stmt::While { condition, body })
+    ///分析循环体，循环体中允许出现`break`和`continue`
+    fn loop_body(&mut self) -> Result<Stmt, LoxResult> {
+        self.loop_depth += 1;
+        let body = self.statement();
+        self.loop_depth -= 1;
+        body
     }
 
     ///处理if
     fn if_statement(&mut self) -> Result<Stmt, LoxResult> {
+        let start = self.current - 1;
         self.consume(LEFT_PAREN, "Expect '(' after 'if'.".to_string())?;
         let condition = self.expression()?;
         self.consume(RIGHT_PAREN, "Expect ')' after if condition.".to_string())?;
@@ -225,13 +598,24 @@ impl Parser {
             condition: Box::new(condition),
             then_branch: Box::new(then_branch),
             else_branch,
+            span: self.span_from(start),
         })
     }
 
     ///处理大括号块
+    ///
+    ///同一个块中`return`之后的语句永远不会执行，只对其中第一条报告错误，不展开
     fn block(&mut self) -> Result<Vec<Stmt>, LoxResult> {
         let mut stmts = Vec::new();
+        let mut unreachable_reported = false;
         while !self.check(&RIGHT_BRACE) && !self.is_at_end() {
+            if !unreachable_reported && matches!(stmts.last(), Some(Stmt::Return { .. })) {
+                unreachable_reported = true;
+                self.errors.push(LoxResult::ParseError {
+                    token: self.peek(),
+                    message: "Unreachable code.".to_string(),
+                });
+            }
             if let Some(stmt) = self.declaration() {
                 stmts.push(stmt);
             }
@@ -241,62 +625,246 @@ impl Parser {
         Ok(stmts)
     }
 
-    ///处理print语句
+    ///处理块表达式`{ stmt; ... value }`，`{`已经被读取
+    ///
+    ///以语句关键字或`{`开头的是语句，其他的是表达式：后面是`;`时是表达式语句，
+    ///后面是`}`时是块的值
+    fn block_expression(&mut self, start: usize) -> Result<Expr, LoxResult> {
+        let mut statements = Vec::new();
+        let mut value = None;
+        while !self.check(&RIGHT_BRACE) && !self.is_at_end() {
+            if self.starts_statement() {
+                statements.push(self.declaration_statement()?);
+                continue;
+            }
+            let expr_start = self.current;
+            let expr = self.expression()?;
+            if self.check(&RIGHT_BRACE) {
+                value = Some(Box::new(expr));
+                break;
+            }
+            self.consume(SEMICOLON, "Expect ';' after value".to_string())?;
+            statements.push(Stmt::Expression {
+                expression: Box::new(expr),
+                span: self.span_from(expr_start),
+            });
+        }
+        self.consume(RIGHT_BRACE, "Expect '}' after a block".to_string())?;
+        Ok(Expr::Block {
+            statements,
+            value,
+            span: self.span_from(start),
+        })
+    }
+
+    ///下一个token是否开始一条声明或者表达式语句以外的语句
+    fn starts_statement(&self) -> bool {
+        match self.peek().token_type {
+            CLASS | VAR | ASSERT | BREAK | CONTINUE | DEFER | FOR | IF | PRINT | RETURN | TRY
+            | WHILE | LEFT_BRACE => true,
+            FUN => self.check_next(&IDENTIFIER),
+            _ => false,
+        }
+    }
+
+    ///处理print语句，值之间的`,`分隔参数而不是逗号表达式，所以每一项都从`assignment`开始
     fn print_statement(&mut self) -> Result<Stmt, LoxResult> {
-        let expr = self.expression()?;
-        self.consume(SEMICOLON, "Expect ';' after value".to_string())?;
+        let start = self.current - 1;
+        let mut expressions = Vec::new();
+        if !self.check(&SEMICOLON) {
+            expressions.push(self.assignment()?);
+            while self.match_token(&[COMMA]) {
+                expressions.push(self.assignment()?);
+            }
+        }
+        self.expect_semicolon("Expect ';' after value");
         Ok(Stmt::Print {
-            expression: Box::new(expr),
+            expressions,
+            span: self.span_from(start),
         })
     }
 
     ///分析token，将其转换成[`Stmt::Expression`],并返回
     fn expression_statement(&mut self) -> Result<Stmt, LoxResult> {
+        let start = self.current;
         let expr = self.expression()?;
-        self.consume(SEMICOLON, "Expect ';' after value".to_string())?;
+        self.expect_semicolon("Expect ';' after value");
         Ok(Stmt::Expression {
             expression: Box::new(expr),
+            span: self.span_from(start),
         })
     }
 
-    ///调用了[`Parser::assignment`]
+    ///语句结尾的`;`，缺少时只记录错误，然后当作`;`已经存在继续分析，
+    ///这样漏掉一个`;`不会让之后的语句跟着出错
+    fn expect_semicolon(&mut self, message: &str) {
+        if !self.match_token(&[SEMICOLON]) {
+            self.errors.push(LoxResult::ParseError {
+                token: self.peek(),
+                message: message.to_string(),
+            });
+        }
+    }
+
+    ///优先级最低的逗号表达式`a, b, c`，只有一项时直接返回这一项
     pub fn expression(&mut self) -> Result<Expr, LoxResult> {
-        self.assignment()
+        let start = self.current;
+        let expr = self.assignment()?;
+        if !self.check(&COMMA) {
+            return Ok(expr);
+        }
+        let mut expressions = vec![expr];
+        while self.match_token(&[COMMA]) {
+            expressions.push(self.assignment()?);
+        }
+        Ok(Expr::Comma {
+            expressions,
+            span: self.span_from(start),
+        })
     }
 
-    ///分析赋值语句，返回[`Expr::Assign`]
+    ///分析赋值语句，返回[`Expr::Assign`]，如果赋值对象是属性则返回[`Expr::Set`]，是列表元素则返回[`Expr::IndexSet`]
     fn assignment(&mut self) -> Result<Expr, LoxResult> {
-        let expr = self.or()?;
+        let start = self.current;
+        let expr = self.conditional()?;
 
-        if self.match_token(&[EQUAL]) {
+        if self.match_token(&[EQUAL, PLUS_EQUAL, MINUS_EQUAL, STAR_EQUAL, SLASH_EQUAL]) {
             let equals = self.previous();
+            let operator = Parser::compound_operator(&equals);
             let value = self.assignment()?;
+            let span = self.span_from(start);
 
-            if let Expr::Variable { name } = expr {
-                return Ok(Expr::Assign {
-                    name,
-                    value: Box::new(value),
-                });
+            match expr {
+                //`a += b`直接转换成`a = a + b`，读取变量没有副作用
+                Expr::Variable {
+                    name, span: target, ..
+                } => {
+                    let value = match operator {
+                        Some(operator) => Expr::Binary {
+                            left: Box::new(Expr::Variable {
+                                id: ExprId::new(),
+                                name: name.clone(),
+                                span: target,
+                            }),
+                            operator,
+                            right: Box::new(value),
+                            span,
+                        },
+                        None => value,
+                    };
+                    return Ok(Expr::Assign {
+                        id: ExprId::new(),
+                        name,
+                        value: Box::new(value),
+                        span,
+                    });
+                }
+                Expr::Get { object, name, .. } => {
+                    return Ok(Expr::Set {
+                        object,
+                        name,
+                        operator,
+                        value: Box::new(value),
+                        span,
+                    })
+                }
+                Expr::Index {
+                    object,
+                    bracket,
+                    index,
+                    ..
+                } => {
+                    return Ok(Expr::IndexSet {
+                        object,
+                        bracket,
+                        index,
+                        operator,
+                        value: Box::new(value),
+                        span,
+                    })
+                }
+                _ => {}
             }
-            return Err(LoxResult::ParseError {
+            //只报告错误而不展开，赋值号两边都已经完整解析，不需要跳过后面的token
+            self.errors.push(LoxResult::ParseError {
                 token: equals,
-                message: "Invaild assignment target.".to_string(),
+                message: "Invalid assignment target.".to_string(),
             });
         }
         Ok(expr)
     }
 
+    ///复合赋值`+=`、`-=`、`*=`、`/=`对应的二元运算符，普通的`=`返回[`None`]
+    ///
+    ///运算符token的位置与赋值号相同，运行时错误会指向赋值号
+    fn compound_operator(equals: &Token) -> Option<Token> {
+        let (token_type, lexeme) = match equals.token_type {
+            PLUS_EQUAL => (PLUS, "+"),
+            MINUS_EQUAL => (MINUS, "-"),
+            STAR_EQUAL => (STAR, "*"),
+            SLASH_EQUAL => (SLASH, "/"),
+            _ => return None,
+        };
+        Some(Token {
+            token_type,
+            lexeme: lexeme.to_string(),
+            ..equals.clone()
+        })
+    }
+
+    ///处理条件运算符`cond ? a : b`，else分支递归调用自身，所以是右结合的
+    fn conditional(&mut self) -> Result<Expr, LoxResult> {
+        let start = self.current;
+        let condition = self.or()?;
+
+        if self.match_token(&[QUESTION]) {
+            let then_branch = self.expression()?;
+            self.consume(
+                COLON,
+                "Expect ':' after then branch of conditional expression.".to_string(),
+            )?;
+            let else_branch = self.conditional()?;
+            return Ok(Expr::Ternary {
+                condition: Box::new(condition),
+                then_branch: Box::new(then_branch),
+                else_branch: Box::new(else_branch),
+                span: self.span_from(start),
+            });
+        }
+        Ok(condition)
+    }
+
     ///处理or运算符
     fn or(&mut self) -> Result<Expr, LoxResult> {
-        let mut expr = self.and()?;
+        let start = self.current;
+        let mut expr = self.coalesce()?;
 
         while self.match_token(&[OR]) {
+            let operator = self.previous();
+            let right = self.coalesce()?;
+            expr = Expr::Logical {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+                span: self.span_from(start),
+            };
+        }
+        Ok(expr)
+    }
+
+    ///处理`??`运算符，它和`and`、`or`一样是[`Expr::Logical`]，优先级在两者之间
+    fn coalesce(&mut self) -> Result<Expr, LoxResult> {
+        let start = self.current;
+        let mut expr = self.and()?;
+
+        while self.match_token(&[QUESTION_QUESTION]) {
             let operator = self.previous();
             let right = self.and()?;
             expr = Expr::Logical {
                 left: Box::new(expr),
                 operator,
                 right: Box::new(right),
+                span: self.span_from(start),
             };
         }
         Ok(expr)
@@ -304,6 +872,7 @@ impl Parser {
 
     ///处理and运算符
     fn and(&mut self) -> Result<Expr, LoxResult> {
+        let start = self.current;
         let mut expr = self.equality()?;
 
         while self.match_token(&[AND]) {
@@ -314,6 +883,7 @@ impl Parser {
                 left: Box::new(expr),
                 operator,
                 right: Box::new(right),
+                span: self.span_from(start),
             };
         }
         Ok(expr)
@@ -321,14 +891,67 @@ impl Parser {
 
     ///处理 ==
     fn equality(&mut self) -> Result<Expr, LoxResult> {
-        let mut expr = self.comparison()?;
+        let start = self.current;
+        let mut expr = self.bit_or()?;
         while self.match_token(&[BANG_EQUAL, EQUAL_EQUAL]) {
+            let operator = self.previous();
+            let right = self.bit_or()?;
+            expr = Expr::Binary {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+                span: self.span_from(start),
+            };
+        }
+        Ok(expr)
+    }
+
+    ///处理按位或，`|`、`^`、`&`的优先级依次升高，都低于比较运算符
+    fn bit_or(&mut self) -> Result<Expr, LoxResult> {
+        let start = self.current;
+        let mut expr = self.bit_xor()?;
+        while self.match_token(&[PIPE]) {
+            let operator = self.previous();
+            let right = self.bit_xor()?;
+            expr = Expr::Binary {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+                span: self.span_from(start),
+            };
+        }
+        Ok(expr)
+    }
+
+    ///处理按位异或
+    fn bit_xor(&mut self) -> Result<Expr, LoxResult> {
+        let start = self.current;
+        let mut expr = self.bit_and()?;
+        while self.match_token(&[CARET]) {
+            let operator = self.previous();
+            let right = self.bit_and()?;
+            expr = Expr::Binary {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+                span: self.span_from(start),
+            };
+        }
+        Ok(expr)
+    }
+
+    ///处理按位与
+    fn bit_and(&mut self) -> Result<Expr, LoxResult> {
+        let start = self.current;
+        let mut expr = self.comparison()?;
+        while self.match_token(&[AMPERSAND]) {
             let operator = self.previous();
             let right = self.comparison()?;
             expr = Expr::Binary {
                 left: Box::new(expr),
                 operator,
                 right: Box::new(right),
+                span: self.span_from(start),
             };
         }
         Ok(expr)
@@ -336,14 +959,33 @@ impl Parser {
 
     ///处理比较运算符
     fn comparison(&mut self) -> Result<Expr, LoxResult> {
-        let mut expr = self.term()?;
+        let start = self.current;
+        let mut expr = self.shift()?;
         while self.match_token(&[GREATER, GREATER_EQUAL, LESS, LESS_EQUAL]) {
+            let operator = self.previous();
+            let right = self.shift()?;
+            expr = Expr::Binary {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+                span: self.span_from(start),
+            };
+        }
+        Ok(expr)
+    }
+
+    ///处理移位，和C一样优先级在比较与加减之间
+    fn shift(&mut self) -> Result<Expr, LoxResult> {
+        let start = self.current;
+        let mut expr = self.term()?;
+        while self.match_token(&[LESS_LESS, GREATER_GREATER]) {
             let operator = self.previous();
             let right = self.term()?;
             expr = Expr::Binary {
                 left: Box::new(expr),
                 operator,
                 right: Box::new(right),
+                span: self.span_from(start),
             };
         }
         Ok(expr)
@@ -351,6 +993,7 @@ impl Parser {
 
     ///处理加减
     fn term(&mut self) -> Result<Expr, LoxResult> {
+        let start = self.current;
         let mut expr = self.factor()?;
         while self.match_token(&[MINUS, PLUS]) {
             let operator = self.previous();
@@ -359,6 +1002,7 @@ impl Parser {
                 left: Box::new(expr),
                 operator,
                 right: Box::new(right),
+                span: self.span_from(start),
             };
         }
         Ok(expr)
@@ -366,14 +1010,16 @@ impl Parser {
 
     ///处理乘除
     fn factor(&mut self) -> Result<Expr, LoxResult> {
+        let start = self.current;
         let mut expr = self.unary()?;
-        while self.match_token(&[SLASH, STAR]) {
+        while self.match_token(&[SLASH, SLASH_SLASH, STAR]) {
             let operator = self.previous();
             let right = self.unary()?;
             expr = Expr::Binary {
                 left: Box::new(expr),
                 operator,
                 right: Box::new(right),
+                span: self.span_from(start),
             };
         }
         Ok(expr)
@@ -381,23 +1027,43 @@ impl Parser {
 
     ///处理单目运算符
     fn unary(&mut self) -> Result<Expr, LoxResult> {
+        let start = self.current;
         if self.match_token(&[BANG, MINUS]) {
             let operator = self.previous();
             let right = self.unary()?;
             return Ok(Expr::Unary {
                 operator,
                 right: Box::new(right),
+                span: self.span_from(start),
             });
         }
         self.call()
     }
 
-    ///处理函数调用
+    ///处理函数调用、属性访问和列表下标
     fn call(&mut self) -> Result<Expr, LoxResult> {
+        let start = self.current;
         let mut expr = self.primary()?;
         loop {
             if self.match_token(&[LEFT_PAREN]) {
-                expr = self.finish_call(expr)?;
+                expr = self.finish_call(expr, start)?;
+            } else if self.match_token(&[DOT]) {
+                let name =
+                    self.consume(IDENTIFIER, "Expect property name after '.'.".to_string())?;
+                expr = Expr::Get {
+                    object: Box::new(expr),
+                    name,
+                    span: self.span_from(start),
+                };
+            } else if self.match_token(&[LEFT_BRACKET]) {
+                let index = self.expression()?;
+                let bracket = self.consume(RIGHT_BRACKET, "Expect ']' after index.".to_string())?;
+                expr = Expr::Index {
+                    object: Box::new(expr),
+                    bracket,
+                    index: Box::new(index),
+                    span: self.span_from(start),
+                };
             } else {
                 break;
             }
@@ -406,20 +1072,26 @@ impl Parser {
     }
 
     ///处理 ), ) 表示一段程序,参数的结束
-    fn finish_call(&mut self, callee: Expr) -> Result<Expr, LoxResult> {
+    ///
+    ///`start`是`callee`的第一个token
+    fn finish_call(&mut self, callee: Expr, start: usize) -> Result<Expr, LoxResult> {
         let mut arguments = Vec::new();
+        let mut names = Vec::new();
         if !self.check(&RIGHT_PAREN) {
-            arguments.push(self.expression()?);
+            self.argument(&mut arguments, &mut names)?;
             while self.match_token(&[COMMA]) {
+                //允许最后一个参数后面有逗号
+                if self.check(&RIGHT_PAREN) {
+                    break;
+                }
                 if arguments.len() >= 255 {
                     return Err(LoxResult::ParseError {
                         token: self.peek(),
                         message: "Can't have more than 255 parameters.".to_string(),
-                    }
-                    .error());
+                    });
                 }
 
-                arguments.push(self.expression()?);
+                self.argument(&mut arguments, &mut names)?;
             }
         }
         let paren = self.consume(RIGHT_PAREN, "Expect ')' after arguments.".to_string())?;
@@ -427,33 +1099,106 @@ impl Parser {
             callee: Box::new(callee),
             paren,
             arguments,
+            names,
+            span: self.span_from(start),
         })
     }
 
+    ///解析一个调用参数，`name: value`形式的具名参数把名字记录到`names`中
+    ///
+    ///具名参数之后不能再有按位置的参数
+    fn argument(
+        &mut self,
+        arguments: &mut Vec<Expr>,
+        names: &mut Vec<Token>,
+    ) -> Result<(), LoxResult> {
+        if self.check(&IDENTIFIER) && self.check_next(&COLON) {
+            names.push(self.advance());
+            self.advance();
+        } else if !names.is_empty() {
+            return Err(LoxResult::ParseError {
+                token: self.peek(),
+                message: "Positional arguments must come before named arguments.".to_string(),
+            });
+        }
+        arguments.push(self.assignment()?);
+        Ok(())
+    }
+
     fn primary(&mut self) -> Result<Expr, LoxResult> {
+        let start = self.current;
         if self.match_token(&[FALSE]) {
             return Ok(Expr::Literal {
                 value: Literal::Bool(false),
+                span: self.span_from(start),
             });
         }
         if self.match_token(&[TRUE]) {
             return Ok(Expr::Literal {
                 value: Literal::Bool(true),
+                span: self.span_from(start),
             });
         }
         if self.match_token(&[NIL]) {
             return Ok(Expr::Literal {
                 value: Literal::Nil,
+                span: self.span_from(start),
+            });
+        }
+        if self.match_token(&[SUPER]) {
+            let keyword = self.previous();
+            self.consume(DOT, "Expect '.' after 'super'.".to_string())?;
+            let method = self.consume(IDENTIFIER, "Expect superclass method name.".to_string())?;
+            return Ok(Expr::Super {
+                id: ExprId::new(),
+                keyword,
+                method,
+                span: self.span_from(start),
+            });
+        }
+        if self.match_token(&[LEFT_BRACE]) {
+            return self.block_expression(start);
+        }
+        if self.match_token(&[FUN]) {
+            let (params, body) = self.function_body("lambda")?;
+            return Ok(Expr::Lambda {
+                params,
+                body,
+                span: self.span_from(start),
+            });
+        }
+        if self.match_token(&[THIS]) {
+            return Ok(Expr::This {
+                id: ExprId::new(),
+                keyword: self.previous(),
+                span: self.span_from(start),
             });
         }
         if self.match_token(&[NUMBER, STRING]) {
             return Ok(Expr::Literal {
                 value: self.previous().literal.clone().unwrap(),
+                span: self.span_from(start),
             });
         }
         if self.match_token(&[IDENTIFIER]) {
             return Ok(Expr::Variable {
+                id: ExprId::new(),
                 name: self.previous(),
+                span: self.span_from(start),
+            });
+        }
+        if self.match_token(&[LEFT_BRACKET]) {
+            let mut elements = Vec::new();
+            if !self.check(&RIGHT_BRACKET) {
+                elements.push(self.assignment()?);
+                while self.match_token(&[COMMA]) && !self.check(&RIGHT_BRACKET) {
+                    elements.push(self.assignment()?);
+                }
+            }
+            self.consume(RIGHT_BRACKET, "Expect ']' after list elements.".to_string())?;
+            return Ok(Expr::List {
+                elements,
+                span: self.span_from(start),
             });
         }
         if self.match_token(&[LEFT_PAREN]) {
@@ -461,13 +1206,29 @@ impl Parser {
             self.consume(RIGHT_PAREN, "Expect ')' after expression.".to_string())?;
             return Ok(Expr::Grouping {
                 expression: Box::new(expr),
+                span: self.span_from(start),
+            });
+        }
+        //语句关键字出现在需要表达式的位置时，给出比`Expect expression`更明确的错误
+        if [
+            ASSERT, BREAK, CLASS, CONTINUE, DEFER, FOR, IF, PRINT, RETURN, TRY, VAR, WHILE,
+        ]
+        .iter()
+        .any(|keyword| self.check(keyword))
+        {
+            let token = self.peek();
+            return Err(LoxResult::ParseError {
+                message: format!(
+                    "Expected an expression, found statement keyword '{}'.",
+                    token.lexeme
+                ),
+                token,
             });
         }
         Err(LoxResult::ParseError {
             token: self.peek(),
             message: "Expect expression".to_string(),
-        }
-        .error())
+        })
     }
 
     ///查看当前分析的token是否在types中，用来决定下一步的分析走向
@@ -491,8 +1252,7 @@ impl Parser {
             Err(LoxResult::ParseError {
                 token: self.peek(),
                 message,
-            }
-            .error())
+            })
         }
     }
 
@@ -503,6 +1263,14 @@ impl Parser {
         self.peek().token_type == *token_type
     }
 
+    ///查看下一个token的类型，不移动current指针
+    fn check_next(&self, token_type: &TokenType) -> bool {
+        match self.tokens.get(self.current + 1) {
+            Some(token) => token.token_type == *token_type,
+            None => false,
+        }
+    }
+
     fn advance(&mut self) -> Token {
         if !self.is_at_end() {
             self.current += 1;
@@ -522,6 +1290,11 @@ impl Parser {
         self.tokens[self.current - 1].clone()
     }
 
+    ///从第`start`个token到刚刚分析完的token的范围
+    fn span_from(&self, start: usize) -> Span {
+        Span::new(&self.tokens[start], &self.tokens[self.current - 1])
+    }
+
     ///当parse出现错误时，会跳出当前语句，直到遇到下一个语句，以防止连环报错
     fn synchronize(&mut self) {
         self.advance();
@@ -530,7 +1303,8 @@ impl Parser {
                 return;
             }
             match self.peek().token_type {
-                CLASS | FUN | VAR | FOR | IF | WHILE | PRINT | RETURN => return,
+                CLASS | FUN | VAR | FOR | IF | WHILE | PRINT | RETURN | DEFER | ASSERT | TRY
+                | BREAK | CONTINUE => return,
                 _ => (),
             }
             self.advance();
@@ -546,14 +1320,573 @@ mod test {
 
     use super::*;
 
+    fn scan(s: &str) -> Vec<Token> {
+        Scanner::new(s.to_string()).scan_tokens()
+    }
+
+    ///把调试输出中每个`open ... close`之间的内容替换成`_`
+    fn mask(text: &str, open: &str, close: &str) -> String {
+        let mut result = String::new();
+        let mut rest = text;
+        while let Some(i) = rest.find(open) {
+            result.push_str(&rest[..i + open.len()]);
+            rest = &rest[i + open.len()..];
+            result.push('_');
+            rest = &rest[rest.find(close).unwrap()..];
+        }
+        result.push_str(rest);
+        result
+    }
+
+    ///语法树去掉表达式编号之后的样子，分别解析出的两个语法树编号总是不同
+    fn without_ids(stmts: &[Stmt]) -> String {
+        mask(&format!("{:?}", stmts), "ExprId(", ")")
+    }
+
+    ///语法树去掉表达式编号和范围之后的结构，用于和手写的语法树比较
+    fn structure(stmts: &[Stmt]) -> String {
+        mask(&without_ids(stmts), "Span {", "}")
+    }
+
+    ///把`before`中第`statement`条语句修改成`after`，只重新解析这一条语句
+    fn reparse(before: &str, after: &str, statement: usize) -> Result<Vec<Stmt>, Vec<LoxResult>> {
+        let mut parser = Parser::new(scan(before));
+        let mut stmts = parser.parse().unwrap();
+        let old_tokens = scan(before);
+        let new_tokens = scan(after);
+        let delta = new_tokens.len() as isize - old_tokens.len() as isize;
+
+        let range = parser.program_index().ranges()[statement];
+        let range = StatementRange {
+            start: range.start,
+            end: (range.end as isize + delta) as usize,
+        };
+        stmts[statement] = Parser::reparse_statement(&new_tokens, range)?;
+        Ok(stmts)
+    }
+
+    #[test]
+    fn test_program_index_records_top_level_statements() {
+        let mut parser = Parser::new(scan("var a = 1;\nfun f() { print a; }\nprint a;"));
+        parser.parse().unwrap();
+        let index = parser.program_index();
+        assert_eq!(
+            index.ranges(),
+            &[
+                StatementRange { start: 0, end: 5 },
+                StatementRange { start: 5, end: 14 },
+                StatementRange { start: 14, end: 17 },
+            ]
+        );
+        assert_eq!(index.statement_at(7), Some(1));
+        assert_eq!(index.statement_at(17), None);
+    }
+
+    #[test]
+    fn test_reparse_edit_inside_function_body() {
+        let before = "fun f(a) {\n  print a;\n}\nvar x = 1;\nprint x;";
+        let after = "fun f(a) {\n  print a + 1;\n}\nvar x = 1;\nprint x;";
+        let spliced = reparse(before, after, 0).unwrap();
+        assert_eq!(
+            without_ids(&spliced),
+            without_ids(&Parser::new(scan(after)).parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_reparse_edit_at_statement_boundary() {
+        let before = "var x = 1;\nprint x;";
+        let replaced = "var x = 2 * 3;\nprint x;";
+        let spliced = reparse(before, replaced, 0).unwrap();
+        assert_eq!(
+            without_ids(&spliced),
+            without_ids(&Parser::new(scan(replaced)).parse().unwrap())
+        );
+
+        let split = "var x = 1; var y = 2;\nprint x;";
+        assert!(matches!(
+            reparse(before, split, 0).unwrap_err()[..],
+            [LoxResult::FullReparse]
+        ));
+    }
+
+    #[test]
+    fn test_reparse_requires_full_reparse_when_balance_changes() {
+        let before = "fun f() {\n  print 1;\n}\nprint 2;";
+        let after = "fun f() {\n  print (1;\n}\nprint 2;";
+        assert!(matches!(
+            reparse(before, after, 0).unwrap_err()[..],
+            [LoxResult::FullReparse]
+        ));
+    }
+
+    #[test]
+    fn test_parse_expression_falls_back_to_statements() {
+        let mut parser = Parser::new(scan("1 + 2"));
+        assert!(matches!(
+            parser.parse_expression(),
+            Some(Expr::Binary { .. })
+        ));
+
+        let mut parser = Parser::new(scan("a = 1; print a;"));
+        assert_eq!(parser.parse_expression(), None);
+        assert_eq!(parser.parse().unwrap().len(), 2);
+
+        let mut parser = Parser::new(scan("1 +"));
+        assert_eq!(parser.parse_expression(), None);
+        assert_eq!(parser.parse().unwrap_err().len(), 1);
+    }
+
+    #[test]
+    fn test_statement_keyword_in_expression() {
+        let mut parser = Parser::new(scan("print 1"));
+        match parser.expression() {
+            Err(LoxResult::ParseError { token, message }) => {
+                assert_eq!(token.token_type, PRINT);
+                assert_eq!(
+                    message,
+                    "Expected an expression, found statement keyword 'print'."
+                );
+            }
+            result => panic!("unexpected {:?}", result),
+        }
+
+        let errors = Parser::new(scan("var a = var b;")).parse().unwrap_err();
+        assert!(matches!(
+            &errors[..],
+            [LoxResult::ParseError { message, .. }]
+                if message == "Expected an expression, found statement keyword 'var'."
+        ));
+
+        //REPL中以语句关键字开头的输入仍然按语句解析
+        let mut parser = Parser::new(scan("print 1;"));
+        assert_eq!(parser.parse_expression(), None);
+        assert_eq!(parser.parse().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_synchronize_at_statement_keywords() {
+        for src in [
+            "print (1 2 3\ndefer f(1 +);",
+            "print (1 2 3\ntry { f(1 +); } catch (e) {}",
+            "print (1 2 3\nassert f(1 +);",
+            "while (true) {\n  print (1 2 3\n  break f(1 +);\n}",
+            "while (true) {\n  print (1 2 3\n  continue f(1 +);\n}",
+        ] {
+            let errors = Parser::new(scan(src)).parse().unwrap_err();
+            let lines: Vec<_> = errors
+                .iter()
+                .map(|error| match error {
+                    LoxResult::ParseError { token, .. } => token.line,
+                    other => panic!("expected a parse error, got {:?}", other),
+                })
+                .collect();
+            let first = lines[0];
+            assert_eq!(lines, [first, first + 1], "{}", src);
+        }
+    }
+
+    #[test]
+    fn test_parse_lambda() {
+        let stmts = Parser::new(scan("apply(fun (a, b) { return a; }, 1);\nfun (x) {};"))
+            .parse()
+            .unwrap();
+        match &stmts[0] {
+            Stmt::Expression { expression, .. } => match expression.as_ref() {
+                Expr::Call { arguments, .. } => match &arguments[0] {
+                    Expr::Lambda { params, body, .. } => {
+                        assert_eq!(params.len(), 2);
+                        assert!(matches!(body[..], [Stmt::Return { .. }]));
+                    }
+                    other => panic!("expected a lambda, got {:?}", other),
+                },
+                other => panic!("expected a call, got {:?}", other),
+            },
+            other => panic!("expected an expression statement, got {:?}", other),
+        }
+        assert!(matches!(
+            &stmts[1],
+            Stmt::Expression { expression, .. } if matches!(expression.as_ref(), Expr::Lambda { .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_grouped_assignment_target() {
+        let mut parser = Parser::new(scan("(a) = 1"));
+        assert!(parser.expression().is_ok());
+        match parser.errors.as_slice() {
+            [LoxResult::ParseError { token, message }] => {
+                assert_eq!(token.token_type, EQUAL);
+                assert_eq!(message, "Invalid assignment target.");
+            }
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_invalid_assignment_targets() {
+        let errors = Parser::new(scan("1 = 2;\nf() = 3;\na + b = c;\nprint 4;"))
+            .parse()
+            .unwrap_err();
+        let lines: Vec<_> = errors
+            .iter()
+            .map(|e| match e {
+                LoxResult::ParseError { token, message } => {
+                    assert_eq!(message, "Invalid assignment target.");
+                    token.line
+                }
+                other => panic!("expected a parse error, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(lines, vec![1, 2, 3]);
+
+        let stmts = Parser::new(scan("obj.field = 1;")).parse().unwrap();
+        assert!(matches!(
+            stmts.as_slice(),
+            [Stmt::Expression { expression, .. }] if matches!(expression.as_ref(), Expr::Set { .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_duplicate_parameters() {
+        let errors = Parser::new(scan("fun f(a, b, a) {}\nvar g = fun (c, c) {};"))
+            .parse()
+            .unwrap_err();
+        let positions: Vec<_> = errors
+            .iter()
+            .map(|e| match e {
+                LoxResult::ParseError { token, message } => {
+                    assert_eq!(message, "Already a variable with this name in this scope.");
+                    (token.lexeme.clone(), token.line)
+                }
+                other => panic!("expected a parse error, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(positions, vec![("a".to_string(), 1), ("c".to_string(), 2)]);
+
+        assert!(
+            Parser::new(scan("fun f(a, b, c) {}\nclass A { m(a, b) {} }"))
+                .parse()
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_parse_dangling_else_binds_to_inner_if() {
+        let stmts = Parser::new(scan("if (a) if (b) x; else y;"))
+            .parse()
+            .unwrap();
+        match stmts.as_slice() {
+            [Stmt::If {
+                then_branch,
+                else_branch: None,
+                ..
+            }] => assert!(matches!(
+                then_branch.as_ref(),
+                Stmt::If {
+                    else_branch: Some(_),
+                    ..
+                }
+            )),
+            other => panic!("expected the else on the inner if, got {:?}", other),
+        }
+
+        let stmts = Parser::new(scan("if (a) x; else if (b) y; else z;"))
+            .parse()
+            .unwrap();
+        match stmts.as_slice() {
+            [Stmt::If {
+                else_branch: Some(else_branch),
+                ..
+            }] => assert!(matches!(
+                else_branch.as_ref(),
+                Stmt::If {
+                    else_branch: Some(_),
+                    ..
+                }
+            )),
+            other => panic!("expected a right-nested else if, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_try() {
+        let stmts = Parser::new(scan("try { f(); } catch (e) { print e; }"))
+            .parse()
+            .unwrap();
+        match stmts.as_slice() {
+            [Stmt::Try {
+                body,
+                catch_var,
+                catch_body,
+                ..
+            }] => {
+                assert_eq!(body.len(), 1);
+                assert_eq!(catch_var.lexeme, "e");
+                assert_eq!(catch_body.len(), 1);
+            }
+            other => panic!("expected a try statement, got {:?}", other),
+        }
+
+        for (src, expected) in [
+            ("try f(); catch (e) {}", "Expect '{' after 'try'."),
+            ("try {} print 1;", "Expect 'catch' after try block."),
+            ("try {} catch e {}", "Expect '(' after 'catch'."),
+            ("try {} catch () {}", "Expect catch variable name."),
+        ] {
+            match Parser::new(scan(src)).parse().unwrap_err().as_slice() {
+                [LoxResult::ParseError { message, .. }, ..] => assert_eq!(message, expected),
+                other => panic!("expected a parse error for {}, got {:?}", src, other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_trailing_commas() {
+        match Parser::new(scan("f(1,)")).expression().unwrap() {
+            Expr::Call { arguments, .. } => assert_eq!(arguments.len(), 1),
+            other => panic!("expected a call, got {:?}", other),
+        }
+        match Parser::new(scan("f(1, 2,)")).expression().unwrap() {
+            Expr::Call { arguments, .. } => assert_eq!(arguments.len(), 2),
+            other => panic!("expected a call, got {:?}", other),
+        }
+        match Parser::new(scan("fun g(a,) {}"))
+            .parse()
+            .unwrap()
+            .as_slice()
+        {
+            [Stmt::Function { params, .. }] => assert_eq!(params.len(), 1),
+            other => panic!("expected a function, got {:?}", other),
+        }
+        assert!(Parser::new(scan("var h = fun (a, b,) {};")).parse().is_ok());
+        match Parser::new(scan("[1, 2,]")).expression().unwrap() {
+            Expr::List { elements, .. } => assert_eq!(elements.len(), 2),
+            other => panic!("expected a list, got {:?}", other),
+        }
+
+        for (src, expected) in [
+            ("f(,);", "Expect expression"),
+            ("f(1,,);", "Expect expression"),
+            ("fun g(,) {}", "Expect parameter name."),
+            ("fun g(a,,) {}", "Expect parameter name."),
+        ] {
+            match Parser::new(scan(src)).parse().unwrap_err().as_slice() {
+                [LoxResult::ParseError { token, message }] => {
+                    assert_eq!(token.token_type, COMMA);
+                    assert_eq!(message, expected);
+                }
+                other => panic!("expected one parse error for {}, got {:?}", src, other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_class() {
+        let stmts = Parser::new(scan("class Bacon { eat() { print \"crunch\"; } }"))
+            .parse()
+            .unwrap();
+        match &stmts[..] {
+            [Stmt::Class { name, methods, .. }] => {
+                assert_eq!(name.lexeme, "Bacon");
+                assert!(
+                    matches!(&methods[..], [Stmt::Function { name, .. }] if name.lexeme == "eat")
+                );
+            }
+            other => panic!("expected a class, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_subclass() {
+        let stmts = Parser::new(scan("class B < A { f() { super.f(); } }"))
+            .parse()
+            .unwrap();
+        match &stmts[..] {
+            [Stmt::Class {
+                superclass: Some(Expr::Variable { name, .. }),
+                ..
+            }] => assert_eq!(name.lexeme, "A"),
+            other => panic!("expected a subclass, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_property_assignment() {
+        let expr = Parser::new(scan("a.b = c")).expression().unwrap();
+        match expr {
+            Expr::Set {
+                object,
+                name,
+                value,
+                ..
+            } => {
+                assert!(matches!(*object, Expr::Variable { name, .. } if name.lexeme == "a"));
+                assert_eq!(name.lexeme, "b");
+                assert!(matches!(*value, Expr::Variable { name, .. } if name.lexeme == "c"));
+            }
+            other => panic!("expected a set expression, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_named_arguments() {
+        match Parser::new(scan("f(1, y: a ? b : c)"))
+            .expression()
+            .unwrap()
+        {
+            Expr::Call {
+                arguments, names, ..
+            } => {
+                assert_eq!(arguments.len(), 2);
+                assert_eq!(names.len(), 1);
+                assert_eq!(names[0].lexeme, "y");
+                assert!(matches!(arguments[1], Expr::Ternary { .. }));
+            }
+            other => panic!("expected a call expression, got {:?}", other),
+        }
+
+        match Parser::new(scan("f(x: 1, 2)")).expression() {
+            Err(LoxResult::ParseError { token, message }) => {
+                assert_eq!(token.lexeme, "2");
+                assert_eq!(
+                    message,
+                    "Positional arguments must come before named arguments."
+                );
+            }
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_chained_access() {
+        //`obj.method().field`是(. field (call (. method obj)))
+        let expr = Parser::new(scan("obj.method().field"))
+            .expression()
+            .unwrap();
+        match expr {
+            Expr::Get { object, name, .. } => {
+                assert_eq!(name.lexeme, "field");
+                match *object {
+                    Expr::Call {
+                        callee, arguments, ..
+                    } => {
+                        assert!(arguments.is_empty());
+                        assert!(matches!(*callee, Expr::Get { object, name, .. }
+                            if name.lexeme == "method"
+                                && matches!(*object, Expr::Variable { ref name, .. } if name.lexeme == "obj")));
+                    }
+                    other => panic!("expected a call expression, got {:?}", other),
+                }
+            }
+            other => panic!("expected a get expression, got {:?}", other),
+        }
+
+        let expr = Parser::new(scan("a.b(1).c.d()")).expression().unwrap();
+        assert_eq!(
+            crate::ast_printer::ExprVisitor.print(&expr),
+            "(call (. d (. c (call (. b a) 1))))"
+        );
+    }
+
+    #[test]
+    fn test_parse_ternary_is_right_associative() {
+        let expr = Parser::new(scan("a ? b : c ? d : e")).expression().unwrap();
+        match expr {
+            Expr::Ternary {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                assert!(matches!(*condition, Expr::Variable { name, .. } if name.lexeme == "a"));
+                assert!(matches!(*then_branch, Expr::Variable { name, .. } if name.lexeme == "b"));
+                assert!(matches!(*else_branch, Expr::Ternary { .. }));
+            }
+            other => panic!("expected a ternary expression, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_comma() {
+        let expr = Parser::new(scan("a = 1, b, c")).expression().unwrap();
+        match expr {
+            Expr::Comma { expressions, .. } => {
+                assert_eq!(expressions.len(), 3);
+                assert!(matches!(expressions[0], Expr::Assign { .. }));
+            }
+            other => panic!("expected a comma expression, got {:?}", other),
+        }
+
+        let expr = Parser::new(scan("f(a, (b, c), [d, e])"))
+            .expression()
+            .unwrap();
+        match expr {
+            Expr::Call { arguments, .. } => {
+                assert_eq!(arguments.len(), 3);
+                assert!(matches!(&arguments[1], Expr::Grouping { expression, .. }
+                    if matches!(expression.as_ref(), Expr::Comma { .. })));
+                assert!(
+                    matches!(&arguments[2], Expr::List { elements, .. } if elements.len() == 2)
+                );
+            }
+            other => panic!("expected a call expression, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_spans() {
+        let tokens = scan("print 1 +\n  foo * 2;");
+        let stmts = Parser::new(tokens.clone()).parse().unwrap();
+        match &stmts[..] {
+            [Stmt::Print { expressions, span }] => {
+                assert_eq!((span.start, span.end), ((1, 1), (2, 11)));
+                match &expressions[..] {
+                    [Expr::Binary {
+                        left, right, span, ..
+                    }] => {
+                        assert_eq!((span.start, span.end), ((1, 7), (2, 10)));
+                        assert_eq!(span.start, left.span().start);
+                        assert_eq!(span.end, right.span().end);
+                        assert_eq!((right.span().start, right.span().end), ((2, 3), (2, 10)));
+                        //`*`在右边的操作数中，`+`不在
+                        assert!(right.span().contains(&tokens[4]));
+                        assert!(!right.span().contains(&tokens[2]));
+                    }
+                    other => panic!("expected a binary expression, got {:?}", other),
+                }
+            }
+            other => panic!("expected one print statement, got {:?}", other),
+        }
+    }
+
     #[test]
     fn test_parse_val() {
         let mut scanner = Scanner::new("var a = 1;\nprint a;".to_string());
         let tokens = scanner.scan_tokens();
         let mut parse = Parser::new(tokens.to_vec());
-        let stmts = parse.parse();
-        dbg!(stmts);
-        assert!(false)
+        let stmts = parse.parse().unwrap();
+        assert_eq!(
+            structure(&stmts),
+            structure(&[
+                Stmt::Var {
+                    name: tokens[1].clone(),
+                    initializer: Some(Box::new(Expr::Literal {
+                        value: Literal::Number(1.0),
+                        span: Span::default(),
+                    })),
+                    span: Span::default(),
+                },
+                Stmt::Print {
+                    expressions: vec![Expr::Variable {
+                        id: ExprId::new(),
+                        name: tokens[6].clone(),
+                        span: Span::default(),
+                    }],
+                    span: Span::default(),
+                },
+            ])
+        );
     }
 
     #[test]
@@ -561,8 +1894,26 @@ mod test {
         let mut scanner = Scanner::new("print true; \"hello\";".to_string());
         let tokens = scanner.scan_tokens();
         let mut parse = Parser::new(tokens.to_vec());
-        let stmts = parse.parse();
-        assert!(false)
+        let stmts = parse.parse().unwrap();
+        assert_eq!(
+            structure(&stmts),
+            structure(&[
+                Stmt::Print {
+                    expressions: vec![Expr::Literal {
+                        value: Literal::Bool(true),
+                        span: Span::default(),
+                    }],
+                    span: Span::default(),
+                },
+                Stmt::Expression {
+                    expression: Box::new(Expr::Literal {
+                        value: Literal::String("hello".to_string()),
+                        span: Span::default(),
+                    }),
+                    span: Span::default(),
+                },
+            ])
+        );
     }
 
     #[test]
@@ -570,7 +1921,115 @@ mod test {
         let mut scanner = Scanner::new("(1 + 1) - 1".to_string());
         let tokens = scanner.scan_tokens();
         let mut parse = Parser::new(tokens.to_vec());
-        let a = parse.parse();
-        assert!(false)
+        match parse.parse() {
+            Err(errors) => match errors.as_slice() {
+                [LoxResult::ParseError { token, message }] => {
+                    assert_eq!(token.token_type, EOF);
+                    assert_eq!(message, "Expect ';' after value");
+                }
+                other => panic!("expected one parse error, got {:?}", other),
+            },
+            Ok(stmts) => panic!("expected a parse error, got {:?}", stmts),
+        }
+    }
+
+    #[test]
+    fn test_unreachable_code() {
+        match Parser::new(scan("fun f() {\n  return 1;\n  print 2;\n  print 3;\n}"))
+            .parse()
+            .unwrap_err()
+            .as_slice()
+        {
+            [LoxResult::ParseError { token, message }] => {
+                assert_eq!((token.lexeme.as_str(), token.line), ("print", 3));
+                assert_eq!(message, "Unreachable code.");
+            }
+            other => panic!("expected one parse error, got {:?}", other),
+        }
+
+        //条件中的return和内层块中的return都不影响之后的语句
+        for src in [
+            "fun f(a) {\n  if (a) return 1;\n  print 2;\n}",
+            "fun f() {\n  { return 1; }\n  print 2;\n}",
+            "fun f() {\n  while (true) { return 1; }\n  print 2;\n}",
+            "fun f() {\n  print 1;\n  return 2;\n}",
+        ] {
+            assert!(Parser::new(scan(src)).parse().is_ok(), "{}", src);
+        }
+    }
+
+    #[test]
+    fn test_parse_var_group() {
+        let stmts = Parser::new(scan("var a = 1, b = a + 1;\nvar c;"))
+            .parse()
+            .unwrap();
+        match &stmts[..] {
+            [Stmt::VarGroup { declarations, span }, Stmt::Var { .. }] => {
+                assert_eq!((span.start, span.end), ((1, 1), (1, 22)));
+                let names: Vec<_> = declarations
+                    .iter()
+                    .map(|stmt| match stmt {
+                        Stmt::Var { name, .. } => name.lexeme.as_str(),
+                        other => panic!("expected a var statement, got {:?}", other),
+                    })
+                    .collect();
+                assert_eq!(names, ["a", "b"]);
+            }
+            other => panic!("expected a var group and a var, got {:?}", other),
+        }
+
+        match Parser::new(scan("var a = 1, ;")).parse() {
+            Err(errors) => match &errors[..] {
+                [LoxResult::ParseError { token, message }] => {
+                    assert_eq!(token.lexeme, ";");
+                    assert_eq!(message, "Expect variable name.");
+                }
+                other => panic!("expected one parse error, got {:?}", other),
+            },
+            Ok(stmts) => panic!("expected a parse error, got {:?}", stmts),
+        }
+    }
+
+    #[test]
+    fn test_parse_missing_semicolon() {
+        let mut parser = Parser::new(scan(
+            "var a = 1\nprint a;\nfun f() { return a }\nf()\nprint f();",
+        ));
+        let errors = parser.parse().unwrap_err();
+        let errors: Vec<_> = errors
+            .iter()
+            .map(|e| match e {
+                LoxResult::ParseError { token, message } => (token.line, message.as_str()),
+                other => panic!("expected a parse error, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                (2, "Expect ';' after variable declaration."),
+                (3, "Expect ';' after return value"),
+                (5, "Expect ';' after value"),
+            ]
+        );
+        //每个漏掉的`;`只产生一个错误，所有语句仍然被解析出来
+        assert_eq!(parser.program_index().ranges().len(), 5);
+
+        let mut parser = Parser::new(scan("print 1\nvar b = 2;\nprint b;"));
+        assert_eq!(parser.parse().unwrap_err().len(), 1);
+        assert_eq!(parser.program_index().ranges().len(), 3);
+    }
+
+    #[test]
+    fn test_parse_collects_every_error() {
+        let mut parser = Parser::new(scan("var = 1;\nprint 1 +;\nvar ok = 1;\n1 + ;"));
+        let errors = parser.parse().unwrap_err();
+        let lines: Vec<_> = errors
+            .iter()
+            .map(|e| match e {
+                LoxResult::ParseError { token, .. } => token.line,
+                other => panic!("expected a parse error, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(lines, vec![1, 2, 4]);
     }
 }
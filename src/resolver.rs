@@ -0,0 +1,553 @@
+//! resolver.rs在解析之后、执行之前遍历一遍[`Vec<Stmt>`]，
+//! 计算每个局部变量的使用处与它的定义之间隔了几层作用域，并把结果记录在[`Interpreter`]中
+//!
+//! 没有被记录的变量都被当作全局变量，执行时直接到[`Interpreter::globals`]中查找
+use std::collections::HashMap;
+
+use crate::{
+    expr::{Expr, ExprId},
+    interpreter::Interpreter,
+    loxresult::LoxResult,
+    stmt::Stmt,
+    token::Token,
+};
+
+///当前正在解析的函数的种类
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FunctionType {
+    ///不在任何函数中
+    None,
+    Function,
+    ///类中的方法，函数的作用域中会定义`this`，有父类时还会定义`super`
+    Method {
+        has_superclass: bool,
+    },
+    ///名为`init`的方法，它总是返回`this`，所以其中的`return`不能带有值
+    Initializer {
+        has_superclass: bool,
+    },
+    ///以`class`开头声明的静态方法，其中不能使用`this`和`super`
+    StaticMethod,
+}
+
+///当前正在解析的类的种类，用于检查`this`和`super`是否在类之中
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ClassType {
+    ///不在任何类中
+    None,
+    Class,
+    ///有父类的类，其中可以使用`super`
+    Subclass,
+}
+
+pub struct Resolver<'a> {
+    interpreter: &'a mut Interpreter,
+    ///局部作用域的栈，值表示变量是否已经完成初始化，全局作用域不在其中
+    scopes: Vec<HashMap<String, bool>>,
+    ///解析过程中发现的错误
+    errors: Vec<LoxResult>,
+    ///当前所在的函数，用于检查`return`是否在函数之外
+    current_function: FunctionType,
+    ///是否在静态方法中，包括静态方法里嵌套的函数
+    in_static_method: bool,
+    ///当前所在的类，类中嵌套的类结束之后恢复为外层的类
+    current_class: ClassType,
+}
+
+impl<'a> Resolver<'a> {
+    pub fn new(interpreter: &'a mut Interpreter) -> Self {
+        Resolver {
+            interpreter,
+            scopes: Vec::new(),
+            errors: Vec::new(),
+            current_function: FunctionType::None,
+            in_static_method: false,
+            current_class: ClassType::None,
+        }
+    }
+
+    ///解析一组语句，返回发现的所有错误
+    pub fn resolve(&mut self, statements: &[Stmt]) -> Result<(), Vec<LoxResult>> {
+        self.resolve_statements(statements);
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
+    }
+
+    fn resolve_statements(&mut self, statements: &[Stmt]) {
+        for stmt in statements {
+            self.resolve_stmt(stmt);
+        }
+    }
+
+    fn resolve_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expression { expression, .. } => self.resolve_expr(expression),
+            Stmt::Print { expressions, .. } => {
+                for expression in expressions {
+                    self.resolve_expr(expression);
+                }
+            }
+            Stmt::Var {
+                name, initializer, ..
+            } => {
+                self.declare(name);
+                if let Some(initializer) = initializer {
+                    self.resolve_expr(initializer);
+                }
+                self.define(name);
+            }
+            Stmt::VarGroup { declarations, .. } => self.resolve_statements(declarations),
+            Stmt::Block { statements, .. } => {
+                self.begin_scope();
+                self.resolve_statements(statements);
+                self.end_scope();
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.resolve_expr(condition);
+                self.resolve_stmt(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.resolve_stmt(else_branch);
+                }
+            }
+            Stmt::While {
+                condition,
+                body,
+                increment,
+                ..
+            } => {
+                self.resolve_expr(condition);
+                self.resolve_stmt(body);
+                if let Some(increment) = increment {
+                    self.resolve_expr(increment);
+                }
+            }
+            Stmt::ForIn {
+                name,
+                iterable,
+                body,
+                ..
+            } => {
+                self.resolve_expr(iterable);
+                self.begin_scope();
+                self.declare(name);
+                self.define(name);
+                self.resolve_stmt(body);
+                self.end_scope();
+            }
+            Stmt::Break { .. } | Stmt::Continue { .. } => {}
+            Stmt::Function {
+                name, params, body, ..
+            } => {
+                self.declare(name);
+                self.define(name);
+                self.resolve_function(params, body, FunctionType::Function);
+            }
+            Stmt::Class {
+                name,
+                superclass,
+                methods,
+                getters,
+                class_methods,
+                ..
+            } => {
+                self.declare(name);
+                self.define(name);
+                if let Some(superclass) = superclass {
+                    self.resolve_expr(superclass);
+                }
+                let class_type = if superclass.is_some() {
+                    ClassType::Subclass
+                } else {
+                    ClassType::Class
+                };
+                let enclosing_class = std::mem::replace(&mut self.current_class, class_type);
+                let has_superclass = superclass.is_some();
+                for method in methods {
+                    if let Stmt::Function {
+                        name, params, body, ..
+                    } = method
+                    {
+                        let kind = if name.lexeme == "init" {
+                            FunctionType::Initializer { has_superclass }
+                        } else {
+                            FunctionType::Method { has_superclass }
+                        };
+                        self.resolve_function(params, body, kind);
+                    }
+                }
+                for getter in getters {
+                    if let Stmt::Function { params, body, .. } = getter {
+                        self.resolve_function(
+                            params,
+                            body,
+                            FunctionType::Method { has_superclass },
+                        );
+                    }
+                }
+                for method in class_methods {
+                    if let Stmt::Function { params, body, .. } = method {
+                        self.resolve_function(params, body, FunctionType::StaticMethod);
+                    }
+                }
+                self.current_class = enclosing_class;
+            }
+            Stmt::Return { keyword, value, .. } => {
+                if self.current_function == FunctionType::None {
+                    self.error(keyword, "Can't return from top-level code.");
+                }
+                if value.is_some()
+                    && matches!(self.current_function, FunctionType::Initializer { .. })
+                {
+                    self.error(keyword, "Can't return a value from an initializer.");
+                }
+                if let Some(value) = value {
+                    self.resolve_expr(value);
+                }
+            }
+            Stmt::Assert {
+                expression,
+                message,
+                ..
+            } => {
+                self.resolve_expr(expression);
+                if let Some(message) = message {
+                    self.resolve_expr(message);
+                }
+            }
+            Stmt::Defer { statement, .. } => self.resolve_stmt(statement),
+            Stmt::Try {
+                body,
+                catch_var,
+                catch_body,
+                ..
+            } => {
+                self.begin_scope();
+                self.resolve_statements(body);
+                self.end_scope();
+                //catch的变量和catch块中的语句在同一个作用域中
+                self.begin_scope();
+                self.declare(catch_var);
+                self.define(catch_var);
+                self.resolve_statements(catch_body);
+                self.end_scope();
+            }
+        }
+    }
+
+    fn resolve_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Binary { left, right, .. } | Expr::Logical { left, right, .. } => {
+                self.resolve_expr(left);
+                self.resolve_expr(right);
+            }
+            Expr::Ternary {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.resolve_expr(condition);
+                self.resolve_expr(then_branch);
+                self.resolve_expr(else_branch);
+            }
+            Expr::Grouping { expression, .. } => self.resolve_expr(expression),
+            Expr::Literal { .. } => {}
+            Expr::Unary { right, .. } => self.resolve_expr(right),
+            Expr::Variable { id, name, .. } => {
+                if let Some(false) = self.scopes.last().and_then(|scope| scope.get(&name.lexeme)) {
+                    self.error(name, "Can't read local variable in its own initializer.");
+                }
+                self.resolve_local(*id, &name.lexeme);
+            }
+            Expr::Assign {
+                id, name, value, ..
+            } => {
+                self.resolve_expr(value);
+                self.resolve_local(*id, &name.lexeme);
+            }
+            Expr::Call {
+                callee, arguments, ..
+            } => {
+                self.resolve_expr(callee);
+                for argument in arguments {
+                    self.resolve_expr(argument);
+                }
+            }
+            Expr::Get { object, .. } => self.resolve_expr(object),
+            Expr::Set { object, value, .. } => {
+                self.resolve_expr(value);
+                self.resolve_expr(object);
+            }
+            Expr::Comma {
+                expressions: elements,
+                ..
+            }
+            | Expr::List { elements, .. } => {
+                for element in elements {
+                    self.resolve_expr(element);
+                }
+            }
+            Expr::Index { object, index, .. } => {
+                self.resolve_expr(object);
+                self.resolve_expr(index);
+            }
+            Expr::IndexSet {
+                object,
+                index,
+                value,
+                ..
+            } => {
+                self.resolve_expr(value);
+                self.resolve_expr(object);
+                self.resolve_expr(index);
+            }
+            Expr::Block {
+                statements, value, ..
+            } => {
+                self.begin_scope();
+                self.resolve_statements(statements);
+                if let Some(value) = value {
+                    self.resolve_expr(value);
+                }
+                self.end_scope();
+            }
+            Expr::Lambda { params, body, .. } => {
+                self.resolve_function(params, body, FunctionType::Function)
+            }
+            Expr::This { id, keyword, .. } => {
+                match self.current_class {
+                    ClassType::None => self.error(keyword, "Can't use 'this' outside of a class."),
+                    _ => self.check_static_method(keyword),
+                }
+                self.resolve_local(*id, "this")
+            }
+            Expr::Super { id, keyword, .. } => {
+                match self.current_class {
+                    ClassType::None => self.error(keyword, "Can't use 'super' outside of a class."),
+                    ClassType::Class => {
+                        self.error(keyword, "Can't use 'super' in a class with no superclass.")
+                    }
+                    ClassType::Subclass => self.check_static_method(keyword),
+                }
+                self.resolve_local(*id, "super")
+            }
+        }
+    }
+
+    ///函数的参数和函数体在同一个作用域中，这与[`crate::loxfunction::LoxFunction`]调用时创建的环境一致
+    fn resolve_function(&mut self, params: &[Token], body: &[Stmt], kind: FunctionType) {
+        let enclosing_function = std::mem::replace(&mut self.current_function, kind);
+        let in_static_method = self.in_static_method;
+        match kind {
+            FunctionType::StaticMethod => self.in_static_method = true,
+            FunctionType::Method { .. } | FunctionType::Initializer { .. } => {
+                self.in_static_method = false
+            }
+            _ => {}
+        }
+        self.begin_scope();
+        if let FunctionType::Method { has_superclass }
+        | FunctionType::Initializer { has_superclass } = kind
+        {
+            self.define_name("this");
+            if has_superclass {
+                self.define_name("super");
+            }
+        }
+        for param in params {
+            self.declare(param);
+            self.define(param);
+        }
+        self.resolve_statements(body);
+        self.end_scope();
+        self.current_function = enclosing_function;
+        self.in_static_method = in_static_method;
+    }
+
+    ///记录一个变量解析的错误，所有的错误都通过这里产生，格式和语法错误相同
+    fn error(&mut self, token: &Token, message: &str) {
+        self.errors.push(LoxResult::ParseError {
+            token: token.clone(),
+            message: message.to_string(),
+        });
+    }
+
+    ///静态方法没有绑定实例，其中的`this`和`super`是错误
+    fn check_static_method(&mut self, keyword: &Token) {
+        if self.in_static_method {
+            let message = format!("Can't use '{}' in a static method.", keyword.lexeme);
+            self.error(keyword, &message);
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    ///在当前作用域中声明变量，此时变量还不能被读取
+    fn declare(&mut self, name: &Token) {
+        let declared = match self.scopes.last_mut() {
+            Some(scope) => scope.insert(name.lexeme.clone(), false).is_some(),
+            None => return,
+        };
+        if declared {
+            self.error(name, "Already a variable with this name in this scope.");
+        }
+    }
+
+    ///标记变量已经完成初始化
+    fn define(&mut self, name: &Token) {
+        self.define_name(&name.lexeme);
+    }
+
+    fn define_name(&mut self, name: &str) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), true);
+        }
+    }
+
+    ///从内向外查找变量所在的作用域，找到时把深度记录到解释器中
+    fn resolve_local(&mut self, id: ExprId, name: &str) {
+        for (depth, scope) in self.scopes.iter().rev().enumerate() {
+            if scope.contains_key(name) {
+                self.interpreter.resolve(id, depth);
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+
+    fn resolve(s: &str) -> Result<(), Vec<LoxResult>> {
+        let statements = Parser::new(Scanner::new(s.to_string()).scan_tokens())
+            .parse()
+            .unwrap();
+        Resolver::new(&mut Interpreter::new()).resolve(&statements)
+    }
+
+    #[test]
+    fn test_top_level_return() {
+        for src in [
+            "return 1;",
+            "{ return; }",
+            "fun f() {}\nwhile (true) return;",
+        ] {
+            match resolve(src).unwrap_err().as_slice() {
+                [LoxResult::ParseError { token, message }] => {
+                    assert_eq!(token.lexeme, "return");
+                    assert_eq!(message, "Can't return from top-level code.");
+                }
+                other => panic!("expected one error for {}, got {:?}", src, other),
+            }
+        }
+        assert_eq!(
+            resolve("return 1;").unwrap_err()[0].to_string(),
+            "[line 1, column 1] Error  at ' return ': Can't return from top-level code."
+        );
+    }
+
+    #[test]
+    fn test_this_in_static_method() {
+        for (src, lexeme) in [
+            ("class A { class make() { return this; } }", "this"),
+            (
+                "class A {}\nclass B < A { class make() { return super.make(); } }",
+                "super",
+            ),
+            ("class A { class make() { fun f() { this; } } }", "this"),
+        ] {
+            match resolve(src).unwrap_err().as_slice() {
+                [LoxResult::ParseError { token, message }] => {
+                    assert_eq!(token.lexeme, lexeme);
+                    assert_eq!(
+                        message,
+                        &format!("Can't use '{}' in a static method.", lexeme)
+                    );
+                }
+                other => panic!("expected one error for {}, got {:?}", src, other),
+            }
+        }
+        assert!(resolve("class A { class make() { class B { m() { return this; } } } }").is_ok());
+    }
+
+    #[test]
+    fn test_this_and_super_outside_of_a_class() {
+        for (src, lexeme, expected) in [
+            ("print this;", "this", "Can't use 'this' outside of a class."),
+            (
+                "fun f() { return this; }",
+                "this",
+                "Can't use 'this' outside of a class.",
+            ),
+            (
+                "fun f() { super.m(); }",
+                "super",
+                "Can't use 'super' outside of a class.",
+            ),
+            (
+                "class A { m() { super.m(); } }",
+                "super",
+                "Can't use 'super' in a class with no superclass.",
+            ),
+            //内层的类结束之后回到外层
+            (
+                "class A {}\nclass B < A { m() { class C {} return super.m; } }\nclass D { m() { class E < A {} return super.m; } }",
+                "super",
+                "Can't use 'super' in a class with no superclass.",
+            ),
+        ] {
+            match resolve(src).unwrap_err().as_slice() {
+                [LoxResult::ParseError { token, message }] => {
+                    assert_eq!(token.lexeme, lexeme);
+                    assert_eq!(message, expected);
+                }
+                other => panic!("expected one error for {}, got {:?}", src, other),
+            }
+        }
+        assert!(resolve("class A { m() { return fun () { return this; }; } }").is_ok());
+        assert!(resolve("class A { m() {} }\nclass B < A { m() { return super.m; } }").is_ok());
+    }
+
+    #[test]
+    fn test_return_inside_functions() {
+        assert!(resolve("fun f() { return 1; }").is_ok());
+        assert!(resolve("var f = fun () { { return; } };").is_ok());
+        assert!(resolve("class A { m() { return this; } }").is_ok());
+        assert!(resolve("fun f() { fun g() { return; } return g; }").is_ok());
+    }
+
+    #[test]
+    fn test_return_in_initializer() {
+        match resolve("class A { init() { return 1; } }")
+            .unwrap_err()
+            .as_slice()
+        {
+            [LoxResult::ParseError { token, message }] => {
+                assert_eq!(token.lexeme, "return");
+                assert_eq!(message, "Can't return a value from an initializer.");
+            }
+            other => panic!("expected one error, got {:?}", other),
+        }
+        assert!(resolve("class A { init() { if (true) return; this.a = 1; } }").is_ok());
+        assert!(resolve("class A { init() { fun f() { return 1; } } }").is_ok());
+        assert!(resolve("class A { init() { return super.init(); } }").is_err());
+        assert!(resolve("class A { other() { return 1; } }").is_ok());
+    }
+}
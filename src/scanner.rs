@@ -1,10 +1,10 @@
 //! scanner.rs是用于词法分析的文件，它将文本分析成`token`流，并将`token`流传递给语法分析器[`crate::parser`]
 
+use crate::loxresult::LoxResult;
 use crate::token::Literal;
 use crate::token::Token;
 use crate::token_type::TokenType;
 use crate::token_type::TokenType::*;
-use crate::Lox;
 use lazy_static::lazy_static;
 use std::collections::HashMap;
 
@@ -14,12 +14,18 @@ lazy_static! {
     pub static ref KEYWORDS: HashMap<String, TokenType> = {
         [
             ("and", AND),
+            ("assert", ASSERT),
+            ("break", BREAK),
+            ("catch", CATCH),
             ("class", CLASS),
+            ("continue", CONTINUE),
+            ("defer", DEFER),
             ("else", ELSE),
             ("false", FALSE),
             ("for", FOR),
             ("fun", FUN),
             ("if", IF),
+            ("in", IN),
             ("nil", NIL),
             ("or", OR),
             ("print", PRINT),
@@ -27,6 +33,7 @@ lazy_static! {
             ("super", SUPER),
             ("this", THIS),
             ("true", TRUE),
+            ("try", TRY),
             ("var", VAR),
             ("while", WHILE),
         ]
@@ -38,8 +45,8 @@ lazy_static! {
 
 ///`Scanner`结构体
 pub struct Scanner {
-    ///源代码
-    source: String,
+    ///源代码，预先拆成字符以便按下标直接访问
+    source: Vec<char>,
     ///保存分析得出的token流
     tokens: Vec<Token>,
     ///记录了一个词开头在`source`中的位置
@@ -48,29 +55,92 @@ pub struct Scanner {
     current: i32,
     ///记录分析到了文件的哪一行，每次遇到一个`\n`，`line = line + 1`
     line: i32,
+    ///当前行的第一个字符在`source`中的位置
+    line_start: i32,
+    ///`start`所在的列，在每个词开始时记录，因为字符串和注释中可能有换行
+    column: i32,
+    ///分析过程中遇到的错误，遇到错误后会继续分析
+    errors: Vec<LoxResult>,
+    ///最多记录的错误数量，超过之后停止分析，见[`Scanner::with_error_limit`]
+    error_limit: usize,
 }
 
+///[`Scanner`]默认最多记录的错误数量
+pub const DEFAULT_ERROR_LIMIT: usize = 50;
+
 impl Scanner {
     pub fn new(source: String) -> Scanner {
         Scanner {
-            source,
+            source: source.chars().collect(),
             tokens: Vec::new(),
             start: 0,
             current: 0,
             line: 1,
+            line_start: 0,
+            column: 1,
+            errors: Vec::new(),
+            error_limit: DEFAULT_ERROR_LIMIT,
         }
     }
 
-    ///启动scanner进行词法分析
-    pub fn scan_tokens(mut self) -> Vec<Token> {
-        while !self.is_at_end() {
+    ///设置最多记录的错误数量，错误达到这个数量之后再遇到错误时，
+    ///会记录一条`Too many errors; stopping.`并停止分析，已经得到的token仍然会返回
+    pub fn with_error_limit(mut self, limit: usize) -> Scanner {
+        self.error_limit = limit;
+        self
+    }
+
+    ///启动scanner进行词法分析，遇到的错误可以通过[`Scanner::errors`]获得
+    pub fn scan_tokens(&mut self) -> Vec<Token> {
+        let mut tokens = Vec::new();
+        self.scan_tokens_into(&mut tokens);
+        tokens
+    }
+
+    ///与[`Scanner::scan_tokens`]相同，但把token写入调用者提供的`buffer`，`buffer`原有的内容会被清空
+    ///
+    ///反复分析许多小段代码时(比如编辑器每次按键后重新分析)，可以一直复用同一个`buffer`，不必每次都重新分配
+    pub fn scan_tokens_into(&mut self, buffer: &mut Vec<Token>) {
+        buffer.clear();
+        std::mem::swap(&mut self.tokens, buffer);
+        while !self.is_at_end() && !self.too_many_errors() {
             self.start = self.current;
+            self.column = self.start - self.line_start + 1;
             self.scan_token();
         }
 
-        self.tokens
-            .push(Token::new(EOF, String::from(""), None, self.line));
-        self.tokens
+        self.tokens.push(
+            Token::new(EOF, String::from(""), None, self.line)
+                .with_column(self.current - self.line_start + 1),
+        );
+        std::mem::swap(&mut self.tokens, buffer);
+    }
+
+    ///词法分析中遇到的错误
+    pub fn errors(&self) -> &[LoxResult] {
+        &self.errors
+    }
+
+    fn error(&mut self, message: String) {
+        if self.too_many_errors() {
+            return;
+        }
+        let message = if self.errors.len() == self.error_limit {
+            "Too many errors; stopping.".to_string()
+        } else {
+            message
+        };
+        //出错时最后读到的字符所在的列，刚读过换行时是新一行的第一列
+        self.errors.push(LoxResult::ScanError {
+            line: self.line,
+            column: (self.current - self.line_start).max(1),
+            message,
+        });
+    }
+
+    ///已经记录了超出上限的那条错误，不再继续分析
+    fn too_many_errors(&self) -> bool {
+        self.errors.len() > self.error_limit
     }
 
     fn is_at_end(&self) -> bool {
@@ -86,7 +156,7 @@ impl Scanner {
     ///  if [`Scanner::is_alpha`] => [`Scanner::identifier`]
     ///  
     ///   对于异常，我们有：
-    ///   _ => [`Lox::error_with_line`]
+    ///   _ => [`Scanner::error`]
     ///
     fn scan_token(&mut self) {
         let c = self.advance();
@@ -95,12 +165,47 @@ impl Scanner {
             ')' => self.add_token(RIGHT_PAREN),
             '{' => self.add_token(LEFT_BRACE),
             '}' => self.add_token(RIGHT_BRACE),
+            '[' => self.add_token(LEFT_BRACKET),
+            ']' => self.add_token(RIGHT_BRACKET),
             ',' => self.add_token(COMMA),
             '.' => self.add_token(DOT),
-            '-' => self.add_token(MINUS),
-            '+' => self.add_token(PLUS),
+            '-' => {
+                let token_type = if self.match_char('=') {
+                    MINUS_EQUAL
+                } else {
+                    MINUS
+                };
+                self.add_token(token_type);
+            }
+            '+' => {
+                let token_type = if self.match_char('=') {
+                    PLUS_EQUAL
+                } else {
+                    PLUS
+                };
+                self.add_token(token_type);
+            }
             ';' => self.add_token(SEMICOLON),
-            '*' => self.add_token(STAR),
+            '*' => {
+                let token_type = if self.match_char('=') {
+                    STAR_EQUAL
+                } else {
+                    STAR
+                };
+                self.add_token(token_type);
+            }
+            '?' => {
+                let token_type = if self.match_char('?') {
+                    QUESTION_QUESTION
+                } else {
+                    QUESTION
+                };
+                self.add_token(token_type);
+            }
+            ':' => self.add_token(COLON),
+            '&' => self.add_token(AMPERSAND),
+            '|' => self.add_token(PIPE),
+            '^' => self.add_token(CARET),
             '!' => {
                 let token_type = if self.match_char('=') {
                     BANG_EQUAL
@@ -120,6 +225,8 @@ impl Scanner {
             '<' => {
                 let token_type = if self.match_char('=') {
                     LESS_EQUAL
+                } else if self.match_char('<') {
+                    LESS_LESS
                 } else {
                     LESS
                 };
@@ -128,26 +235,75 @@ impl Scanner {
             '>' => {
                 let token_type = if self.match_char('=') {
                     GREATER_EQUAL
+                } else if self.match_char('>') {
+                    GREATER_GREATER
                 } else {
                     GREATER
                 };
                 self.add_token(token_type);
             }
             '/' => {
-                if self.match_char('/') {
+                if self.peek() == '/' && self.is_floor_division() {
+                    self.advance();
+                    self.add_token(SLASH_SLASH);
+                } else if self.match_char('/') {
                     while self.peek() != '\n' && !self.is_at_end() {
                         self.advance();
                     }
+                } else if self.match_char('*') {
+                    self.block_comment();
+                } else if self.match_char('=') {
+                    self.add_token(SLASH_EQUAL);
                 } else {
                     self.add_token(SLASH);
                 }
             }
             ' ' | '\r' | '\t' => {}
-            '\n' => self.line += 1,
+            '\n' => self.new_line(),
             '"' => self.string(),
             c if Scanner::is_digit(c) => self.number(),
             c if Scanner::is_alpha(c) => self.identifier(),
-            _ => Lox::error_with_line(self.line, "Unexpected character."),
+            _ => self.error("Unexpected character.".to_string()),
+        }
+    }
+
+    ///`//`两边都没有空白，并且紧挨着一个操作数的结尾时是整除运算符，比如`a//b`和`(a + 1)//2`
+    ///
+    ///其他的`//`都是注释，所以`a // b`、`2 // two`和`init(x) // constructor`中的`//`都是注释
+    fn is_floor_division(&self) -> bool {
+        let last = match self.tokens.last() {
+            Some(token) if token.line == self.line => token,
+            _ => return false,
+        };
+        let adjacent = last.column + last.lexeme.chars().count() as i32 == self.column;
+        let operand = matches!(
+            last.token_type,
+            NUMBER | STRING | IDENTIFIER | RIGHT_BRACKET | RIGHT_PAREN | TRUE | FALSE | NIL | THIS
+        );
+        let next = self.peek_next();
+        adjacent && operand && !next.is_whitespace() && next != '\0'
+    }
+
+    ///跳过`/* ... */`注释，注释可以嵌套，每个`/*`都要有对应的`*/`
+    fn block_comment(&mut self) {
+        let mut depth = 1;
+        while depth > 0 {
+            if self.is_at_end() {
+                self.error("Unterminated block comment.".to_string());
+                return;
+            }
+            match self.advance() {
+                '\n' => self.new_line(),
+                '/' if self.peek() == '*' => {
+                    self.advance();
+                    depth += 1;
+                }
+                '*' if self.peek() == '/' => {
+                    self.advance();
+                    depth -= 1;
+                }
+                _ => {}
+            }
         }
     }
 
@@ -156,8 +312,8 @@ impl Scanner {
         while Scanner::is_alphanumeric(self.peek()) {
             self.advance();
         }
-        let text = &self.source[self.start as usize..self.current as usize];
-        let token_type = *KEYWORDS.get(text).unwrap_or(&IDENTIFIER);
+        let text = self.lexeme();
+        let token_type = *KEYWORDS.get(&text).unwrap_or(&IDENTIFIER);
         match token_type {
             TRUE => self.add_token_with_literal(TRUE, Some(Literal::Bool(true))),
             FALSE => self.add_token_with_literal(FALSE, Some(Literal::Bool(false))),
@@ -165,41 +321,157 @@ impl Scanner {
             _ => self.add_token(token_type),
         }
     }
-    ///识别字符串
+    ///识别字符串，同时处理其中的转义字符
     fn string(&mut self) {
+        let mut value = String::new();
         while self.peek() != '"' && !self.is_at_end() {
-            if self.peek() == '\n' {
-                self.line += 1;
+            match self.advance() {
+                '\\' => {
+                    if let Some(c) = self.escape() {
+                        value.push(c);
+                    }
+                }
+                c => {
+                    if c == '\n' {
+                        self.new_line();
+                    }
+                    value.push(c);
+                }
             }
-            self.advance();
         }
 
         if self.is_at_end() {
-            Lox::error_with_line(self.line, "Unterminated string.");
+            self.error("Unterminated string.".to_string());
             return;
         }
 
         self.advance();
+        self.add_token_with_literal(STRING, Some(Literal::String(value)));
+    }
 
-        let value = &self.source[self.start as usize + 1..self.current as usize - 1];
-        self.add_token_with_literal(STRING, Some(Literal::String(value.to_string())));
+    ///识别`\`之后的转义字符，支持`\n` `\t` `\r` `\\` `\"` `\0`，无法识别时报告错误并返回None
+    fn escape(&mut self) -> Option<char> {
+        if self.is_at_end() {
+            return None;
+        }
+        let c = self.advance();
+        match c {
+            'n' => Some('\n'),
+            't' => Some('\t'),
+            'r' => Some('\r'),
+            '\\' => Some('\\'),
+            '"' => Some('"'),
+            '0' => Some('\0'),
+            'u' => self.unicode_escape(),
+            _ => {
+                if c == '\n' {
+                    self.new_line();
+                }
+                self.error(format!("Invalid escape sequence '\\{}'.", c));
+                None
+            }
+        }
     }
 
-    ///识别数字
+    ///识别`\u{1F600}`形式的转义，大括号中是1到6位十六进制数表示的Unicode码点
+    fn unicode_escape(&mut self) -> Option<char> {
+        if !self.match_char('{') {
+            self.error("Expect '{' after '\\u'.".to_string());
+            return None;
+        }
+        let mut digits = String::new();
+        while self.peek().is_ascii_hexdigit() {
+            digits.push(self.advance());
+        }
+        if !self.match_char('}') {
+            self.error("Expect '}' after unicode escape.".to_string());
+            return None;
+        }
+        let c = if digits.is_empty() || digits.len() > 6 {
+            None
+        } else {
+            u32::from_str_radix(&digits, 16)
+                .ok()
+                .and_then(char::from_u32)
+        };
+        if c.is_none() {
+            self.error(format!("Invalid unicode escape '\\u{{{}}}'.", digits));
+        }
+        c
+    }
+
+    ///识别数字，支持用`_`分隔数字(`1_000_000`)、十六进制(`0x1F`)和二进制(`0b1010`)
     fn number(&mut self) {
-        while Scanner::is_digit(self.peek()) {
-            self.advance();
+        let first = self.source[self.start as usize];
+        if first == '0' && matches!(self.peek(), 'x' | 'X' | 'b' | 'B') {
+            let radix = if matches!(self.advance(), 'x' | 'X') {
+                16
+            } else {
+                2
+            };
+            self.radix_number(radix);
+            return;
         }
 
+        self.decimal_digits();
         if self.peek() == '.' && Scanner::is_digit(self.peek_next()) {
             self.advance();
-            while Scanner::is_digit(self.peek()) {
-                self.advance();
+            self.decimal_digits();
+        }
+
+        let text = self.lexeme();
+        if !Scanner::valid_separators(&text) {
+            self.error(format!("Invalid numeric separator in '{}'.", text));
+            return;
+        }
+        let value = text.replace('_', "").parse().unwrap();
+        self.add_token_with_literal(NUMBER, Some(Literal::Number(value)));
+    }
+
+    fn decimal_digits(&mut self) {
+        while Scanner::is_digit(self.peek()) || self.peek() == '_' {
+            self.advance();
+        }
+    }
+
+    ///识别`0x`或`0b`之后的数字，此时前缀已经读过了
+    fn radix_number(&mut self, radix: u32) {
+        while Scanner::is_alphanumeric(self.peek()) {
+            self.advance();
+        }
+        let text = self.lexeme();
+        let (prefix, digits) = text.split_at(2);
+        if digits.is_empty() {
+            self.error(format!("Expect digits after '{}'.", prefix));
+            return;
+        }
+        if !Scanner::valid_separators(digits) {
+            self.error(format!("Invalid numeric separator in '{}'.", text));
+            return;
+        }
+        let mut value = 0.0;
+        for c in digits.chars().filter(|&c| c != '_') {
+            match c.to_digit(radix) {
+                Some(d) => value = value * radix as f64 + d as f64,
+                None => {
+                    self.error(format!("Invalid digit '{}' in '{}'.", c, text));
+                    return;
+                }
             }
         }
+        self.add_token_with_literal(NUMBER, Some(Literal::Number(value)));
+    }
 
-        let value = &self.source[self.start as usize..self.current as usize];
-        self.add_token_with_literal(NUMBER, Some(Literal::Number(value.parse().unwrap())));
+    ///`_`只能出现在两个数字之间，不能连续出现，也不能在开头、结尾或小数点旁边
+    fn valid_separators(digits: &str) -> bool {
+        let chars: Vec<char> = digits.chars().collect();
+        chars.iter().enumerate().all(|(i, &c)| {
+            c != '_'
+                || (i > 0
+                    && i + 1 < chars.len()
+                    && chars[i - 1].is_ascii_alphanumeric()
+                    && chars[i + 1].is_ascii_alphanumeric())
+        })
     }
 
     /// 判断当前字符是否为expected，如果是，current指针后移一位
@@ -207,7 +479,7 @@ impl Scanner {
         if self.is_at_end() {
             return false;
         }
-        if self.source.chars().nth(self.current as usize).unwrap() != expected {
+        if self.source[self.current as usize] != expected {
             return false;
         }
 
@@ -220,7 +492,7 @@ impl Scanner {
         if self.is_at_end() {
             return '\0';
         }
-        self.source.chars().nth(self.current as usize).unwrap()
+        self.source[self.current as usize]
     }
 
     /// 预览下一个字符
@@ -228,10 +500,13 @@ impl Scanner {
         if self.current + 1 >= self.source.len() as i32 {
             return '\0';
         }
-        self.source
-            .chars()
-            .nth((self.current + 1) as usize)
-            .unwrap()
+        self.source[(self.current + 1) as usize]
+    }
+
+    /// 刚刚读过了一个`\n`，进入下一行
+    fn new_line(&mut self) {
+        self.line += 1;
+        self.line_start = self.current;
     }
 
     /// 判断是否是字母
@@ -246,29 +521,456 @@ impl Scanner {
 
     /// 判断是否是数字
     fn is_digit(c: char) -> bool {
-        c >= '0' && c <= '9'
+        c.is_ascii_digit()
     }
 
     /// 查看当前字符并将current指针后移一位
     fn advance(&mut self) -> char {
         self.current += 1;
-        self.source
-            .chars()
-            .nth((self.current - 1) as usize)
-            .unwrap()
+        self.source[(self.current - 1) as usize]
+    }
+
+    /// 当前这个词的文本，即`source`中`start`到`current`之间的字符
+    fn lexeme(&self) -> String {
+        self.source[self.start as usize..self.current as usize]
+            .iter()
+            .collect()
     }
 
     /// 添加token
     fn add_token(&mut self, token_type: TokenType) {
-        let text = &self.source[self.start as usize..self.current as usize];
         self.tokens
-            .push(Token::new(token_type, text.to_string(), None, self.line));
+            .push(Token::new(token_type, self.lexeme(), None, self.line).with_column(self.column));
     }
 
     /// 添加带有字面量的token
     fn add_token_with_literal(&mut self, token_type: TokenType, literal: Option<Literal>) {
-        let text = &self.source[self.start as usize..self.current as usize];
-        self.tokens
-            .push(Token::new(token_type, text.to_string(), literal, self.line));
+        self.tokens.push(
+            Token::new(token_type, self.lexeme(), literal, self.line).with_column(self.column),
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn scan(s: &str) -> Vec<Token> {
+        Scanner::new(s.to_string()).scan_tokens()
+    }
+
+    #[test]
+    fn test_scan_tokens_into_reuses_buffer() {
+        let lexemes = |tokens: &[Token]| -> Vec<_> {
+            tokens
+                .iter()
+                .map(|t| (t.token_type, t.lexeme.clone(), t.line))
+                .collect()
+        };
+        let mut buffer = Vec::new();
+        Scanner::new("var a = \"x\";\nprint a + 1;".to_string()).scan_tokens_into(&mut buffer);
+        assert_eq!(
+            lexemes(&buffer),
+            lexemes(&scan("var a = \"x\";\nprint a + 1;"))
+        );
+        let capacity = buffer.capacity();
+
+        //第二段代码更短，之前的token被清空，已经分配的空间被复用
+        Scanner::new("b//c".to_string()).scan_tokens_into(&mut buffer);
+        assert_eq!(lexemes(&buffer), lexemes(&scan("b//c")));
+        assert_eq!(buffer.len(), 4);
+        assert_eq!(buffer.capacity(), capacity);
+    }
+
+    #[test]
+    fn test_block_comment() {
+        let tokens = scan("var a /* one\ntwo /* nested\n */ three */ = 1;\nb");
+        let types: Vec<_> = tokens.iter().map(|t| t.token_type).collect();
+        assert_eq!(
+            types,
+            vec![VAR, IDENTIFIER, EQUAL, NUMBER, SEMICOLON, IDENTIFIER, EOF]
+        );
+        assert_eq!(tokens[2].line, 3);
+        assert_eq!(tokens[5].line, 4);
+    }
+
+    #[test]
+    fn test_floor_division() {
+        let types = |s: &str| -> Vec<_> { scan(s).iter().map(|t| t.token_type).collect() };
+        //两边都没有空白并且紧挨着操作数的结尾时是整除
+        assert_eq!(
+            types("a//b"),
+            vec![IDENTIFIER, SLASH_SLASH, IDENTIFIER, EOF]
+        );
+        assert_eq!(types("7//2"), vec![NUMBER, SLASH_SLASH, NUMBER, EOF]);
+        assert_eq!(scan("7//2")[1].lexeme, "//");
+        assert_eq!(
+            types("(a)//l[0]"),
+            vec![
+                LEFT_PAREN,
+                IDENTIFIER,
+                RIGHT_PAREN,
+                SLASH_SLASH,
+                IDENTIFIER,
+                LEFT_BRACKET,
+                NUMBER,
+                RIGHT_BRACKET,
+                EOF
+            ]
+        );
+        //其余情况都是注释
+        assert_eq!(types("a // b"), vec![IDENTIFIER, EOF]);
+        assert_eq!(types("a// b"), vec![IDENTIFIER, EOF]);
+        assert_eq!(types("a //b"), vec![IDENTIFIER, EOF]);
+        assert_eq!(types("a//"), vec![IDENTIFIER, EOF]);
+        assert_eq!(types("a;//b"), vec![IDENTIFIER, SEMICOLON, EOF]);
+        assert_eq!(types("//a//b"), vec![EOF]);
+        assert_eq!(types("=//b"), vec![EQUAL, EOF]);
+    }
+
+    #[test]
+    fn test_trailing_line_comments() {
+        let types = |s: &str| -> Vec<_> { scan(s).iter().map(|t| t.token_type).collect() };
+        assert_eq!(
+            types("var a = [1,\n 2 // two\n];"),
+            vec![
+                VAR,
+                IDENTIFIER,
+                EQUAL,
+                LEFT_BRACKET,
+                NUMBER,
+                COMMA,
+                NUMBER,
+                RIGHT_BRACKET,
+                SEMICOLON,
+                EOF
+            ]
+        );
+        assert_eq!(
+            types("class A // a class\n{ }"),
+            vec![CLASS, IDENTIFIER, LEFT_BRACE, RIGHT_BRACE, EOF]
+        );
+        assert_eq!(
+            types("init(x) // constructor\n{ }"),
+            vec![
+                IDENTIFIER,
+                LEFT_PAREN,
+                IDENTIFIER,
+                RIGHT_PAREN,
+                LEFT_BRACE,
+                RIGHT_BRACE,
+                EOF
+            ]
+        );
+        assert_eq!(
+            types("area // getter\n{ }"),
+            vec![IDENTIFIER, LEFT_BRACE, RIGHT_BRACE, EOF]
+        );
+        assert_eq!(
+            types("f(a) // call\n;"),
+            vec![
+                IDENTIFIER,
+                LEFT_PAREN,
+                IDENTIFIER,
+                RIGHT_PAREN,
+                SEMICOLON,
+                EOF
+            ]
+        );
+    }
+
+    #[test]
+    fn test_bitwise_operators() {
+        let types: Vec<_> = scan("& | ^ << >> <= >= < >")
+            .iter()
+            .map(|t| t.token_type)
+            .collect();
+        assert_eq!(
+            types,
+            vec![
+                AMPERSAND,
+                PIPE,
+                CARET,
+                LESS_LESS,
+                GREATER_GREATER,
+                LESS_EQUAL,
+                GREATER_EQUAL,
+                LESS,
+                GREATER,
+                EOF
+            ]
+        );
+    }
+
+    #[test]
+    fn test_question_question() {
+        let types: Vec<_> = scan("a ?? b ? c : d ???")
+            .iter()
+            .map(|t| t.token_type)
+            .collect();
+        assert_eq!(
+            types,
+            vec![
+                IDENTIFIER,
+                QUESTION_QUESTION,
+                IDENTIFIER,
+                QUESTION,
+                IDENTIFIER,
+                COLON,
+                IDENTIFIER,
+                QUESTION_QUESTION,
+                QUESTION,
+                EOF
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compound_assignment_operators() {
+        let types: Vec<_> = scan("+= -= *= /= + - * /")
+            .iter()
+            .map(|t| t.token_type)
+            .collect();
+        assert_eq!(
+            types,
+            vec![
+                PLUS_EQUAL,
+                MINUS_EQUAL,
+                STAR_EQUAL,
+                SLASH_EQUAL,
+                PLUS,
+                MINUS,
+                STAR,
+                SLASH,
+                EOF
+            ]
+        );
+    }
+
+    #[test]
+    fn test_token_columns() {
+        let tokens = scan("var a = 1;\n  print /* x\ny */ a;");
+        let positions: Vec<_> = tokens.iter().map(|t| (t.line, t.column)).collect();
+        assert_eq!(
+            positions,
+            vec![
+                (1, 1),
+                (1, 5),
+                (1, 7),
+                (1, 9),
+                (1, 10),
+                (2, 3),
+                (3, 6),
+                (3, 7),
+                (3, 8)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_string_escapes() {
+        let tokens = scan("\"a\\tb\" \"\\\"q\\\" \\\\ \\n\\r\\0\"");
+        assert_eq!(tokens[0].literal, Some(Literal::String("a\tb".to_string())));
+        match &tokens[0].literal {
+            Some(Literal::String(s)) => assert_eq!(s.len(), 3),
+            other => panic!("expected a string, got {:?}", other),
+        }
+        assert_eq!(
+            tokens[1].literal,
+            Some(Literal::String("\"q\" \\ \n\r\0".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_unicode_escapes() {
+        let tokens = scan("\"\\u{48}\\u{69}\" \"\\u{1F600}\\u{e9}\"");
+        assert_eq!(tokens[0].literal, Some(Literal::String("Hi".to_string())));
+        assert_eq!(
+            tokens[1].literal,
+            Some(Literal::String("\u{1F600}\u{e9}".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_invalid_unicode_escapes() {
+        for (src, expected_column, expected) in [
+            (
+                "\"\\u{110000}\"",
+                11,
+                "Invalid unicode escape '\\u{110000}'.",
+            ),
+            ("\"\\u{D800}\"", 9, "Invalid unicode escape '\\u{D800}'."),
+            ("\"\\u{}\"", 5, "Invalid unicode escape '\\u{}'."),
+            (
+                "\"\\u{0000041}\"",
+                12,
+                "Invalid unicode escape '\\u{0000041}'.",
+            ),
+            ("\"\\u41\"", 3, "Expect '{' after '\\u'."),
+            ("\"\\u{4g}\"", 5, "Expect '}' after unicode escape."),
+            ("\n\"\\u{41\"", 6, "Expect '}' after unicode escape."),
+        ] {
+            let mut scanner = Scanner::new(src.to_string());
+            scanner.scan_tokens();
+            match scanner.errors() {
+                [LoxResult::ScanError {
+                    line,
+                    column,
+                    message,
+                }] => {
+                    assert_eq!(*line, src.lines().count() as i32, "{}", src);
+                    assert_eq!(*column, expected_column, "{}", src);
+                    assert_eq!(message, expected);
+                }
+                other => panic!("expected one scan error for {}, got {:?}", src, other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_invalid_escape() {
+        let mut scanner = Scanner::new("\"\\q\"".to_string());
+        let tokens = scanner.scan_tokens();
+        assert_eq!(tokens[0].literal, Some(Literal::String(String::new())));
+        match scanner.errors() {
+            [LoxResult::ScanError {
+                line,
+                column,
+                message,
+            }] => {
+                assert_eq!((*line, *column), (1, 3));
+                assert_eq!(message, "Invalid escape sequence '\\q'.");
+            }
+            other => panic!("expected one scan error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_unterminated_block_comment() {
+        let mut scanner = Scanner::new("a /* never\nclosed".to_string());
+        let tokens = scanner.scan_tokens();
+        assert_eq!(tokens.len(), 2);
+        assert_eq!(tokens[1].token_type, EOF);
+        assert_eq!(tokens[1].line, 2);
+        match scanner.errors() {
+            [LoxResult::ScanError {
+                line,
+                column,
+                message,
+            }] => {
+                assert_eq!((*line, *column), (2, 6));
+                assert_eq!(message, "Unterminated block comment.");
+            }
+            other => panic!("expected one scan error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_multibyte_lexemes() {
+        let tokens = scan("var a = \"你好\" + \"🦀\"; x");
+        let lexemes: Vec<_> = tokens.iter().map(|t| t.lexeme.as_str()).collect();
+        assert_eq!(
+            lexemes,
+            vec!["var", "a", "=", "\"你好\"", "+", "\"🦀\"", ";", "x", ""]
+        );
+        assert_eq!(tokens[3].literal, Some(Literal::String("你好".to_string())));
+        assert_eq!(tokens[7].column, 21);
+    }
+
+    ///如果每读一个字符都从头遍历源码，扫描的耗时是平方级的，这样大小的输入几乎无法扫描完
+    #[test]
+    fn test_large_input() {
+        let source = "var x = \"文字\" + 12.5; // 注释\n".repeat(20_000);
+        let tokens = scan(&source);
+        assert_eq!(tokens.len(), 7 * 20_000 + 1);
+        assert_eq!(tokens.last().unwrap().line, 20_001);
+    }
+
+    fn number(s: &str) -> Option<f64> {
+        match scan(s).first().and_then(|t| t.literal.clone()) {
+            Some(Literal::Number(n)) => Some(n),
+            _ => None,
+        }
+    }
+
+    fn scan_error(s: &str) -> String {
+        let mut scanner = Scanner::new(s.to_string());
+        scanner.scan_tokens();
+        match scanner.errors() {
+            [LoxResult::ScanError {
+                line,
+                column,
+                message,
+            }] => format!("{}:{}: {}", line, column, message),
+            other => panic!("expected one scan error for {}, got {:?}", s, other),
+        }
+    }
+
+    #[test]
+    fn test_number_literals() {
+        assert_eq!(number("42"), Some(42.0));
+        assert_eq!(number("3.5"), Some(3.5));
+        assert_eq!(number("1_000_000"), Some(1_000_000.0));
+        assert_eq!(number("1_000.000_5"), Some(1000.0005));
+        assert_eq!(number("0x1F"), Some(31.0));
+        assert_eq!(number("0XfF_ff"), Some(65535.0));
+        assert_eq!(number("0b1010"), Some(10.0));
+        assert_eq!(number("0B1111_0000"), Some(240.0));
+        assert_eq!(number("0"), Some(0.0));
+    }
+
+    #[test]
+    fn test_malformed_number_literals() {
+        assert_eq!(scan_error("0x"), "1:2: Expect digits after '0x'.");
+        assert_eq!(scan_error("\n0b;"), "2:2: Expect digits after '0b'.");
+        assert_eq!(
+            scan_error("1__0"),
+            "1:4: Invalid numeric separator in '1__0'."
+        );
+        assert_eq!(scan_error("1_"), "1:2: Invalid numeric separator in '1_'.");
+        assert_eq!(
+            scan_error("1_.5"),
+            "1:4: Invalid numeric separator in '1_.5'."
+        );
+        assert_eq!(
+            scan_error("0x_1"),
+            "1:4: Invalid numeric separator in '0x_1'."
+        );
+        assert_eq!(scan_error("0b102"), "1:5: Invalid digit '2' in '0b102'.");
+        assert_eq!(scan_error("0x1G"), "1:4: Invalid digit 'G' in '0x1G'.");
+    }
+
+    #[test]
+    fn test_scan_error_display() {
+        let mut scanner = Scanner::new("var a = 1;\nvar b @ 2;\n\"abc".to_string());
+        scanner.scan_tokens();
+        let messages: Vec<_> = scanner.errors().iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            [
+                "[line 2, column 7] Error : Unexpected character.",
+                "[line 3, column 4] Error : Unterminated string."
+            ]
+        );
+    }
+
+    #[test]
+    fn test_error_limit() {
+        let mut scanner = Scanner::new(format!("a {} b", "@".repeat(100)));
+        let tokens = scanner.scan_tokens();
+        assert_eq!(scanner.errors().len(), DEFAULT_ERROR_LIMIT + 1);
+        assert!(matches!(scanner.errors().last(),
+            Some(LoxResult::ScanError { message, .. }) if message == "Too many errors; stopping."));
+        let types: Vec<_> = tokens.iter().map(|t| t.token_type).collect();
+        assert_eq!(types, vec![IDENTIFIER, EOF]);
+
+        let mut scanner = Scanner::new("@@@@@ x".to_string()).with_error_limit(3);
+        scanner.scan_tokens();
+        assert_eq!(scanner.errors().len(), 4);
+
+        let mut scanner = Scanner::new("@@@ x".to_string()).with_error_limit(3);
+        let tokens = scanner.scan_tokens();
+        assert_eq!(scanner.errors().len(), 3);
+        assert_eq!(tokens.len(), 2);
     }
 }
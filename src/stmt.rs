@@ -1,39 +1,218 @@
-use crate::{expr::Expr, token::Token};
+use crate::{
+    expr::Expr,
+    token::{Span, Token},
+};
 
 ///定义了statement，以下是语言中的各种statement
+///
+///和[`Expr`]一样，每条语句的`span`记录了它在源代码中的范围
 #[derive(Debug, PartialEq, Clone)]
 pub enum Stmt {
     Expression {
         expression: Box<Expr>,
+        span: Span,
     },
+    ///`print a, b;`打印用空格隔开的每个值，`print;`只打印一个换行
     Print {
-        expression: Box<Expr>,
+        expressions: Vec<Expr>,
+        span: Span,
     },
     Var {
         name: Token,
         initializer: Option<Box<Expr>>,
+        span: Span,
+    },
+    ///`var a = 1, b = a + 1;`，每一项都是一个[`Stmt::Var`]，在当前作用域中按顺序定义
+    VarGroup {
+        declarations: Vec<Stmt>,
+        span: Span,
     },
     Block {
         statements: Vec<Stmt>,
+        span: Span,
     },
     If {
         condition: Box<Expr>,
         then_branch: Box<Stmt>,
         else_branch: Option<Box<Stmt>>,
+        span: Span,
     },
+    ///`increment`只由`for`循环产生，它在每次循环体结束后执行，`continue`也不会跳过它
     While {
         condition: Box<Expr>,
         body: Box<Stmt>,
+        increment: Option<Box<Expr>>,
+        span: Span,
+    },
+    ///`for (name in iterable) body`，每次循环都在新的作用域中把`name`绑定到一个元素
+    ForIn {
+        name: Token,
+        iterable: Box<Expr>,
+        body: Box<Stmt>,
+        span: Span,
     },
     Function {
         name: Token,
         params: Vec<Token>,
         body: Vec<Stmt>,
+        span: Span,
+    },
+    ///`superclass`是[`Expr::Variable`]，`methods`、`getters`和`class_methods`中都是[`Stmt::Function`]
+    ///
+    ///getter声明时没有参数列表，读取属性时就会被调用，它们的`params`总是空的；
+    ///`class_methods`是以`class`开头声明的静态方法，通过类名调用
+    Class {
+        name: Token,
+        superclass: Option<Expr>,
+        methods: Vec<Stmt>,
+        getters: Vec<Stmt>,
+        class_methods: Vec<Stmt>,
+        span: Span,
     },
     Return {
         keyword: Token,
         value: Option<Expr>,
+        span: Span,
+    },
+    ///跳出最内层的循环
+    Break {
+        keyword: Token,
+        span: Span,
+    },
+    ///结束本次循环，进入下一次
+    Continue {
+        keyword: Token,
+        span: Span,
+    },
+    ///`assert expression : message;`，表达式为假时产生运行时错误，`message`可以省略
+    Assert {
+        keyword: Token,
+        expression: Box<Expr>,
+        message: Option<Box<Expr>>,
+        span: Span,
+    },
+    ///在所在的块退出时才执行的语句
+    Defer {
+        keyword: Token,
+        statement: Box<Stmt>,
+        span: Span,
+    },
+    ///`try { body } catch (catch_var) { catch_body }`，`body`中的运行时错误会被接住，
+    ///错误信息绑定到`catch_var`后执行`catch_body`
+    Try {
+        body: Vec<Stmt>,
+        catch_var: Token,
+        catch_body: Vec<Stmt>,
+        span: Span,
     },
 }
 
-impl Stmt {}
+impl Stmt {
+    ///节点在源代码中的范围，见[`Span`]
+    pub fn span(&self) -> Span {
+        match self {
+            Stmt::Expression { span, .. }
+            | Stmt::Print { span, .. }
+            | Stmt::Var { span, .. }
+            | Stmt::VarGroup { span, .. }
+            | Stmt::Block { span, .. }
+            | Stmt::If { span, .. }
+            | Stmt::While { span, .. }
+            | Stmt::ForIn { span, .. }
+            | Stmt::Function { span, .. }
+            | Stmt::Class { span, .. }
+            | Stmt::Return { span, .. }
+            | Stmt::Break { span, .. }
+            | Stmt::Continue { span, .. }
+            | Stmt::Assert { span, .. }
+            | Stmt::Defer { span, .. }
+            | Stmt::Try { span, .. } => *span,
+        }
+    }
+
+    ///为[`Stmt`]类型实现观察者模式，与[`Expr::accept`]相同
+    pub fn accept<R>(&self, visitor: &impl StmtVisitor<R>) -> R {
+        match self {
+            Stmt::Expression { expression, .. } => visitor.visit_expression_stmt(expression),
+            Stmt::Print { expressions, .. } => visitor.visit_print(expressions),
+            Stmt::Var {
+                name, initializer, ..
+            } => visitor.visit_var(name, initializer.as_deref()),
+            Stmt::VarGroup { declarations, .. } => visitor.visit_var_group(declarations),
+            Stmt::Block { statements, .. } => visitor.visit_block(statements),
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => visitor.visit_if(condition, then_branch, else_branch.as_deref()),
+            Stmt::While {
+                condition,
+                body,
+                increment,
+                ..
+            } => visitor.visit_while(condition, body, increment.as_deref()),
+            Stmt::ForIn {
+                name,
+                iterable,
+                body,
+                ..
+            } => visitor.visit_for_in(name, iterable, body),
+            Stmt::Function {
+                name, params, body, ..
+            } => visitor.visit_function(name, params, body),
+            Stmt::Class {
+                name,
+                superclass,
+                methods,
+                getters,
+                class_methods,
+                ..
+            } => visitor.visit_class(name, superclass.as_ref(), methods, getters, class_methods),
+            Stmt::Return { keyword, value, .. } => visitor.visit_return(keyword, value.as_ref()),
+            Stmt::Break { keyword, .. } => visitor.visit_break(keyword),
+            Stmt::Continue { keyword, .. } => visitor.visit_continue(keyword),
+            Stmt::Assert {
+                keyword,
+                expression,
+                message,
+                ..
+            } => visitor.visit_assert(keyword, expression, message.as_deref()),
+            Stmt::Defer {
+                keyword, statement, ..
+            } => visitor.visit_defer(keyword, statement),
+            Stmt::Try {
+                body,
+                catch_var,
+                catch_body,
+                ..
+            } => visitor.visit_try(body, catch_var, catch_body),
+        }
+    }
+}
+
+pub trait StmtVisitor<R> {
+    fn visit_expression_stmt(&self, expression: &Expr) -> R;
+    fn visit_print(&self, expressions: &[Expr]) -> R;
+    fn visit_var(&self, name: &Token, initializer: Option<&Expr>) -> R;
+    fn visit_var_group(&self, declarations: &[Stmt]) -> R;
+    fn visit_block(&self, statements: &[Stmt]) -> R;
+    fn visit_if(&self, condition: &Expr, then_branch: &Stmt, else_branch: Option<&Stmt>) -> R;
+    fn visit_while(&self, condition: &Expr, body: &Stmt, increment: Option<&Expr>) -> R;
+    fn visit_for_in(&self, name: &Token, iterable: &Expr, body: &Stmt) -> R;
+    fn visit_function(&self, name: &Token, params: &[Token], body: &[Stmt]) -> R;
+    fn visit_class(
+        &self,
+        name: &Token,
+        superclass: Option<&Expr>,
+        methods: &[Stmt],
+        getters: &[Stmt],
+        class_methods: &[Stmt],
+    ) -> R;
+    fn visit_return(&self, keyword: &Token, value: Option<&Expr>) -> R;
+    fn visit_break(&self, keyword: &Token) -> R;
+    fn visit_continue(&self, keyword: &Token) -> R;
+    fn visit_assert(&self, keyword: &Token, expression: &Expr, message: Option<&Expr>) -> R;
+    fn visit_defer(&self, keyword: &Token, statement: &Stmt) -> R;
+    fn visit_try(&self, body: &[Stmt], catch_var: &Token, catch_body: &[Stmt]) -> R;
+}
@@ -1,4 +1,5 @@
 use crate::token_type::TokenType;
+use crate::value::format_number;
 use std::fmt::Display;
 
 ///Token结构体
@@ -12,6 +13,8 @@ pub struct Token {
     pub literal: Option<Literal>,
     ///token所在的行数
     pub line: i32,
+    ///token的第一个字符在所在行中的列数，从1开始，0表示不知道所在的列
+    pub column: i32,
 }
 
 impl Token {
@@ -26,8 +29,43 @@ impl Token {
             lexeme,
             literal,
             line,
+            column: 0,
         }
     }
+
+    ///设置token所在的列
+    pub fn with_column(mut self, column: i32) -> Token {
+        self.column = column;
+        self
+    }
+}
+
+///语法树节点在源代码中的范围，由节点的第一个和最后一个token决定
+///
+///位置都是`(行, 列)`，与[`Token`]的`line`和`column`相同
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Span {
+    ///第一个token的第一个字符所在的位置
+    pub start: (i32, i32),
+    ///最后一个token的最后一个字符之后的位置
+    pub end: (i32, i32),
+}
+
+impl Span {
+    ///从`first`的开头到`last`的结尾
+    pub fn new(first: &Token, last: &Token) -> Span {
+        let width = last.lexeme.chars().count() as i32;
+        Span {
+            start: (first.line, first.column),
+            end: (last.line, last.column + width),
+        }
+    }
+
+    ///`token`是否在范围之内，可以用来找到运行时错误发生在哪个节点中
+    pub fn contains(&self, token: &Token) -> bool {
+        let position = (token.line, token.column);
+        self.start <= position && position < self.end
+    }
 }
 
 ///用于记录token内部的值
@@ -45,7 +83,7 @@ impl Display for Literal {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Literal::String(s) => write!(f, "{}", s),
-            Literal::Number(n) => write!(f, "{}", n),
+            Literal::Number(n) => write!(f, "{}", format_number(*n)),
             Literal::Bool(b) => write!(f, "{}", b),
             Literal::Nil => write!(f, "nil"),
         }
@@ -1,4 +1,5 @@
 ///定义了lox语言中的所有token
+#[allow(non_camel_case_types)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TokenType {
     /// Single-character tokens.
@@ -6,6 +7,8 @@ pub enum TokenType {
     RIGHT_PAREN,
     LEFT_BRACE,
     RIGHT_BRACE,
+    LEFT_BRACKET,
+    RIGHT_BRACKET,
     COMMA,
     DOT,
     MINUS,
@@ -13,6 +16,11 @@ pub enum TokenType {
     SEMICOLON,
     SLASH,
     STAR,
+    QUESTION,
+    COLON,
+    AMPERSAND,
+    PIPE,
+    CARET,
 
     /// One or two character tokens.
     BANG,
@@ -23,6 +31,14 @@ pub enum TokenType {
     GREATER_EQUAL,
     LESS,
     LESS_EQUAL,
+    LESS_LESS,
+    GREATER_GREATER,
+    PLUS_EQUAL,
+    MINUS_EQUAL,
+    STAR_EQUAL,
+    SLASH_EQUAL,
+    SLASH_SLASH,
+    QUESTION_QUESTION,
 
     /// Literals.
     IDENTIFIER,
@@ -31,12 +47,18 @@ pub enum TokenType {
 
     /// Keywords.
     AND,
+    ASSERT,
+    BREAK,
+    CATCH,
     CLASS,
+    CONTINUE,
+    DEFER,
     ELSE,
     FALSE,
     FUN,
     FOR,
     IF,
+    IN,
     NIL,
     OR,
     PRINT,
@@ -44,6 +66,7 @@ pub enum TokenType {
     SUPER,
     THIS,
     TRUE,
+    TRY,
     VAR,
     WHILE,
 
@@ -1,6 +1,17 @@
-use crate::{loxcallable::LoxCallable, loxfunction::LoxFunction};
+use crate::{
+    loxcallable::{Arity, LoxCallable},
+    loxclass::LoxClass,
+    loxfunction::LoxFunction,
+    loxinstance::LoxInstance,
+    loxresult::LoxResult,
+    natives::NativeFunction,
+    token::Token,
+    token_type::TokenType,
+};
+use std::cell::RefCell;
 use std::cmp::Ordering;
 use std::fmt::Display;
+use std::rc::Rc;
 
 ///定义了lox中的数据类型
 #[derive(Debug, PartialEq, Clone)]
@@ -10,6 +21,13 @@ pub enum Value {
     String(String),
     Nil,
     LoxFunction(LoxFunction),
+    LoxClass(Rc<LoxClass>),
+    LoxInstance(Rc<RefCell<LoxInstance>>),
+    NativeFunction(NativeFunction),
+    ///列表，多个变量可以共享同一个列表
+    List(Rc<RefCell<Vec<Value>>>),
+    ///`var a;`声明了但还没有赋值的变量，只会存放在环境中，读取这样的变量是运行时错误
+    Uninitialized,
 }
 
 ///为[`Value`]实现了比较功能
@@ -25,124 +43,444 @@ impl PartialOrd for Value {
             | (Value::Boolean(_), _)
             | (Value::String(_), _)
             | (Value::Nil, _) => None,
-            (Value::LoxFunction(_), _) => None,
+            (Value::LoxFunction(_), _)
+            | (Value::LoxClass(_), _)
+            | (Value::LoxInstance(_), _)
+            | (Value::NativeFunction(_), _)
+            | (Value::List(_), _)
+            | (Value::Uninitialized, _) => None,
         }
     }
     // add code here
 }
 
-///如果value为[`Value::Boolean`] 的 true，则返回true，其他情况返回返回false
+///lox的真值规则：只有[`Value::Nil`]和[`Value::Boolean`]的false为假，其他值都为真
 impl Value {
     pub fn is_true(&self) -> bool {
-        if let &Value::Boolean(b) = self {
-            b
-        } else {
-            false
+        !matches!(self, Value::Nil | Value::Boolean(false))
+    }
+
+    ///lox中`==`和`!=`的规则，不使用derive出的[`PartialEq`]
+    ///
+    ///不同类型的值总是不相等，所以`nil == false`和`1 == "1"`都是false。
+    ///数字按IEEE 754比较，`NaN`和任何数都不相等，包括它自己。
+    ///字符串按内容比较，函数、类、实例和列表只和它们自己相等
+    pub fn is_equal(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Number(a), Value::Number(b)) => a == b,
+            (Value::Boolean(a), Value::Boolean(b)) => a == b,
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::Nil, Value::Nil) => true,
+            (Value::LoxFunction(a), Value::LoxFunction(b)) => a.is_same(b),
+            (Value::LoxClass(a), Value::LoxClass(b)) => Rc::ptr_eq(a, b),
+            (Value::LoxInstance(a), Value::LoxInstance(b)) => Rc::ptr_eq(a, b),
+            (Value::List(a), Value::List(b)) => Rc::ptr_eq(a, b),
+            (Value::NativeFunction(a), Value::NativeFunction(b)) => a == b,
+            _ => false,
+        }
+    }
+
+    ///值的类型名，也是原生函数`typeof`的返回值
+    ///
+    ///lox函数和原生函数都是`"function"`
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Number(_) => "number",
+            Value::Boolean(_) => "boolean",
+            Value::String(_) => "string",
+            Value::Nil => "nil",
+            Value::LoxFunction(_) | Value::NativeFunction(_) => "function",
+            Value::LoxClass(_) => "class",
+            Value::LoxInstance(_) => "instance",
+            Value::List(_) => "list",
+            Value::Uninitialized => "uninitialized",
         }
     }
 }
 
-///为[`Value::LoxFunction`]实现了调用功能,如果[`Value`] 类型不为[`Value::LoxFunction`], 则[`unreachable`]
+///为[`Value::LoxFunction`]、[`Value::LoxClass`]和[`Value::NativeFunction`]实现了调用功能,如果[`Value`] 类型不可调用, 则[`unreachable`]
 impl LoxCallable for Value {
     fn call(
         &self,
         interpreter: &mut crate::interpreter::Interpreter,
+        paren: &crate::token::Token,
         arguments: Vec<Value>,
     ) -> Result<Value, crate::loxresult::LoxResult> {
         match self {
             //WARNING: error may occur
-            Value::LoxFunction(func) => func.call(interpreter, arguments),
+            Value::LoxFunction(func) => func.call(interpreter, paren, arguments),
+            Value::LoxClass(class) => class.call(interpreter, paren, arguments),
+            Value::NativeFunction(native) => native.call(interpreter, paren, arguments),
             _ => unreachable!(),
         }
     }
 
-    fn arity(&self) -> usize {
+    fn arity(&self) -> Arity {
         match self {
             Value::LoxFunction(func) => func.arity(),
+            Value::LoxClass(class) => class.arity(),
+            Value::NativeFunction(native) => native.arity(),
             _ => unreachable!(),
         }
     }
 }
+
+///把数字转换成lox中打印的样子，[`Value`]和[`crate::token::Literal`]都使用它
+///
+///整数不带小数点，比如`1.0`打印成`1`；绝对值不小于1e21的数使用科学计数法，比如`1e21`
+///
+///特殊值和JavaScript一样打印成`Infinity`、`-Infinity`和`NaN`
+pub fn format_number(n: f64) -> String {
+    if n.is_nan() {
+        "NaN".to_string()
+    } else if n.is_infinite() {
+        if n > 0.0 { "Infinity" } else { "-Infinity" }.to_string()
+    } else if n.abs() >= 1e21 {
+        format!("{:e}", n)
+    } else {
+        format!("{}", n)
+    }
+}
+
 impl Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Value::Number(n) => write!(f, "{}", n),
+            Value::Number(n) => write!(f, "{}", format_number(*n)),
             Value::Boolean(b) => write!(f, "{}", b),
             Value::String(s) => write!(f, "{}", s),
             Value::Nil => write!(f, "nil"),
             Value::LoxFunction(func) => write!(f, "{}", func),
+            Value::LoxClass(class) => write!(f, "{}", class),
+            Value::LoxInstance(instance) => write!(f, "{}", instance.borrow()),
+            Value::NativeFunction(native) => write!(f, "{}", native),
+            Value::Uninitialized => write!(f, "<uninitialized>"),
+            Value::List(list) => write_list(list, f, &mut Vec::new()),
         }
     }
 }
 
-impl std::ops::Neg for Value {
-    type Output = Self;
-
-    fn neg(self) -> Self::Output {
-        match self {
-            Value::Number(n) => Value::Number(-n),
-            _ => panic!("Unary negation is only defined for numbers"),
+///递归地打印列表，其中的字符串带引号，而顶层的字符串原样打印
+///
+///`open`是正在打印的列表，再次遇到其中的列表时打印成`[...]`，这样包含自己的列表不会无限递归
+fn write_list(
+    list: &Rc<RefCell<Vec<Value>>>,
+    f: &mut std::fmt::Formatter<'_>,
+    open: &mut Vec<*const RefCell<Vec<Value>>>,
+) -> std::fmt::Result {
+    if open.contains(&Rc::as_ptr(list)) {
+        return write!(f, "[...]");
+    }
+    open.push(Rc::as_ptr(list));
+    write!(f, "[")?;
+    for (i, element) in list.borrow().iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        match element {
+            Value::String(s) => write!(f, "{:?}", s)?,
+            Value::List(inner) => write_list(inner, f, open)?,
+            element => write!(f, "{}", element)?,
         }
     }
+    open.pop();
+    write!(f, "]")
 }
 
-impl std::ops::Add for Value {
-    type Output = Self;
+///算术运算，操作数的类型不对时返回指向`operator`的运行时错误，而不是让整个程序panic
+impl Value {
+    pub fn neg(self, operator: &Token) -> Result<Value, LoxResult> {
+        match self {
+            Value::Number(n) => Ok(Value::Number(-n)),
+            _ => Err(LoxResult::RuntimeError {
+                token: operator.clone(),
+                message: "Operand must be a number.".to_string(),
+            }),
+        }
+    }
 
-    fn add(self, other: Self) -> Self::Output {
+    ///两个数字相加；只要有一边是字符串，另一边就按打印的形式转换成字符串再拼接
+    pub fn add(self, other: Value, operator: &Token) -> Result<Value, LoxResult> {
         match (self, other) {
-            (Value::Number(l), Value::Number(r)) => Value::Number(l + r),
-            (Value::String(mut l), Value::String(r)) => {
-                l.push_str(&r);
-                Value::String(l)
+            (Value::Number(l), Value::Number(r)) => Ok(Value::Number(l + r)),
+            (Value::String(mut l), r) => {
+                l.push_str(&r.to_string());
+                Ok(Value::String(l))
             }
-            _ => panic!("Addition is only defined for two numbers or two strings"),
+            (l, Value::String(r)) => Ok(Value::String(format!("{}{}", l, r))),
+            _ => Err(LoxResult::RuntimeError {
+                token: operator.clone(),
+                message: "Operands must be two numbers or include a string.".to_string(),
+            }),
+        }
+    }
+
+    pub fn sub(self, other: Value, operator: &Token) -> Result<Value, LoxResult> {
+        let (l, r) = Value::number_operands(self, other, operator)?;
+        Ok(Value::Number(l - r))
+    }
+
+    pub fn mul(self, other: Value, operator: &Token) -> Result<Value, LoxResult> {
+        let (l, r) = Value::number_operands(self, other, operator)?;
+        Ok(Value::Number(l * r))
+    }
+
+    ///浮点除法，除数为0时返回运行时错误
+    pub fn div(self, other: Value, operator: &Token) -> Result<Value, LoxResult> {
+        let (l, r) = Value::number_operands(self, other, operator)?;
+        if r == 0.0 {
+            return Err(LoxResult::RuntimeError {
+                token: operator.clone(),
+                message: "Division by zero.".to_string(),
+            });
+        }
+        Ok(Value::Number(l / r))
+    }
+
+    ///`//`整除，结果是`l / r`向下取整，所以`-7//2`是`-4`
+    pub fn floor_div(self, other: Value, operator: &Token) -> Result<Value, LoxResult> {
+        match self.div(other, operator)? {
+            Value::Number(n) => Ok(Value::Number(n.floor())),
+            _ => unreachable!(),
+        }
+    }
+
+    ///按位运算`&`、`|`、`^`、`<<`和`>>`，两边都必须是整数，按`i64`计算后再转换回数字
+    ///
+    ///移位的位数必须在0到63之间，`>>`是算术右移
+    pub fn bitwise(self, other: Value, operator: &Token) -> Result<Value, LoxResult> {
+        let (l, r) = Value::number_operands(self, other, operator)?;
+        let error = |message: &str| LoxResult::RuntimeError {
+            token: operator.clone(),
+            message: message.to_string(),
+        };
+        let (l, r) = match (Value::to_integer(l), Value::to_integer(r)) {
+            (Some(l), Some(r)) => (l, r),
+            _ => return Err(error("Operands must be integers.")),
+        };
+        let shift = || {
+            u32::try_from(r)
+                .ok()
+                .filter(|r| *r < i64::BITS)
+                .ok_or_else(|| error("Shift amount must be between 0 and 63."))
+        };
+        let result = match operator.token_type {
+            TokenType::AMPERSAND => l & r,
+            TokenType::PIPE => l | r,
+            TokenType::CARET => l ^ r,
+            TokenType::LESS_LESS => l << shift()?,
+            TokenType::GREATER_GREATER => l >> shift()?,
+            _ => unreachable!(),
+        };
+        Ok(Value::Number(result as f64))
+    }
+
+    ///没有小数部分并且在`i64`范围内的数字才能参与按位运算
+    fn to_integer(n: f64) -> Option<i64> {
+        if n.fract() == 0.0 && n >= i64::MIN as f64 && n < i64::MAX as f64 {
+            Some(n as i64)
+        } else {
+            None
+        }
+    }
+
+    fn number_operands(
+        left: Value,
+        right: Value,
+        operator: &Token,
+    ) -> Result<(f64, f64), LoxResult> {
+        match (left, right) {
+            (Value::Number(l), Value::Number(r)) => Ok((l, r)),
+            _ => Err(LoxResult::RuntimeError {
+                token: operator.clone(),
+                message: "Operands must be numbers.".to_string(),
+            }),
         }
     }
 }
 
-impl std::ops::Sub for Value {
+impl std::ops::Not for Value {
     type Output = Self;
 
-    fn sub(self, other: Self) -> Self::Output {
-        match (self, other) {
-            (Value::Number(l), Value::Number(r)) => Value::Number(l - r),
-            _ => panic!("Subtraction is only defined for two numbers"),
+    fn not(self) -> Self::Output {
+        Value::Boolean(!self.is_true())
+    }
+}
+
+///方便嵌入解释器的程序构造[`Value`]，比如在原生函数中写`Ok(x.into())`
+impl From<f64> for Value {
+    fn from(n: f64) -> Self {
+        Value::Number(n)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Boolean(b)
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::String(s)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::String(s.to_string())
+    }
+}
+
+impl From<()> for Value {
+    fn from(_: ()) -> Self {
+        Value::Nil
+    }
+}
+
+///取出[`Value`]中的数字，类型不符时返回错误信息，比如`Expected a number but got string.`
+impl TryFrom<Value> for f64 {
+    type Error = String;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Number(n) => Ok(n),
+            value => Err(value.type_mismatch("number")),
         }
     }
 }
 
-impl std::ops::Mul for Value {
-    type Output = Self;
+impl TryFrom<Value> for bool {
+    type Error = String;
 
-    fn mul(self, other: Self) -> Self::Output {
-        match (self, other) {
-            (Value::Number(l), Value::Number(r)) => Value::Number(l * r),
-            _ => panic!("Multiplication is only defined for two numbers"),
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Boolean(b) => Ok(b),
+            value => Err(value.type_mismatch("boolean")),
         }
     }
 }
 
-impl std::ops::Div for Value {
-    type Output = Self;
+impl TryFrom<Value> for String {
+    type Error = String;
 
-    fn div(self, other: Self) -> Self::Output {
-        match (self, other) {
-            (Value::Number(l), Value::Number(r)) => Value::Number(l / r),
-            _ => panic!("Division is only defined for two numbers"),
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::String(s) => Ok(s),
+            value => Err(value.type_mismatch("string")),
         }
     }
 }
 
-impl std::ops::Not for Value {
-    type Output = Self;
+impl Value {
+    fn type_mismatch(&self, expected: &str) -> String {
+        format!("Expected a {} but got {}.", expected, self.type_name())
+    }
+}
 
-    fn not(self) -> Self::Output {
-        let b = match self {
-            Value::Boolean(b) => b,
-            Value::Nil => false,
-            _ => true,
-        };
-        Value::Boolean(!b)
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_is_equal() {
+        let string = |s: &str| Value::String(s.to_string());
+        assert!(Value::Number(1.0).is_equal(&Value::Number(1.0)));
+        assert!(Value::Number(0.0).is_equal(&Value::Number(-0.0)));
+        assert!(!Value::Number(f64::NAN).is_equal(&Value::Number(f64::NAN)));
+        assert!(Value::Number(f64::INFINITY).is_equal(&Value::Number(f64::INFINITY)));
+        assert!(!Value::Number(f64::INFINITY).is_equal(&Value::Number(f64::NEG_INFINITY)));
+        assert!(string("a").is_equal(&string("a")));
+        assert!(!string("a").is_equal(&string("b")));
+        assert!(Value::Boolean(true).is_equal(&Value::Boolean(true)));
+        assert!(Value::Nil.is_equal(&Value::Nil));
+
+        assert!(!Value::Nil.is_equal(&Value::Boolean(false)));
+        assert!(!Value::Number(1.0).is_equal(&string("1")));
+        assert!(!Value::Number(0.0).is_equal(&Value::Boolean(false)));
+        assert!(!string("").is_equal(&Value::Nil));
+
+        let list = Value::List(Rc::new(RefCell::new(vec![Value::Nil])));
+        assert!(list.is_equal(&list.clone()));
+        assert!(!list.is_equal(&Value::List(Rc::new(RefCell::new(vec![Value::Nil])))));
+    }
+
+    #[test]
+    fn test_type_name() {
+        assert_eq!(Value::Number(1.0).type_name(), "number");
+        assert_eq!(Value::Boolean(false).type_name(), "boolean");
+        assert_eq!(Value::String(String::new()).type_name(), "string");
+        assert_eq!(Value::Nil.type_name(), "nil");
+        assert_eq!(
+            Value::List(Rc::new(RefCell::new(Vec::new()))).type_name(),
+            "list"
+        );
+        assert_eq!(Value::Uninitialized.type_name(), "uninitialized");
+    }
+
+    #[test]
+    fn test_display_list() {
+        let list = |elements: Vec<Value>| Value::List(Rc::new(RefCell::new(elements)));
+        let nested = list(vec![
+            Value::Number(1.0),
+            list(vec![Value::String("two".to_string()), Value::Nil]),
+            list(vec![]),
+        ]);
+        assert_eq!(nested.to_string(), "[1, [\"two\", nil], []]");
+        assert_eq!(Value::String("two".to_string()).to_string(), "two");
+
+        //同一个列表出现两次但不构成环时正常打印
+        let inner = list(vec![Value::Boolean(true)]);
+        assert_eq!(
+            list(vec![inner.clone(), inner.clone()]).to_string(),
+            "[[true], [true]]"
+        );
+
+        if let Value::List(elements) = &inner {
+            elements.borrow_mut().push(inner.clone());
+        }
+        assert_eq!(inner.to_string(), "[true, [...]]");
+        assert_eq!(list(vec![inner.clone()]).to_string(), "[[true, [...]]]");
+    }
+
+    #[test]
+    fn test_conversions() {
+        assert_eq!(Value::from(1.5), Value::Number(1.5));
+        assert_eq!(Value::from(true), Value::Boolean(true));
+        assert_eq!(Value::from("a"), Value::String("a".to_string()));
+        assert_eq!(Value::from("b".to_string()), Value::String("b".to_string()));
+        assert_eq!(Value::from(()), Value::Nil);
+
+        assert_eq!(f64::try_from(Value::from(1.5)), Ok(1.5));
+        assert_eq!(bool::try_from(Value::from(false)), Ok(false));
+        assert_eq!(String::try_from(Value::from("a")), Ok("a".to_string()));
+
+        assert_eq!(
+            f64::try_from(Value::from("1")),
+            Err("Expected a number but got string.".to_string())
+        );
+        assert_eq!(
+            bool::try_from(Value::Nil),
+            Err("Expected a boolean but got nil.".to_string())
+        );
+        assert_eq!(
+            String::try_from(Value::from(1.0)),
+            Err("Expected a string but got number.".to_string())
+        );
+    }
+
+    #[test]
+    fn test_format_number() {
+        assert_eq!(Value::Number(100.0).to_string(), "100");
+        assert_eq!(Value::Number(2.75).to_string(), "2.75");
+        assert_eq!(Value::Number(-0.0).to_string(), "-0");
+        assert_eq!(Value::Number(10.0 / 4.0).to_string(), "2.5");
+        assert_eq!(Value::Number(1e20).to_string(), "100000000000000000000");
+        assert_eq!(Value::Number(1e21).to_string(), "1e21");
+        assert_eq!(Value::Number(-1.5e300).to_string(), "-1.5e300");
+        assert_eq!(Value::Number(f64::INFINITY).to_string(), "Infinity");
+        assert_eq!(Value::Number(f64::NEG_INFINITY).to_string(), "-Infinity");
+        assert_eq!(Value::Number(f64::NAN).to_string(), "NaN");
+        assert_eq!(Value::Number(-f64::NAN).to_string(), "NaN");
+        assert_eq!(crate::token::Literal::Number(1e21).to_string(), "1e21");
     }
 }